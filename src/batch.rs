@@ -0,0 +1,69 @@
+use std::future::Future;
+use std::sync::Mutex;
+use std::time::Duration;
+use futures::stream::{self, StreamExt};
+use tokio::time::{sleep_until, Instant};
+
+/// How batch operations treat errors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BatchMode {
+    /// Stop at the first error and return it.
+    FailFast,
+    /// Run every item and return each input paired with its result.
+    CollectAll,
+}
+
+/// Runs `task` for every input with at most `max_concurrency` in flight.
+/// Results come back in completion order.
+pub async fn run_batch<I, T, E, F, Fut>(inputs: Vec<I>, max_concurrency: usize, mode: BatchMode, task: F) -> Result<Vec<(I, Result<T, E>)>, E>
+where
+    I: Clone,
+    F: Fn(I) -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+{
+    let mut pending = stream::iter(inputs)
+        .map(|input| {
+            let fut = task(input.clone());
+            async move { (input, fut.await) }
+        })
+        .buffer_unordered(max_concurrency.max(1));
+
+    let mut results = Vec::new();
+    while let Some((input, result)) = pending.next().await {
+        match result {
+            Err(e) if mode == BatchMode::FailFast => return Err(e),
+            result => results.push((input, result)),
+        }
+    }
+
+    Ok(results)
+}
+
+/// Pause shared by the tasks of one batch, so a rate limit hit by any of them holds back
+/// all of them instead of each finding out on its own.
+#[derive(Debug, Default)]
+pub struct Throttle {
+    until: Mutex<Option<Instant>>,
+}
+
+impl Throttle {
+    /// Holds back every task for `wait` from now, unless they are already held longer.
+    pub fn hold(&self, wait: Duration) {
+        let until = Instant::now() + wait;
+        let mut current = self.until.lock().unwrap();
+        if current.is_none_or(|current| current < until) {
+            *current = Some(until);
+        }
+    }
+
+    /// Waits out the hold, including any placed while waiting.
+    pub async fn ready(&self) {
+        loop {
+            let until = *self.until.lock().unwrap();
+            match until {
+                Some(until) if until > Instant::now() => sleep_until(until).await,
+                _ => return,
+            }
+        }
+    }
+}
@@ -0,0 +1,102 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+/// In-memory cache whose entries expire `ttl` after they were inserted.
+/// Clones share the same entries. A zero `ttl` disables caching.
+#[derive(Debug, Clone)]
+pub struct TtlCache<V> {
+    ttl: Duration,
+    entries: Arc<Mutex<HashMap<String, (Instant, V)>>>,
+}
+
+impl<V: Clone> TtlCache<V> {
+    pub fn new(ttl: Duration) -> Self {
+        TtlCache {
+            ttl,
+            entries: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<V> {
+        let mut entries = self.entries.lock().unwrap();
+        match entries.get(key) {
+            Some((expires, value)) if Instant::now() < *expires => Some(value.clone()),
+            Some(_) => {
+                entries.remove(key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    pub fn insert(&self, key: String, value: V) {
+        self.insert_expiring(key, value, self.ttl);
+    }
+
+    /// Inserts `value` to expire after `remaining`, at most the cache's `ttl`.
+    pub fn insert_expiring(&self, key: String, value: V, remaining: Duration) {
+        let remaining = remaining.min(self.ttl);
+        if !remaining.is_zero() {
+            self.entries.lock().unwrap().insert(key, (Instant::now() + remaining, value));
+        }
+    }
+
+    /// Unexpired entries with how long each has left.
+    pub fn live_entries(&self) -> Vec<(String, V, Duration)> {
+        let now = Instant::now();
+        self.entries.lock().unwrap().iter()
+            .filter(|(_, (expires, _))| now < *expires)
+            .map(|(key, (expires, value))| (key.clone(), value.clone(), *expires - now))
+            .collect()
+    }
+
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct SavedEntry<V> {
+    expires_at: DateTime<Utc>,
+    value: V,
+}
+
+impl<V: Clone + Serialize + DeserializeOwned> TtlCache<V> {
+    /// Writes the unexpired entries to `path` as JSON, each with the time it expires.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let now = Utc::now();
+        let saved: HashMap<String, SavedEntry<V>> = self.live_entries().into_iter()
+            .filter_map(|(key, value, remaining)| {
+                let expires_at = now + chrono::Duration::from_std(remaining).ok()?;
+                Some((key, SavedEntry { expires_at, value }))
+            })
+            .collect();
+
+        let content = serde_json::to_string_pretty(&saved).context("Failed to serialize cache")?;
+        fs::write(path, content).context("Failed to write cache file")
+    }
+
+    /// Adds the entries saved to `path` that haven't expired yet. A missing file adds nothing.
+    pub fn load(&self, path: &Path) -> Result<()> {
+        if !path.exists() {
+            return Ok(());
+        }
+
+        let content = fs::read_to_string(path).context("Failed to read cache file")?;
+        let saved: HashMap<String, SavedEntry<V>> = serde_json::from_str(&content).context("Failed to deserialize cache")?;
+        let now = Utc::now();
+        for (key, entry) in saved {
+            if let Ok(remaining) = (entry.expires_at - now).to_std() {
+                self.insert_expiring(key, entry.value, remaining);
+            }
+        }
+        Ok(())
+    }
+}
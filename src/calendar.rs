@@ -0,0 +1,71 @@
+use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Reservation inventory for a single day of a venue calendar.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CalendarDay {
+    pub date: String,
+    pub status: CalendarStatus,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CalendarStatus {
+    Available,
+    SoldOut,
+    Closed,
+    Unknown,
+}
+
+impl CalendarStatus {
+    fn from_inventory(inventory: &str) -> Self {
+        match inventory {
+            "available" => CalendarStatus::Available,
+            "sold-out" => CalendarStatus::SoldOut,
+            "closed" => CalendarStatus::Closed,
+            _ => CalendarStatus::Unknown,
+        }
+    }
+}
+
+/// Availability transition of a day between two calendar polls.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CalendarChange {
+    Opened(String),
+    Closed(String),
+}
+
+pub fn format_calendar(json: Value) -> Vec<CalendarDay> {
+    if let Some(days) = json["scheduled"].as_array() {
+        days.iter().filter_map(|day| {
+            Some(CalendarDay {
+                date: day["date"].as_str()?.to_string(),
+                status: CalendarStatus::from_inventory(day["inventory"]["reservation"].as_str()?),
+            })
+        }).collect()
+    } else {
+        Vec::new()
+    }
+}
+
+/// Reports the days that became available or stopped being available between `old` and `new`.
+/// Days missing from a calendar count as not available.
+pub fn diff_calendars(old: &[CalendarDay], new: &[CalendarDay]) -> Vec<CalendarChange> {
+    let was_available: HashMap<&str, bool> = old.iter()
+        .map(|day| (day.date.as_str(), day.status == CalendarStatus::Available))
+        .collect();
+    let is_available: HashMap<&str, bool> = new.iter()
+        .map(|day| (day.date.as_str(), day.status == CalendarStatus::Available))
+        .collect();
+
+    let mut changes: Vec<CalendarChange> = new.iter()
+        .filter(|day| is_available[day.date.as_str()] && !was_available.get(day.date.as_str()).copied().unwrap_or(false))
+        .map(|day| CalendarChange::Opened(day.date.clone()))
+        .collect();
+
+    changes.extend(old.iter()
+        .filter(|day| was_available[day.date.as_str()] && !is_available.get(day.date.as_str()).copied().unwrap_or(false))
+        .map(|day| CalendarChange::Closed(day.date.clone())));
+
+    changes
+}
@@ -1,10 +1,16 @@
+use std::collections::HashMap;
 use std::fs;
 use std::fs::OpenOptions;
 use std::path::{Path, PathBuf};
-use anyhow::{Context, Result};
+use anyhow::{anyhow, bail, Context, Result};
 use serde::{Serialize, Deserialize};
-use toml;
-use chrono::{Utc, Duration, Local};
+use chrono::{DateTime, Duration, Local, LocalResult, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use crate::batch::BatchMode;
+use crate::notify::NotifyOn;
+use crate::retry::RetryPolicy;
+use crate::schedule::Recurrence;
+use crate::resy_api_gateway::{DEFAULT_MAX_RESPONSE_BYTES, RESY_API_BASE_URL};
 
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -21,6 +27,13 @@ pub struct Config {
     #[serde(default)]
     pub venue_slug: String,
 
+    #[serde(default = "_default_venue_location")]
+    pub venue_location: String,
+
+    pub venue_coordinates: Option<Coordinates>,
+
+    pub find_coordinates: Option<Coordinates>,
+
     #[serde(default = "_default_date")]
     pub date: String,
 
@@ -29,6 +42,36 @@ pub struct Config {
 
     pub target_time: Option<String>,
 
+    pub reservation_type: Option<String>,
+
+    #[serde(default)]
+    pub prefer_fewer_fees: bool,
+
+    #[serde(default)]
+    pub allowed_types: Vec<String>,
+
+    #[serde(default)]
+    pub blocked_types: Vec<String>,
+
+    #[serde(default)]
+    pub criteria: Vec<SlotCriterion>,
+
+    #[serde(default)]
+    pub min_lead_mins: u64,
+
+    pub preferred_add_on: Option<String>,
+
+    #[serde(default)]
+    pub revalidate_before_book: bool,
+
+    #[serde(default)]
+    pub check_service_hours: bool,
+
+    pub max_total: Option<f64>,
+
+    #[serde(default = "_default_allow_deposits")]
+    pub allow_deposits: bool,
+
     #[serde(default = "_default_snipe_time")]
     pub snipe_time: String,
 
@@ -36,7 +79,218 @@ pub struct Config {
     pub snipe_date: String,
 
     #[serde(default)]
-    pub payment_id: String
+    pub payment_id: String,
+
+    pub guest_id: Option<String>,
+
+    #[serde(default = "_default_burst_window_secs")]
+    pub burst_window_secs: u64,
+
+    #[serde(default = "_default_burst_interval_ms")]
+    pub burst_interval_ms: u64,
+
+    #[serde(default = "_default_poll_interval_ms")]
+    pub poll_interval_ms: u64,
+
+    #[serde(default = "_default_poll_timeout_secs")]
+    pub poll_timeout_secs: u64,
+
+    #[serde(default = "_default_keepalive_interval_secs")]
+    pub keepalive_interval_secs: u64,
+
+    #[serde(default)]
+    pub on_found: FoundAction,
+
+    #[serde(default = "_default_venue_cache_ttl_secs")]
+    pub venue_cache_ttl_secs: u64,
+
+    pub venue_cache_path: Option<String>,
+
+    #[serde(default = "_default_shutdown_timeout_secs")]
+    pub shutdown_timeout_secs: u64,
+
+    #[serde(default = "_default_dedupe_finds")]
+    pub dedupe_finds: bool,
+
+    #[serde(default)]
+    pub retry: RetryPolicy,
+
+    /// Retries of each details and book call once the drop is on. Fields left out
+    /// take `retry`'s defaults, not the tight ones.
+    #[serde(default = "_default_booking_retry")]
+    pub booking_retry: RetryPolicy,
+
+    pub notify_command: Option<String>,
+
+    #[serde(default)]
+    pub notify_on: NotifyOn,
+
+    #[serde(default = "_default_api_base_url")]
+    pub api_base_url: String,
+
+    pub device_id: Option<String>,
+
+    #[serde(default)]
+    pub send_correlation_id: bool,
+
+    #[serde(default = "_default_max_response_bytes")]
+    pub max_response_bytes: usize,
+    /// Idle connections kept per host. Unset (recommended) keeps reqwest's default, no
+    /// cap. A cap saves handshakes only if it is at least the widest burst, e.g.
+    /// `find_range`'s `max_concurrency`; a burst wider than the cap reconnects.
+    pub pool_max_idle_per_host: Option<usize>,
+    #[serde(default)]
+    pub http1_only: bool,
+
+    #[serde(default)]
+    pub override_auth_headers: bool,
+
+    #[serde(default)]
+    pub extra_headers: HashMap<String, String>,
+
+    #[serde(default)]
+    pub targets: Vec<SnipeTarget>,
+}
+
+/// A reservation the daemon snipes once its release time arrives.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SnipeTarget {
+    pub venue_id: String,
+
+    pub date: String,
+
+    #[serde(default = "_default_party_size")]
+    pub party_size: u8,
+
+    pub target_time: Option<String>,
+
+    #[serde(default)]
+    pub snipe_date: String,
+
+    #[serde(default)]
+    pub snipe_time: String,
+
+    /// Explicit release time overriding snipe_date + snipe_time, for venues whose drops
+    /// don't follow the usual schedule. See `parse_release_at` for the accepted formats.
+    pub release_at: Option<String>,
+
+    /// When set, a failed snipe is retried at the next occurrence of the release
+    pub recurrence: Option<Recurrence>,
+}
+
+impl SnipeTarget {
+    /// Local time at which the reservation is released: the explicit `release_at` when
+    /// set, else snipe_date + snipe_time. `None` if the one used is invalid.
+    pub fn release_at(&self) -> Option<DateTime<Local>> {
+        if let Some(release_at) = &self.release_at {
+            return parse_release_at(release_at).ok();
+        }
+
+        let date = NaiveDate::parse_from_str(&self.snipe_date, "%Y-%m-%d").ok()?;
+        let time = NaiveTime::parse_from_str(&self.snipe_time, "%H%M").ok()?;
+        Local.from_local_datetime(&date.and_time(time)).single()
+    }
+
+    /// This target moved to its first occurrence after `now`: the release and the
+    /// reservation date shift by the same number of days, and the release is given as a
+    /// local snipe date and time. `None` without a recurrence or a valid release time.
+    pub fn next_occurrence(&self, now: DateTime<Local>) -> Option<SnipeTarget> {
+        let recurrence = self.recurrence?;
+        let release_at = self.release_at()?.naive_local();
+        let next = recurrence.next_after(release_at, now.naive_local());
+        let date = NaiveDate::parse_from_str(&self.date, "%Y-%m-%d").ok()? + (next.date() - release_at.date());
+
+        Some(SnipeTarget {
+            date: date.format("%Y-%m-%d").to_string(),
+            snipe_date: next.format("%Y-%m-%d").to_string(),
+            snipe_time: next.format("%H%M").to_string(),
+            release_at: None,
+            ..self.clone()
+        })
+    }
+
+    /// Base config with this target's reservation details applied.
+    pub fn apply(&self, config: &Config) -> Config {
+        Config {
+            venue_id: self.venue_id.clone(),
+            date: self.date.clone(),
+            party_size: self.party_size,
+            target_time: self.target_time.clone(),
+            snipe_date: self.snipe_date.clone(),
+            snipe_time: self.snipe_time.clone(),
+            targets: Vec::new(),
+            ..config.clone()
+        }
+    }
+}
+
+/// Parses an explicit release time, either RFC 3339 (`2024-05-18T09:00:00-04:00`) or a
+/// local time in a named zone (`2024-05-18 09:00 America/New_York`). A time repeated when
+/// clocks fall back resolves to its first occurrence; one skipped when they spring forward
+/// is an error.
+pub fn parse_release_at(release_at: &str) -> Result<DateTime<Local>> {
+    let release_at = release_at.trim();
+    if let Ok(instant) = DateTime::parse_from_rfc3339(release_at) {
+        return Ok(instant.with_timezone(&Local));
+    }
+
+    let (local, zone) = release_at.rsplit_once(' ')
+        .ok_or_else(|| anyhow!("Invalid release_at {}, use RFC 3339 or YYYY-MM-DD HH:MM <time zone>", release_at))?;
+    let tz: Tz = zone.parse().map_err(|_| anyhow!("Unknown time zone {} in release_at", zone))?;
+    let local = NaiveDateTime::parse_from_str(local.trim(), "%Y-%m-%d %H:%M")
+        .with_context(|| format!("Invalid release_at {}, use RFC 3339 or YYYY-MM-DD HH:MM <time zone>", release_at))?;
+
+    match tz.from_local_datetime(&local) {
+        LocalResult::Single(instant) | LocalResult::Ambiguous(instant, _) => Ok(instant.with_timezone(&Local)),
+        LocalResult::None => bail!("release_at {} does not exist in {} (skipped by a clock change)", local, tz),
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Coordinates {
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+/// One acceptable time and seating combination, e.g. "Patio at 1900" or "Bar any time".
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct SlotCriterion {
+    /// Start time (HHMM), `None` takes any time
+    pub time: Option<String>,
+    /// Seating type (case-insensitive), `None` takes any seating
+    pub seating: Option<String>,
+    /// How far (in minutes) a slot may start from `time`
+    #[serde(default)]
+    pub within_mins: u32,
+}
+
+impl SlotCriterion {
+    /// Any seating at exactly `time` (HHMM).
+    pub fn at(time: &str) -> Self {
+        SlotCriterion { time: Some(time.to_string()), ..SlotCriterion::default() }
+    }
+}
+
+impl std::fmt::Display for SlotCriterion {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.seating.as_deref().unwrap_or("any seating"))?;
+        match &self.time {
+            Some(time) if self.within_mins > 0 => write!(f, " at {} (±{} min)", time, self.within_mins),
+            Some(time) => write!(f, " at {}", time),
+            None => write!(f, " any time"),
+        }
+    }
+}
+
+/// What the sniper does once slots show up.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum FoundAction {
+    /// Book the best slot
+    #[default]
+    Book,
+    /// Report each new slot and keep watching
+    NotifyOnly,
 }
 
 fn _default_date() -> String {
@@ -52,6 +306,32 @@ const fn _default_party_size() -> u8 { 2 }
 
 fn _default_snipe_time() -> String { String::from("0000") }
 
+const fn _default_burst_window_secs() -> u64 { 3 }
+
+const fn _default_burst_interval_ms() -> u64 { 100 }
+
+const fn _default_poll_interval_ms() -> u64 { 1000 }
+
+const fn _default_poll_timeout_secs() -> u64 { 30 }
+
+const fn _default_keepalive_interval_secs() -> u64 { 600 }
+
+const fn _default_venue_cache_ttl_secs() -> u64 { 3600 }
+
+const fn _default_shutdown_timeout_secs() -> u64 { 10 }
+
+fn _default_booking_retry() -> RetryPolicy { RetryPolicy::tight() }
+
+const fn _default_allow_deposits() -> bool { true }
+
+const fn _default_dedupe_finds() -> bool { true }
+
+const fn _default_max_response_bytes() -> usize { DEFAULT_MAX_RESPONSE_BYTES }
+
+fn _default_venue_location() -> String { String::from("new-york-ny") }
+
+fn _default_api_base_url() -> String { String::from(RESY_API_BASE_URL) }
+
 impl Default for Config {
     fn default() -> Self {
         let one_week_later = Utc::now().date_naive() + Duration::days(7);
@@ -62,12 +342,50 @@ impl Default for Config {
             auth_token: String::new(),
             venue_id: String::new(),
             venue_slug: String::new(),
+            venue_location: _default_venue_location(),
+            venue_coordinates: None,
+            find_coordinates: None,
             date: one_week_later.format("%Y-%m-%d").to_string(),
             party_size: 2,
             target_time: None,
+            reservation_type: None,
+            prefer_fewer_fees: false,
+            allowed_types: Vec::new(),
+            blocked_types: Vec::new(),
+            criteria: Vec::new(),
+            min_lead_mins: 0,
+            preferred_add_on: None,
+            revalidate_before_book: false,
+            check_service_hours: false,
+            max_total: None,
+            allow_deposits: _default_allow_deposits(),
             payment_id: String::new(),
+            guest_id: None,
             snipe_time: String::from("0000"),
-            snipe_date: tmrw
+            snipe_date: tmrw,
+            burst_window_secs: _default_burst_window_secs(),
+            burst_interval_ms: _default_burst_interval_ms(),
+            poll_interval_ms: _default_poll_interval_ms(),
+            poll_timeout_secs: _default_poll_timeout_secs(),
+            keepalive_interval_secs: _default_keepalive_interval_secs(),
+            on_found: FoundAction::default(),
+            venue_cache_ttl_secs: _default_venue_cache_ttl_secs(),
+            venue_cache_path: None,
+            shutdown_timeout_secs: _default_shutdown_timeout_secs(),
+            dedupe_finds: _default_dedupe_finds(),
+            retry: RetryPolicy::default(),
+            booking_retry: _default_booking_retry(),
+            notify_command: None,
+            notify_on: NotifyOn::default(),
+            api_base_url: String::from(RESY_API_BASE_URL),
+            device_id: None,
+            send_correlation_id: false,
+            max_response_bytes: DEFAULT_MAX_RESPONSE_BYTES,
+            pool_max_idle_per_host: None,
+            http1_only: false,
+            override_auth_headers: false,
+            extra_headers: HashMap::new(),
+            targets: Vec::new(),
         }
     }
 }
@@ -79,23 +397,84 @@ impl Clone for Config {
             auth_token: self.auth_token.clone(),
             venue_id: self.venue_id.clone(),
             venue_slug: self.venue_slug.clone(),
+            venue_location: self.venue_location.clone(),
+            venue_coordinates: self.venue_coordinates,
+            find_coordinates: self.find_coordinates,
             date: self.date.clone(),
             party_size: self.party_size,
             target_time: self.target_time.clone(),
+            reservation_type: self.reservation_type.clone(),
+            prefer_fewer_fees: self.prefer_fewer_fees,
+            allowed_types: self.allowed_types.clone(),
+            blocked_types: self.blocked_types.clone(),
+            criteria: self.criteria.clone(),
+            min_lead_mins: self.min_lead_mins,
+            preferred_add_on: self.preferred_add_on.clone(),
+            revalidate_before_book: self.revalidate_before_book,
+            check_service_hours: self.check_service_hours,
+            max_total: self.max_total,
+            allow_deposits: self.allow_deposits,
             payment_id: self.payment_id.clone(),
+            guest_id: self.guest_id.clone(),
             snipe_time: self.snipe_time.clone(),
             snipe_date: self.snipe_date.clone(),
+            burst_window_secs: self.burst_window_secs,
+            burst_interval_ms: self.burst_interval_ms,
+            poll_interval_ms: self.poll_interval_ms,
+            poll_timeout_secs: self.poll_timeout_secs,
+            keepalive_interval_secs: self.keepalive_interval_secs,
+            on_found: self.on_found,
+            venue_cache_ttl_secs: self.venue_cache_ttl_secs,
+            venue_cache_path: self.venue_cache_path.clone(),
+            shutdown_timeout_secs: self.shutdown_timeout_secs,
+            dedupe_finds: self.dedupe_finds,
+            retry: self.retry,
+            booking_retry: self.booking_retry,
+            notify_command: self.notify_command.clone(),
+            notify_on: self.notify_on,
+            api_base_url: self.api_base_url.clone(),
+            device_id: self.device_id.clone(),
+            send_correlation_id: self.send_correlation_id,
+            max_response_bytes: self.max_response_bytes,
+            pool_max_idle_per_host: self.pool_max_idle_per_host,
+            http1_only: self.http1_only,
+            override_auth_headers: self.override_auth_headers,
+            extra_headers: self.extra_headers.clone(),
+            targets: self.targets.clone(),
         }
     }
 }
 
 impl Config {
-    pub(crate) fn validate(&self) -> bool {
-        !self.api_key.is_empty() &&
-        !self.auth_token.is_empty() &&
-        !self.venue_id.is_empty() &&
-        !self.date.is_empty() &&
-        self.party_size > 0
+    /// Coordinates to send to find: the explicit override, else the venue's own.
+    pub fn search_coordinates(&self) -> Coordinates {
+        self.find_coordinates
+            .or(self.venue_coordinates)
+            .unwrap_or(Coordinates { latitude: 0.0, longitude: 0.0 })
+    }
+
+    pub fn validate(&self) -> bool {
+        self.problems(BatchMode::FailFast).is_empty()
+    }
+
+    /// Lists what is missing for a reservation; `FailFast` stops at the first problem.
+    pub fn problems(&self, mode: BatchMode) -> Vec<String> {
+        let checks = [
+            (self.api_key.is_empty(), "api_key is missing"),
+            (self.auth_token.is_empty(), "auth_token is missing"),
+            (self.venue_id.is_empty(), "venue_id is missing"),
+            (self.date.is_empty(), "date is missing"),
+            (self.party_size == 0, "party_size must be at least 1"),
+        ];
+
+        let mut problems = checks.iter()
+            .filter(|(failed, _)| *failed)
+            .map(|(_, problem)| problem.to_string());
+
+        match mode {
+            BatchMode::FailFast => problems.next().into_iter().collect(),
+            BatchMode::CollectAll => problems.collect(),
+        }
     }
 }
 
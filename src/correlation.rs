@@ -0,0 +1,22 @@
+use std::future::Future;
+use rand::Rng;
+
+tokio::task_local! {
+    static CORRELATION_ID: String;
+}
+
+/// Short random id for one snipe attempt, e.g. `3f9a01c2`.
+pub fn new_id() -> String {
+    format!("{:08x}", rand::thread_rng().gen::<u32>())
+}
+
+/// Runs `future` with `id` as the correlation id, which is picked up by the log format
+/// and, with `send_correlation_id`, sent as `x-correlation-id` on every request.
+pub async fn scope<F: Future>(id: String, future: F) -> F::Output {
+    CORRELATION_ID.scope(id, future).await
+}
+
+/// Correlation id of the attempt being run, if any.
+pub fn current() -> Option<String> {
+    CORRELATION_ID.try_with(|id| id.clone()).ok()
+}
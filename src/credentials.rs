@@ -0,0 +1,74 @@
+use std::env;
+use std::fmt::Debug;
+use std::fs;
+use std::path::PathBuf;
+use anyhow::{Context, Result};
+use futures::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+
+/// Resy api key and auth token.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Credentials {
+    pub api_key: String,
+    pub auth_token: String,
+}
+
+impl Credentials {
+    /// Without surrounding whitespace, e.g. the newline a token file or variable ends in.
+    pub fn trimmed(self) -> Self {
+        Credentials {
+            api_key: self.api_key.trim().to_string(),
+            auth_token: self.auth_token.trim().to_string(),
+        }
+    }
+}
+
+/// Source of Resy credentials, asked again whenever the current ones are rejected.
+pub trait CredentialProvider: Debug + Send + Sync {
+    fn credentials(&self) -> BoxFuture<'_, Result<Credentials>>;
+}
+
+/// Reads credentials from environment variables, `RESY_API_KEY` and `RESY_AUTH_TOKEN` by default.
+#[derive(Debug, Clone)]
+pub struct EnvCredentials {
+    pub api_key_var: String,
+    pub auth_token_var: String,
+}
+
+impl Default for EnvCredentials {
+    fn default() -> Self {
+        EnvCredentials {
+            api_key_var: String::from("RESY_API_KEY"),
+            auth_token_var: String::from("RESY_AUTH_TOKEN"),
+        }
+    }
+}
+
+impl CredentialProvider for EnvCredentials {
+    fn credentials(&self) -> BoxFuture<'_, Result<Credentials>> {
+        Box::pin(async move {
+            Ok(Credentials {
+                api_key: env::var(&self.api_key_var).with_context(|| format!("{} is not set", self.api_key_var))?,
+                auth_token: env::var(&self.auth_token_var).with_context(|| format!("{} is not set", self.auth_token_var))?,
+            }.trimmed())
+        })
+    }
+}
+
+/// Reads credentials from a TOML file with `api_key` and `auth_token` keys. The file is
+/// re-read on every call, so rotating it takes effect on the next refresh.
+#[derive(Debug, Clone)]
+pub struct FileCredentials {
+    pub path: PathBuf,
+}
+
+impl CredentialProvider for FileCredentials {
+    fn credentials(&self) -> BoxFuture<'_, Result<Credentials>> {
+        Box::pin(async move {
+            let content = fs::read_to_string(&self.path)
+                .with_context(|| format!("Failed to read credentials from {}", self.path.display()))?;
+            let credentials: Credentials = toml::from_str(&content).context("Failed to deserialize credentials")?;
+            Ok(credentials.trimmed())
+        })
+    }
+}
@@ -0,0 +1,278 @@
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::time::Duration as StdDuration;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Duration, Local};
+use futures::future::join_all;
+use log::{error, info, warn};
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::time::{sleep, timeout};
+use crate::config::{self, Config, SnipeTarget};
+use crate::correlation;
+use crate::notify::{self, SnipeEvent};
+use crate::resy_client::{BookingResult, ResyClient, ResyClientError, ResyResult, WARM_UP_SECS};
+use crate::store::{self, JsonFileStore, SnipeAttempt, Store};
+
+/// No keepalives are sent this close to a release, leaving the connection to the snipe.
+const KEEPALIVE_QUIET_PERIOD: StdDuration = StdDuration::from_secs(120);
+
+/// How long snipes still in flight at SIGTERM get to finish, notify and be recorded.
+const SHUTDOWN_GRACE_PERIOD: StdDuration = StdDuration::from_secs(30);
+
+/// Runs until SIGTERM, sniping each configured target when its release time arrives.
+/// The config is reloaded on SIGHUP.
+pub async fn run(config_path: &Path) -> Result<()> {
+    let mut hangup = signal(SignalKind::hangup()).context("Failed to listen for SIGHUP")?;
+    let mut terminate = signal(SignalKind::terminate()).context("Failed to listen for SIGTERM")?;
+    let mut client = ResyClient::from_config(config::read_config(config_path).context("Failed to load configuration")?);
+    let mut store = JsonFileStore::new(store::get_store_path()?);
+    let mut fired: HashSet<String> = HashSet::new();
+    // releases up to here were seen (and fired or skipped) by an earlier schedule
+    let mut since = Local::now();
+
+    loop {
+        let rescheduled = store.rescheduled().unwrap_or_else(|e| {
+            error!("Failed to load rescheduled targets: {:#}", e);
+            HashMap::new()
+        });
+        let now = Local::now();
+        let schedule = schedule(&client.config, &rescheduled, &fired, since, now);
+        since = now;
+        for scheduled in &schedule {
+            info!("Scheduled {} for {} at {}", scheduled.target.venue_id, scheduled.target.date, scheduled.release_at);
+        }
+
+        // woken ahead of the release, so the snipe can warm up its connection first
+        let next_release = match schedule.first() {
+            Some(scheduled) => scheduled.release_at - warm_up_lead(),
+            None => {
+                info!("No upcoming targets, waiting for SIGHUP");
+                tokio::select! {
+                    _ = hangup.recv() => {
+                        client = reload(config_path, client).await;
+                        continue;
+                    }
+                    _ = terminate.recv() => {
+                        shutdown(&client).await;
+                        return Ok(());
+                    }
+                }
+            }
+        };
+
+        let wait = (next_release - Local::now()).to_std().unwrap_or_default();
+        let keepalive_interval = StdDuration::from_secs(client.config.keepalive_interval_secs);
+        let keepalive = !keepalive_interval.is_zero() && wait > keepalive_interval + KEEPALIVE_QUIET_PERIOD;
+
+        tokio::select! {
+            _ = sleep(keepalive_interval), if keepalive => {
+                client.keepalive().await;
+            }
+            _ = sleep(wait) => {
+                let due: Vec<&Scheduled> = schedule.iter()
+                    .filter(|scheduled| scheduled.release_at - warm_up_lead() <= Local::now())
+                    .collect();
+
+                let mut pending = Vec::new();
+                for scheduled in due {
+                    let target = &scheduled.target;
+                    fired.insert(fired_key(target, scheduled.release_at));
+                    match store.is_booked(&target.venue_id, &target.date) {
+                        Ok(true) => info!("Already booked {} for {}, skipping", target.venue_id, target.date),
+                        _ => pending.push(scheduled),
+                    }
+                }
+
+                let (results, terminated) = {
+                    let round = join_all(pending.iter().map(|scheduled| snipe(&client, scheduled)));
+                    tokio::pin!(round);
+                    tokio::select! {
+                        results = &mut round => (Some(results), false),
+                        _ = terminate.recv() => {
+                            info!("Shutting down once in-flight snipes finish (at most {:?})", SHUTDOWN_GRACE_PERIOD);
+                            (timeout(SHUTDOWN_GRACE_PERIOD, round).await.ok(), true)
+                        }
+                    }
+                };
+
+                match results {
+                    Some(results) => {
+                        for (scheduled, result) in pending.into_iter().zip(results) {
+                            if scheduled.target.recurrence.is_some() {
+                                reschedule(&mut store, scheduled, result.is_ok());
+                            }
+                            record(&mut store, &scheduled.target, result);
+                        }
+                    }
+                    None => {
+                        for scheduled in pending {
+                            record_interrupted(&mut store, &scheduled.target);
+                        }
+                    }
+                }
+
+                if terminated {
+                    shutdown(&client).await;
+                    return Ok(());
+                }
+            }
+            _ = hangup.recv() => {
+                client = reload(config_path, client).await;
+            }
+            _ = terminate.recv() => {
+                shutdown(&client).await;
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// A target due at `release_at`. Recurring targets run as their latest rescheduled
+/// occurrence, `series` is the key of the configured target they repeat.
+struct Scheduled {
+    release_at: DateTime<Local>,
+    target: SnipeTarget,
+    series: String,
+}
+
+/// Not yet fired targets ordered by release time, recurring ones at the occurrence
+/// `rescheduled` holds for them, if any. Targets released between `since` (the previous
+/// schedule) and `now` were passed over by a running round and are kept, to be fired late.
+fn schedule(config: &Config, rescheduled: &HashMap<String, SnipeTarget>, fired: &HashSet<String>, since: DateTime<Local>, now: DateTime<Local>) -> Vec<Scheduled> {
+    let mut schedule: Vec<Scheduled> = config.targets.iter()
+        .map(|target| {
+            let series = target_key(target);
+            let target = match rescheduled.get(&series) {
+                // an occurrence missed while the daemon was down moves on to the next one
+                Some(next) if target.recurrence.is_some() => match next.release_at() {
+                    Some(release_at) if release_at <= since => next.next_occurrence(now).unwrap_or_else(|| next.clone()),
+                    _ => next.clone(),
+                },
+                _ => target.clone(),
+            };
+            (series, target)
+        })
+        .filter_map(|(series, target)| match target.release_at() {
+            Some(release_at) if fired.contains(&fired_key(&target, release_at)) => None,
+            Some(release_at) if release_at > now => Some(Scheduled { release_at, target, series }),
+            Some(release_at) if release_at > since => {
+                warn!("Release of {} for {} at {} passed during the last round, sniping it late", target.venue_id, target.date, release_at);
+                Some(Scheduled { release_at, target, series })
+            }
+            Some(_) => None,
+            None => {
+                match target.release_at.as_deref().map(config::parse_release_at) {
+                    Some(Err(e)) => warn!("Skipping target {}: {:#}", target.venue_id, e),
+                    _ => warn!("Skipping target {} with invalid snipe date/time", target.venue_id),
+                }
+                None
+            }
+        })
+        .collect();
+
+    schedule.sort_by_key(|scheduled| scheduled.release_at);
+    schedule
+}
+
+/// Snipes `target` under a fresh correlation id, so its log lines can be told apart from
+/// the other snipes of the round.
+async fn snipe(client: &ResyClient, scheduled: &Scheduled) -> ResyResult<BookingResult> {
+    correlation::scope(correlation::new_id(), snipe_target(client, &scheduled.target, scheduled.release_at)).await
+}
+
+/// Snipes `target` the way `run_sniper` does: warm up, wait for `release_at`, then poll
+/// until slots show up. With `on_found = notify_only`, a watch that ends without booking
+/// counts as a failed snipe.
+async fn snipe_target(client: &ResyClient, target: &SnipeTarget, release_at: DateTime<Local>) -> ResyResult<BookingResult> {
+    info!("Sniping {} for {}", target.venue_id, target.date);
+
+    let result = client.for_target(target)
+        .snipe_at(release_at, false, |slot| info!("Slot open at {}: {}", target.venue_id, slot))
+        .await
+        .and_then(|booking| booking.ok_or_else(|| ResyClientError::NotFound("watch ended without booking".to_string())));
+    match &result {
+        Ok(booking) => info!("Booked {} @ {} (token: {})", target.venue_id, booking, booking.resy_token),
+        Err(e) => error!("Snipe for {} failed: {}", target.venue_id, e),
+    }
+
+    let event = match &result {
+        Ok(booking) => SnipeEvent::Booked(booking),
+        Err(error) => SnipeEvent::Failed { venue_id: &target.venue_id, date: &target.date, error },
+    };
+    if let Err(e) = notify::notify(&client.config, event) {
+        warn!("Failed to send notification for {}: {:#}", target.venue_id, e);
+    }
+    result
+}
+
+fn record(store: &mut impl Store, target: &SnipeTarget, result: ResyResult<BookingResult>) {
+    let error = result.as_ref().err().map(|e| e.to_string());
+    let attempt = SnipeAttempt::new(&target.venue_id, &target.date, target.party_size, error);
+
+    let stored = match result {
+        Ok(booking) => store.record(&booking).and_then(|_| store.record_attempt(attempt)),
+        Err(_) => store.record_attempt(attempt),
+    };
+    if let Err(e) = stored {
+        error!("Failed to record snipe for {}: {:#}", target.venue_id, e);
+    }
+}
+
+/// Moves a recurring target to its next occurrence after a failed snipe, or ends the
+/// series once it booked. Kept in the store, so restarts pick up where it left off.
+fn reschedule(store: &mut impl Store, scheduled: &Scheduled, booked: bool) {
+    let next = if booked { None } else { scheduled.target.next_occurrence(Local::now()) };
+    match &next {
+        Some(next) => info!("Rescheduled {} for {} at {} {}", next.venue_id, next.date, next.snipe_date, next.snipe_time),
+        None if booked => info!("Booked {}, not repeating it", scheduled.target.venue_id),
+        None => warn!("Could not reschedule {}", scheduled.target.venue_id),
+    }
+    if let Err(e) = store.reschedule(&scheduled.series, next) {
+        error!("Failed to reschedule {}: {:#}", scheduled.target.venue_id, e);
+    }
+}
+
+/// Records a snipe abandoned at shutdown, so the attempt still shows up in history.
+fn record_interrupted(store: &mut impl Store, target: &SnipeTarget) {
+    warn!("Snipe for {} did not finish before shutdown", target.venue_id);
+    let attempt = SnipeAttempt::new(&target.venue_id, &target.date, target.party_size, Some("interrupted by shutdown".to_string()));
+    if let Err(e) = store.record_attempt(attempt) {
+        error!("Failed to record snipe for {}: {:#}", target.venue_id, e);
+    }
+}
+
+async fn shutdown(client: &ResyClient) {
+    if let Err(e) = client.shutdown().await {
+        error!("Failed to shut down client: {}", e);
+    }
+}
+
+/// Client for the reloaded config. The current one is shut down first, so the new one
+/// starts from its saved venue cache.
+async fn reload(config_path: &Path, current: ResyClient) -> ResyClient {
+    info!("Reloading configuration");
+    match config::read_config(config_path) {
+        Ok(config) => {
+            shutdown(&current).await;
+            ResyClient::from_config(config)
+        }
+        Err(e) => {
+            error!("Failed to reload configuration, keeping the previous one: {:#}", e);
+            current
+        }
+    }
+}
+
+fn warm_up_lead() -> Duration {
+    Duration::seconds(WARM_UP_SECS)
+}
+
+fn target_key(target: &SnipeTarget) -> String {
+    format!("{}|{}|{}|{} {}", target.venue_id, target.date, target.party_size, target.snipe_date, target.snipe_time)
+}
+
+/// Key of one firing of `target`, told apart by the release it resolved to, which
+/// `target_key` misses for targets set by `release_at` or a recurrence.
+fn fired_key(target: &SnipeTarget, release_at: DateTime<Local>) -> String {
+    format!("{}@{}", target_key(target), release_at.to_rfc3339())
+}
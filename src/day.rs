@@ -0,0 +1,73 @@
+use std::fmt;
+use std::str::FromStr;
+use chrono::{Duration, NaiveDate};
+use crate::resy_client::ResyClientError;
+
+const API_DATE_FORMAT: &str = "%Y-%m-%d";
+
+/// A reservation day, formatted as YYYY-MM-DD for the API.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Day(pub NaiveDate);
+
+impl Day {
+    pub fn to_api_string(&self) -> String {
+        self.0.format(API_DATE_FORMAT).to_string()
+    }
+}
+
+impl From<NaiveDate> for Day {
+    fn from(date: NaiveDate) -> Self {
+        Day(date)
+    }
+}
+
+impl FromStr for Day {
+    type Err = ResyClientError;
+
+    fn from_str(day: &str) -> Result<Self, Self::Err> {
+        NaiveDate::parse_from_str(day, API_DATE_FORMAT)
+            .map(Day)
+            .map_err(|_| ResyClientError::InvalidInput(format!("Invalid date {}. Please use YYYY-MM-DD.", day)))
+    }
+}
+
+impl TryFrom<&str> for Day {
+    type Error = ResyClientError;
+
+    fn try_from(day: &str) -> Result<Self, Self::Error> {
+        day.parse()
+    }
+}
+
+impl fmt::Display for Day {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.to_api_string())
+    }
+}
+
+/// Inclusive range of days, iterated in order.
+#[derive(Debug, Clone)]
+pub struct DateRange {
+    next: Day,
+    end: Day,
+}
+
+impl DateRange {
+    pub fn new(start: Day, end: Day) -> Self {
+        DateRange { next: start, end }
+    }
+}
+
+impl Iterator for DateRange {
+    type Item = Day;
+
+    fn next(&mut self) -> Option<Day> {
+        if self.next > self.end {
+            return None;
+        }
+
+        let day = self.next;
+        self.next = Day(day.0 + Duration::days(1));
+        Some(day)
+    }
+}
@@ -0,0 +1,70 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use crate::config::Config;
+use crate::day::Day;
+
+/// A venue returned by discovery, with how much it has open on the searched day.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct VenueSummary {
+    pub id: u64,
+    pub name: String,
+    pub url_slug: String,
+    pub neighborhood: Option<String>,
+    pub cuisine: Option<String>,
+    pub price_range: Option<u64>,
+    /// Open slots on the searched day
+    pub open_slots: usize,
+}
+
+impl VenueSummary {
+    pub fn is_available(&self) -> bool {
+        self.open_slots > 0
+    }
+}
+
+/// What `discover` searches for around a location.
+#[derive(Debug, Clone)]
+pub struct DiscoverFilters {
+    /// Free text (name, cuisine, ...), empty matches everything
+    pub query: Option<String>,
+    pub day: Day,
+    pub party_size: u8,
+    pub radius_meters: u32,
+    /// Stop paging once this many venues were found
+    pub max_results: usize,
+    pub only_available: bool,
+}
+
+impl DiscoverFilters {
+    /// Filters for the configured day and party size.
+    pub fn from_config(config: &Config) -> Option<Self> {
+        Some(DiscoverFilters {
+            query: None,
+            day: config.date.parse().ok()?,
+            party_size: config.party_size,
+            radius_meters: 5000,
+            max_results: 50,
+            only_available: false,
+        })
+    }
+}
+
+/// Parses one search page into summaries, plus the total number of hits Resy reported.
+pub fn format_venue_summaries(json: &Value) -> (Vec<VenueSummary>, Option<u64>) {
+    let total = json["meta"]["total"].as_u64();
+    let summaries = json["search"]["hits"].as_array()
+        .map(|hits| hits.iter().filter_map(|hit| {
+            Some(VenueSummary {
+                id: hit["id"]["resy"].as_u64()?,
+                name: hit["name"].as_str().unwrap_or_default().to_string(),
+                url_slug: hit["url_slug"].as_str().unwrap_or_default().to_string(),
+                neighborhood: hit["neighborhood"].as_str().map(String::from),
+                cuisine: hit["cuisine"][0].as_str().map(String::from),
+                price_range: hit["price_range_id"].as_u64(),
+                open_slots: hit["availability"]["slots"].as_array().map_or(0, |slots| slots.len()),
+            })
+        }).collect())
+        .unwrap_or_default();
+
+    (summaries, total)
+}
@@ -0,0 +1,23 @@
+#[cfg(not(any(feature = "rustls-tls", feature = "native-tls")))]
+compile_error!("enable a TLS backend: the `rustls-tls` (default) or `native-tls` feature");
+
+pub mod resy_client;
+pub mod config;
+pub mod resy_api_gateway;
+pub mod view_utils;
+pub mod calendar;
+pub mod batch;
+pub mod daemon;
+pub mod day;
+pub mod store;
+pub mod cache;
+pub mod single_flight;
+pub mod retry;
+pub mod notify;
+pub mod discover;
+pub mod schedule;
+pub mod credentials;
+pub mod correlation;
+pub mod money;
+pub mod snapshot;
+pub mod prelude;
\ No newline at end of file
@@ -1,37 +1,50 @@
-#[macro_use] extern crate prettytable;
 use std::io;
 use clap::{Command, Arg, ArgAction};
 use std::io::Write;
 use anyhow::{Context, Result};
-use regex::Regex;
-use resy_client::ResyClient;
-use std::sync::Arc;
+use marksman::{config, correlation, daemon, notify, store, view_utils};
+use marksman::notify::SnipeEvent;
+use marksman::config::{SlotCriterion, SnipeTarget};
+use marksman::day::Day;
+use marksman::resy_client::{explain_selection, seating_types, ResyClient};
+use serde::Serialize;
+use serde_json::json;
+use marksman::store::{JsonFileStore, SnipeAttempt, Store};
 use env_logger::{Env};
-use chrono::{Local, Duration};
-
-mod resy_client;
-mod config;
-mod resy_api_gateway;
-mod view_utils;
+use chrono::{Local, Duration, NaiveDateTime, TimeZone};
 
 #[tokio::main]
 async fn main() -> Result<()> {
 
-    // setup logging
-    let env = Env::default().default_filter_or("none");
-    env_logger::init_from_env(env);
-
-    let config_path = config::get_config_path().context("Failed to get config path")?;
-    let marks_config = config::read_config(&config_path)
-        .expect("Failed to load configuration");
-
-    let mut resy_client = ResyClient::from_config(marks_config);
-
     // define cli commands
     let cli = Command::new("marksman")
         .version("0.1.0")
         .author("Anish Agrawal")
         .about("Snipe reservations in NYC")
+        .arg(
+            Arg::new("json")
+                .help("Print results and errors as JSON")
+                .long("json")
+                .global(true)
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("verbose")
+                .help("Log more: -v info, -vv debug, -vvv trace (warnings are always logged)")
+                .short('v')
+                .long("verbose")
+                .global(true)
+                .action(ArgAction::Count),
+        )
+        .arg(
+            Arg::new("quiet")
+                .help("Log nothing, only print the result")
+                .short('q')
+                .long("quiet")
+                .global(true)
+                .conflicts_with("verbose")
+                .action(ArgAction::SetTrue),
+        )
         .subcommand(
             Command::new("hello")
                 .about("Prints greeting")
@@ -84,6 +97,46 @@ async fn main() -> Result<()> {
                         .action(ArgAction::SetTrue),
                 ),
         )
+        .subcommand(
+            Command::new("slots")
+                .about("open slots at the configured venue, and which one would be booked")
+                .arg(
+                    Arg::new("date")
+                        .help("Date to check (YYYY-MM-DD), defaults to the configured date")
+                        .value_parser(clap::builder::NonEmptyStringValueParser::new())
+                        .short('d')
+                        .long("date")
+                        .required(false),
+                )
+                .arg(
+                    Arg::new("party-size")
+                        .help("Party size to check, defaults to the configured party size")
+                        .value_parser(clap::value_parser!(u8))
+                        .short('p')
+                        .long("party-size")
+                        .required(false),
+                ),
+        )
+        .subcommand(
+            Command::new("available")
+                .about("whether the configured venue has any table open (exits 1 if not, 2 on errors)")
+                .arg(
+                    Arg::new("date")
+                        .help("Date to check (YYYY-MM-DD), defaults to the configured date")
+                        .value_parser(clap::builder::NonEmptyStringValueParser::new())
+                        .short('d')
+                        .long("date")
+                        .required(false),
+                )
+                .arg(
+                    Arg::new("party-size")
+                        .help("Party size to check, defaults to the configured party size")
+                        .value_parser(clap::value_parser!(u8))
+                        .short('p')
+                        .long("party-size")
+                        .required(false),
+                ),
+        )
         .subcommand(
             Command::new("load")
                 .about("Load auth credentials for Resy API")
@@ -118,14 +171,115 @@ async fn main() -> Result<()> {
                         .long("snipe-date")
                         .required(false),
                 )
+                .arg(
+                    Arg::new("confirm")
+                        .help("Confirm each booking before it is made")
+                        .short('c')
+                        .long("confirm")
+                        .action(ArgAction::SetTrue),
+                )
+        )
+        .subcommand(
+            Command::new("watch-for")
+                .about("book one exact time as soon as it opens, ignoring every other slot")
+                .arg(
+                    Arg::new("time")
+                        .help("Exact reservation time to book (HHMM)")
+                        .value_parser(clap::builder::NonEmptyStringValueParser::new())
+                        .short('t')
+                        .long("time")
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("seating")
+                        .help("Seating type to book, any seating if not set")
+                        .value_parser(clap::builder::NonEmptyStringValueParser::new())
+                        .short('s')
+                        .long("seating")
+                        .required(false),
+                )
+                .arg(
+                    Arg::new("until")
+                        .help("Stop watching at this local time (YYYY-MM-DD HH:MM), defaults to the reservation time")
+                        .value_parser(clap::builder::NonEmptyStringValueParser::new())
+                        .short('u')
+                        .long("until")
+                        .required(false),
+                )
+                .arg(
+                    Arg::new("interval")
+                        .help("Seconds between checks")
+                        .value_parser(clap::value_parser!(u64).range(1..))
+                        .short('i')
+                        .long("interval")
+                        .default_value("30"),
+                )
+                .arg(
+                    Arg::new("confirm")
+                        .help("Confirm the booking before it is made")
+                        .short('c')
+                        .long("confirm")
+                        .action(ArgAction::SetTrue),
+                )
+        )
+        .subcommand(
+            Command::new("cancel")
+                .about("cancel reservations by resy token")
+                .arg(
+                    Arg::new("resy-token")
+                        .help("Resy tokens of the reservations to cancel")
+                        .value_parser(clap::builder::NonEmptyStringValueParser::new())
+                        .num_args(1..)
+                        .required_unless_present("all-upcoming"),
+                )
+                .arg(
+                    Arg::new("all-upcoming")
+                        .help("Cancel every booking in the history dated today or later")
+                        .long("all-upcoming")
+                        .conflicts_with("resy-token")
+                        .action(ArgAction::SetTrue),
+                )
+        )
+        .subcommand(
+            Command::new("plan")
+                .about("preview what the sniper would do, without checking availability")
         )
         .subcommand(
             Command::new("setup")
                 .about("configure setup wizard")
+        )
+        .subcommand(
+            Command::new("doctor")
+                .about("check credentials, payment method and API reachability")
+        )
+        .subcommand(
+            Command::new("history")
+                .about("past bookings and snipe attempts")
+        )
+        .subcommand(
+            Command::new("daemon")
+                .about("snipe every configured target as its release time arrives (reloads on SIGHUP, stops on SIGTERM)")
         );
 
     // parse cli
     let matches = cli.get_matches();
+    let json = matches.get_flag("json");
+
+    // setup logging, RUST_LOG overrides the flags. Lines logged during a snipe attempt
+    // are tagged with its correlation id
+    let env = Env::default().default_filter_or(log_filter(matches.get_count("verbose"), matches.get_flag("quiet")));
+    env_logger::Builder::from_env(env)
+        .format(|buf, record| {
+            let id = correlation::current().map(|id| format!(" {}", id)).unwrap_or_default();
+            writeln!(buf, "[{} {:<5} {}{}] {}", buf.timestamp(), record.level(), record.target(), id, record.args())
+        })
+        .init();
+
+    let config_path = config::get_config_path().context("Failed to get config path")?;
+    let marks_config = config::read_config(&config_path)
+        .expect("Failed to load configuration");
+
+    let mut resy_client = ResyClient::from_config(marks_config);
 
     // handling subcommands
     match matches.subcommand() {
@@ -150,13 +304,56 @@ async fn main() -> Result<()> {
             }
 
             match resy_client.view_venue(url, date, party_size, target_time).await {
+                Ok((venue_id, slots)) if json => print_json(&json!({ "venue_id": venue_id, "slots": slots })),
                 Ok((_, slots)) => {
                     println!("venue details loaded successfully");
                     view_utils::print_table(&slots);
                 },
+                Err(e) if json => print_json_error(&e),
                 Err(e) => println!("Failed to load venue details: {}", e),
             }
         }
+        Some(("slots", sub_matches)) => {
+            let party_size = sub_matches.get_one("party-size").copied();
+            match sub_matches.get_one::<String>("date").map(|date| date.parse::<Day>()).transpose() {
+                Ok(day) => match resy_client.find(day, party_size).await {
+                    Ok(slots) if json => print_json(&slots),
+                    Ok(slots) if slots.is_empty() => println!("No open slots"),
+                    Ok(slots) => {
+                        view_utils::print_slots_table(&slots);
+                        println!("Available types: {}", seating_types(&slots).join(", "));
+                        println!("{}", explain_selection(slots, &resy_client.slot_preferences().await));
+                    }
+                    Err(e) if json => print_json_error(&e),
+                    Err(e) => println!("Failed to fetch slots: {}", e),
+                },
+                Err(e) if json => print_json_error(&e),
+                Err(e) => println!("{}", e),
+            }
+        }
+        Some(("available", sub_matches)) => {
+            let party_size = sub_matches.get_one("party-size").copied().unwrap_or(resy_client.config.party_size);
+            let day = match sub_matches.get_one::<String>("date") {
+                Some(date) => date.parse::<Day>(),
+                None => resy_client.config.date.parse::<Day>(),
+            };
+            let available = match day {
+                Ok(day) => resy_client.is_available(day, party_size).await,
+                Err(e) => Err(e),
+            };
+            match &available {
+                Ok(available) if json => print_json(&json!({ "available": available })),
+                Ok(true) => println!("Available"),
+                Ok(false) => println!("Not available"),
+                Err(e) if json => print_json_error(e),
+                Err(e) => println!("Failed to check availability: {}", e),
+            }
+            match available {
+                Ok(true) => {}
+                Ok(false) => std::process::exit(1),
+                Err(_) => std::process::exit(2),
+            }
+        }
         Some(("load", sub_matches)) => {
             if !sub_matches.get_flag("skip") {
                 let mut input_string = String::new();
@@ -171,18 +368,21 @@ async fn main() -> Result<()> {
                 io::stdin().read_line(&mut input_string).expect("Failed to read line");
                 let auth_token = input_string.trim().to_string().clone();
 
-                resy_client.config.api_key = api_key;
-                resy_client.config.auth_token = auth_token;
-
-                println!("Successfully loaded .marksman.config!");
+                match resy_client.update_auth(api_key, auth_token) {
+                    Ok(()) => println!("Successfully loaded .marksman.config!"),
+                    Err(e) => println!("Failed to load credentials: {}", e),
+                }
             }
 
             match resy_client.get_payment_id().await {
+                Ok(payment_id) if json => print_json(&json!({ "payment_id": payment_id })),
                 Ok(payment_id) => println!("Payment id found: {}", payment_id),
+                Err(e) if json => print_json_error(&e),
                 Err(e) => println!("Failed to load payment_id: {}", e),
             }
 
         }
+        Some(("state", _)) if json => print_json(&resy_client.config),
         Some(("state", _)) => {
             match serde_json::to_string_pretty(&resy_client.config) {
                 Ok(json_string) => println!("Current Configuration:\n{}", json_string),
@@ -192,6 +392,7 @@ async fn main() -> Result<()> {
         Some(("snipe", sub_matches)) => {
             let snipe_time = sub_matches.get_one("snipe-time").map(String::as_str).unwrap_or("");
             let snipe_date = sub_matches.get_one("snipe-date").map(String::as_str);
+            let confirm = sub_matches.get_flag("confirm");
 
             // Determine the date based on input
             let formatted_date = match snipe_date {
@@ -200,14 +401,230 @@ async fn main() -> Result<()> {
                 _ => snipe_date.unwrap_or_default().to_string(),
             };
 
-            match resy_client.run_sniper(snipe_time, &formatted_date).await {
-                Ok(tok) => println!("Successful booking! (token: {:#?})", tok),
+            let mut store = JsonFileStore::new(store::get_store_path()?);
+            let result = correlation::scope(correlation::new_id(), resy_client.run_sniper(snipe_time, &formatted_date, confirm)).await;
+            let error = result.as_ref().err().map(|e| e.to_string());
+
+            let watched_only = matches!(result, Ok(None));
+
+            let config = &resy_client.config;
+            let event = match &result {
+                Ok(Some(booking)) => Some(SnipeEvent::Booked(booking)),
+                Ok(None) => None,
+                Err(error) => Some(SnipeEvent::Failed { venue_id: &config.venue_id, date: &config.date, error }),
+            };
+            if let Some(event) = event {
+                if let Err(e) = notify::notify(config, event) {
+                    eprintln!("Failed to send notification: {:#}", e);
+                }
+            }
+
+            match result {
+                Ok(Some(booking)) => {
+                    if json {
+                        print_json(&booking);
+                    } else {
+                        println!("Successful booking! {} (token: {:#?})", booking, booking.resy_token);
+                    }
+                    store.record(&booking).context("Failed to record booking")?;
+                }
+                Ok(None) if json => print_json(&json!({ "booking": null })),
+                Ok(None) => println!("Stopped watching, nothing was booked"),
+                Err(e) if json => print_json_error(&e),
                 Err(e) => println!("Snipe failed with {}", e)
             }
+
+            // a notify-only watch never tries to book, so it isn't an attempt
+            if !watched_only {
+                let config = &resy_client.config;
+                store.record_attempt(SnipeAttempt::new(&config.venue_id, &config.date, config.party_size, error))
+                    .context("Failed to record snipe attempt")?;
+            }
+        }
+        Some(("watch-for", sub_matches)) => {
+            let time = sub_matches.get_one::<String>("time").expect("time is required");
+            let wanted = SlotCriterion {
+                seating: sub_matches.get_one::<String>("seating").cloned(),
+                ..SlotCriterion::at(time)
+            };
+            let until = match sub_matches.get_one::<String>("until") {
+                Some(until) => until.clone(),
+                None => format!("{} {}", resy_client.config.date, time),
+            };
+            let until = NaiveDateTime::parse_from_str(&until, "%Y-%m-%d %H:%M")
+                .or_else(|_| NaiveDateTime::parse_from_str(&until, "%Y-%m-%d %H%M"))
+                .ok()
+                .and_then(|until| Local.from_local_datetime(&until).earliest())
+                .with_context(|| format!("Invalid --until {}, expected YYYY-MM-DD HH:MM", until))?;
+            let interval = std::time::Duration::from_secs(*sub_matches.get_one::<u64>("interval").expect("interval has a default"));
+            let confirm = sub_matches.get_flag("confirm");
+
+            let mut store = JsonFileStore::new(store::get_store_path()?);
+            let result = correlation::scope(correlation::new_id(), resy_client.book_when_open(&wanted, until, interval, confirm)).await;
+            let error = result.as_ref().err().map(|e| e.to_string());
+
+            let config = &resy_client.config;
+            let event = match &result {
+                Ok(booking) => SnipeEvent::Booked(booking),
+                Err(error) => SnipeEvent::Failed { venue_id: &config.venue_id, date: &config.date, error },
+            };
+            if let Err(e) = notify::notify(config, event) {
+                eprintln!("Failed to send notification: {:#}", e);
+            }
+
+            match result {
+                Ok(booking) => {
+                    if json {
+                        print_json(&booking);
+                    } else {
+                        println!("Successful booking! {} (token: {:#?})", booking, booking.resy_token);
+                    }
+                    store.record(&booking).context("Failed to record booking")?;
+                }
+                Err(e) if json => print_json_error(&e),
+                Err(e) => println!("Watch failed with {}", e),
+            }
+
+            store.record_attempt(SnipeAttempt::new(&config.venue_id, &config.date, config.party_size, error))
+                .context("Failed to record snipe attempt")?;
+        }
+        Some(("cancel", sub_matches)) => {
+            let mut store = JsonFileStore::new(store::get_store_path()?);
+            let tokens: Vec<String> = if sub_matches.get_flag("all-upcoming") {
+                let today = Local::now().format("%Y-%m-%d").to_string();
+                store.active_bookings()?.into_iter()
+                    .filter(|booking| booking.date >= today)
+                    .map(|booking| booking.resy_token)
+                    .collect()
+            } else {
+                sub_matches.get_many::<String>("resy-token").unwrap_or_default().cloned().collect()
+            };
+            let tokens: Vec<&str> = tokens.iter().map(String::as_str).collect();
+
+            let results = resy_client.cancel_all(&tokens).await;
+            for (token, _) in results.iter().filter(|(_, result)| result.is_ok()) {
+                if let Err(e) = store.record_cancellation(token) {
+                    eprintln!("Failed to record cancellation of {}: {:#}", token, e);
+                }
+            }
+            if json {
+                let results: Vec<_> = results.iter().map(|(token, result)| {
+                    json!({ "resy_token": token, "cancelled": result.is_ok(), "error": result.as_ref().err() })
+                }).collect();
+                print_json(&results);
+            } else if results.is_empty() {
+                println!("No reservations to cancel");
+            } else {
+                for (token, result) in &results {
+                    match result {
+                        Ok(()) => println!("Cancelled {}", token),
+                        Err(e) => println!("Failed to cancel {}: {}", token, e),
+                    }
+                }
+            }
+        }
+        Some(("plan", _)) => {
+            let config = &resy_client.config;
+            let configured = SnipeTarget {
+                venue_id: config.venue_id.clone(),
+                date: config.date.clone(),
+                party_size: config.party_size,
+                target_time: config.target_time.clone(),
+                snipe_date: config.snipe_date.clone(),
+                snipe_time: config.snipe_time.clone(),
+                release_at: None,
+                recurrence: None,
+            };
+            let targets: Vec<SnipeTarget> = std::iter::once(configured).chain(config.targets.iter().cloned()).collect();
+
+            let mut plans = Vec::new();
+            for target in &targets {
+                match resy_client.plan(target).await {
+                    Ok(plan) if json => plans.push(json!(plan)),
+                    Ok(plan) => println!("{}\n", plan),
+                    Err(e) if json => plans.push(json!({ "venue_id": target.venue_id, "error": e })),
+                    Err(e) => println!("Failed to plan {} for {}: {}\n", target.venue_id, target.date, e),
+                }
+            }
+            if json {
+                print_json(&plans);
+            }
+        }
+        Some(("doctor", _)) if json => {
+            let report = resy_client.healthcheck().await;
+            print_json(&json!({ "healthy": report.is_healthy(), "checks": report.checks, "latency_ms": report.latency.map(|latency| latency.as_millis()) }));
+        }
+        Some(("doctor", _)) => {
+            let report = resy_client.healthcheck().await;
+            for check in &report.checks {
+                let status = if check.passed { "ok" } else { "FAIL" };
+                println!("[{}] {}: {}", status, check.name, check.detail);
+            }
+            if let Some(latency) = report.latency {
+                println!("API latency: {} ms", latency.as_millis());
+            }
+            println!("{}", if report.is_healthy() { "All checks passed" } else { "Some checks failed" });
+        }
+        Some(("history", _)) if json => {
+            let store = JsonFileStore::new(store::get_store_path()?);
+            print_json(&json!({ "bookings": store.history()?, "cancelled": store.cancelled()?, "attempts": store.attempts()?, "venues": store.venue_stats()? }));
+        }
+        Some(("history", _)) => {
+            let store = JsonFileStore::new(store::get_store_path()?);
+            println!("Bookings:");
+            let cancelled = store.cancelled()?;
+            for booking in store.history()? {
+                let status = if cancelled.contains(&booking.resy_token) { ", cancelled" } else { "" };
+                println!("  {} venue {} (token: {}{})", booking, booking.venue_id, booking.resy_token, status);
+            }
+
+            println!("Attempts:");
+            for attempt in store.attempts()? {
+                let outcome = attempt.error.unwrap_or_else(|| "booked".to_string());
+                println!("  {} venue {} for {}: {}", attempt.attempted_at, attempt.venue_id, attempt.date, outcome);
+            }
+
+            println!("Success rate by venue:");
+            let mut venues: Vec<_> = store.venue_stats()?.into_iter().collect();
+            venues.sort_by(|(a_id, a), (b_id, b)| b.success_rate().unwrap_or_default().total_cmp(&a.success_rate().unwrap_or_default()).then(a_id.cmp(b_id)));
+            for (venue_id, stats) in venues {
+                let rate = stats.success_rate().unwrap_or_default() * 100.0;
+                println!("  venue {}: {:.0}% ({} of {} attempts)", venue_id, rate, stats.successes, stats.attempts);
+            }
+        }
+        Some(("daemon", _)) => {
+            // the daemon reloads the config file itself, so don't overwrite it with the one loaded at startup
+            return daemon::run(&config_path).await;
         }
         _ => {} // handle new commands
     }
 
+    if let Err(e) = resy_client.shutdown().await {
+        eprintln!("Failed to shut down client: {}", e);
+    }
     config::write_config(&resy_client.config, Some(&config_path)).context("Failed to write config")?;
     Ok(())
 }
+
+/// Log level for `-v` given `verbosity` times, or none at all with `--quiet`.
+fn log_filter(verbosity: u8, quiet: bool) -> &'static str {
+    match (quiet, verbosity) {
+        (true, _) => "off",
+        (false, 0) => "warn",
+        (false, 1) => "info",
+        (false, 2) => "debug",
+        (false, _) => "trace",
+    }
+}
+
+fn print_json<T: Serialize + ?Sized>(value: &T) {
+    match serde_json::to_string_pretty(value) {
+        Ok(json_string) => println!("{}", json_string),
+        Err(e) => print_json_error(&json!({ "kind": "Serialize", "message": e.to_string() })),
+    }
+}
+
+/// Errors are wrapped as `{"error": {"kind": ..., "message": ...}}`.
+fn print_json_error<E: Serialize + ?Sized>(error: &E) {
+    println!("{}", json!({ "error": error }));
+}
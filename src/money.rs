@@ -0,0 +1,79 @@
+use std::fmt;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+const DEFAULT_CURRENCY: &str = "USD";
+
+/// An amount in a venue's currency (ISO 4217 code, e.g. `USD`).
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(from = "MoneyRepr")]
+pub struct Money {
+    pub amount: f64,
+    pub currency: String,
+}
+
+impl Money {
+    pub fn new(amount: f64, currency: &str) -> Self {
+        Money { amount, currency: currency.to_ascii_uppercase() }
+    }
+
+    pub fn usd(amount: f64) -> Self {
+        Money::new(amount, DEFAULT_CURRENCY)
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.amount == 0.0
+    }
+
+    /// Symbol for the currency, `None` when it has no unambiguous one.
+    pub fn symbol(&self) -> Option<&'static str> {
+        match self.currency.as_str() {
+            "USD" => Some("$"),
+            "GBP" => Some("£"),
+            "EUR" => Some("€"),
+            "JPY" => Some("¥"),
+            "CAD" => Some("CA$"),
+            "AUD" => Some("A$"),
+            _ => None,
+        }
+    }
+}
+
+impl Default for Money {
+    fn default() -> Self {
+        Money::usd(0.0)
+    }
+}
+
+impl fmt::Display for Money {
+    /// e.g. `$25.00`, `£25.00`, or `CHF 25.00` for currencies without a symbol.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.symbol() {
+            Some(symbol) => write!(f, "{}{:.2}", symbol, self.amount),
+            None => write!(f, "{} {:.2}", self.currency, self.amount),
+        }
+    }
+}
+
+/// Bookings recorded before currencies were tracked stored a bare USD amount.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum MoneyRepr {
+    Amount(f64),
+    Money { amount: f64, currency: String },
+}
+
+impl From<MoneyRepr> for Money {
+    fn from(repr: MoneyRepr) -> Self {
+        match repr {
+            MoneyRepr::Amount(amount) => Money::usd(amount),
+            MoneyRepr::Money { amount, currency } => Money::new(amount, &currency),
+        }
+    }
+}
+
+/// Currency code of a Resy object carrying `currency: {code}` (venues and
+/// payment blocks), USD when absent.
+pub fn currency_of(json: &Value) -> &str {
+    json["currency"]["code"].as_str().unwrap_or(DEFAULT_CURRENCY)
+}
@@ -0,0 +1,83 @@
+use std::process::Command;
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use crate::config::Config;
+use crate::resy_client::{BookingResult, ResyClientError};
+
+/// Outcome of a snipe that is worth telling someone about.
+#[derive(Debug)]
+pub enum SnipeEvent<'a> {
+    Booked(&'a BookingResult),
+    /// The snipe ended without a booking
+    Failed {
+        venue_id: &'a str,
+        date: &'a str,
+        error: &'a ResyClientError,
+    },
+}
+
+/// Which snipe outcomes trigger a notification.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum NotifyOn {
+    SuccessOnly,
+    #[default]
+    Both,
+}
+
+impl NotifyOn {
+    pub fn allows(&self, event: &SnipeEvent) -> bool {
+        matches!((self, event), (NotifyOn::Both, _) | (NotifyOn::SuccessOnly, SnipeEvent::Booked(_)))
+    }
+}
+
+pub trait Notifier {
+    fn notify(&self, event: &SnipeEvent) -> Result<()>;
+}
+
+/// Runs a shell command per event, describing it through `MARKSMAN_*` environment variables:
+/// `MARKSMAN_EVENT` (`booked` or `failed`), `MARKSMAN_VENUE_ID`, `MARKSMAN_DATE`,
+/// `MARKSMAN_MESSAGE` and, for failures, `MARKSMAN_ERROR_KIND`.
+#[derive(Debug, Clone)]
+pub struct CommandNotifier {
+    pub command: String,
+}
+
+impl Notifier for CommandNotifier {
+    fn notify(&self, event: &SnipeEvent) -> Result<()> {
+        let mut command = Command::new("sh");
+        command.arg("-c").arg(&self.command);
+
+        match event {
+            SnipeEvent::Booked(booking) => {
+                command.env("MARKSMAN_EVENT", "booked")
+                    .env("MARKSMAN_VENUE_ID", &booking.venue_id)
+                    .env("MARKSMAN_DATE", &booking.date)
+                    .env("MARKSMAN_MESSAGE", booking.to_string());
+            }
+            SnipeEvent::Failed { venue_id, date, error } => {
+                command.env("MARKSMAN_EVENT", "failed")
+                    .env("MARKSMAN_VENUE_ID", venue_id)
+                    .env("MARKSMAN_DATE", date)
+                    .env("MARKSMAN_ERROR_KIND", error.kind())
+                    .env("MARKSMAN_MESSAGE", error.to_string());
+            }
+        }
+
+        let status = command.status().context("Failed to run notify command")?;
+        if !status.success() {
+            bail!("Notify command exited with {}", status);
+        }
+        Ok(())
+    }
+}
+
+/// Sends `event` through the configured `notify_command`, if any and if `notify_on` allows it.
+pub fn notify(config: &Config, event: SnipeEvent) -> Result<()> {
+    match &config.notify_command {
+        Some(command) if config.notify_on.allows(&event) => {
+            CommandNotifier { command: command.clone() }.notify(&event)
+        }
+        _ => Ok(()),
+    }
+}
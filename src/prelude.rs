@@ -0,0 +1,27 @@
+//! The types needed to drive a client, importable in one line.
+//!
+//! ```
+//! use marksman::prelude::*;
+//!
+//! let config = Config {
+//!     venue_id: "1505".to_string(),
+//!     party_size: 4,
+//!     ..Config::default()
+//! };
+//! let client = ResyClient::from_config(config);
+//!
+//! let preferences = SlotPreferences::from_config(&client.config);
+//! assert_eq!(select_slot(Vec::new(), &preferences).map(|slot| slot.id), None);
+//! ```
+
+pub use crate::config::{Config, FoundAction, SlotCriterion, SnipeTarget};
+pub use crate::credentials::{CredentialProvider, Credentials, EnvCredentials, FileCredentials};
+pub use crate::day::Day;
+pub use crate::money::Money;
+pub use crate::resy_api_gateway::{ResyAPIError, ResyAPIGateway};
+pub use crate::resy_client::{
+    explain_selection, rank_slots, select_slot, BookingResult, CancellationPolicy, ReservationDetails,
+    ResyClient, ResyClientError, ResyResult, ResySlot, ResyVenue, SlotPreferences, SlotSelection, SlotSelector,
+};
+pub use crate::retry::RetryPolicy;
+pub use crate::store::{JsonFileStore, Store};
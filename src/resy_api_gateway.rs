@@ -1,74 +1,376 @@
-use std::error::Error;
 use reqwest::{Client, Response};
-use reqwest::header::{ACCEPT, ACCEPT_LANGUAGE, AUTHORIZATION, CONTENT_TYPE, HeaderMap, HeaderValue};
+use log::warn;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use reqwest::header::{ACCEPT, ACCEPT_LANGUAGE, AUTHORIZATION, CONTENT_TYPE, RETRY_AFTER, HeaderMap, HeaderName, HeaderValue};
+use serde::{Deserialize, Serialize, Serializer};
 use serde_json::{json, Value};
+use thiserror::Error;
+use crate::config::Coordinates;
+use crate::correlation;
+use crate::credentials::Credentials;
 
-const RESY_API_BASE_URL: &str = "https://api.resy.com";
+pub const RESY_API_BASE_URL: &str = "https://api.resy.com";
+
+/// Headers carrying credentials, protected from being overwritten by extra headers.
+const AUTH_HEADERS: [&str; 3] = ["authorization", "x-resy-auth-token", "x-resy-universal-auth"];
+
+/// Default cap on response bodies. Resy's largest responses are well under a megabyte.
+pub const DEFAULT_MAX_RESPONSE_BYTES: usize = 8 * 1024 * 1024;
+
+/// Idle connections kept per host: reqwest's default, no cap, so every connection a
+/// burst opened is still there for the next one.
+pub const DEFAULT_POOL_MAX_IDLE_PER_HOST: usize = usize::MAX;
+
+/// Characters of an unparseable body kept on `Deserialize` errors.
+const ERROR_SNIPPET_CHARS: usize = 200;
+
+/// Venues requested per venue search page.
+const SEARCH_PAGE_SIZE: u32 = 20;
 
 /// Error type for Resy API specific errors.
-#[derive(Debug)]
-pub struct ResyAPIError {
+#[derive(Debug, Error)]
+pub enum ResyAPIError {
+    /// The request never got a response (connect, timeout, ...)
+    #[error("network error: {0}")]
+    Network(#[from] reqwest::Error),
+
+    /// The response body was not the expected JSON
+    #[error("failed to deserialize response: {source} (body: {snippet})")]
+    Deserialize {
+        #[source]
+        source: serde_json::Error,
+        /// Start of the body with credentials redacted, for debugging
+        snippet: String,
+    },
+
+    /// Resy answered with a non-success status
+    #[error("API request failed: {status}{}", body.as_ref().map(|body| format!(" ({})", body.message)).unwrap_or_default())]
+    Status {
+        status: u16,
+        /// Wait requested through the `Retry-After` header
+        retry_after: Option<Duration>,
+        /// Resy's explanation, when the body had one
+        body: Option<ResyErrorBody>,
+    },
+
+    /// The response body was bigger than the configured limit
+    #[error("response body exceeds {limit} bytes")]
+    TooLarge { limit: usize },
+
+    /// The api key or auth token can't be sent as a header (e.g. it has a control character)
+    #[error("invalid credentials: {0}")]
+    InvalidCredentials(String),
+
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+/// Error body Resy sends with failed requests, e.g.
+/// `{"status": 412, "code": "slot_unavailable", "message": "...", "specs": {...}}`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ResyErrorBody {
+    /// Machine readable code, numeric codes are kept as strings
+    pub code: Option<String>,
     pub message: String,
+    /// Existing reservation the request conflicts with (`specs.resy_token` or
+    /// `specs.reservation_id`), when Resy names one
+    pub reservation: Option<String>,
 }
 
-impl std::fmt::Display for ResyAPIError {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "{}", self.message)
+impl ResyErrorBody {
+    /// `None` unless the body is JSON with a non-empty `message`.
+    pub fn parse(body: &[u8]) -> Option<Self> {
+        let json: Value = serde_json::from_slice(body).ok()?;
+        let message = json["message"].as_str().map(str::trim).filter(|message| !message.is_empty())?;
+        let specs = &json["specs"];
+        let reservation = string_or_number(&specs["resy_token"]).or_else(|| string_or_number(&specs["reservation_id"]));
+        Some(ResyErrorBody { code: string_or_number(&json["code"]), message: message.to_string(), reservation })
     }
 }
 
-impl Error for ResyAPIError {}
+fn string_or_number(value: &Value) -> Option<String> {
+    match value {
+        Value::String(value) => Some(value.clone()),
+        Value::Number(value) => Some(value.to_string()),
+        _ => None,
+    }
+}
+
+impl ResyAPIError {
+    /// HTTP status of the failed response, if one was received
+    pub fn status(&self) -> Option<u16> {
+        match self {
+            ResyAPIError::Status { status, .. } => Some(*status),
+            ResyAPIError::Network(e) => e.status().map(|status| status.as_u16()),
+            _ => None,
+        }
+    }
+
+    /// Whether sending the same request again could succeed: failed connections and
+    /// timeouts, truncated bodies, rate limiting and server errors are, rejected
+    /// requests and bodies that are complete but not the expected JSON are not.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            ResyAPIError::Network(e) => e.is_connect() || e.is_timeout() || e.is_request(),
+            ResyAPIError::Status { status, .. } => *status == 429 || *status >= 500,
+            ResyAPIError::Deserialize { source, .. } => source.is_eof(),
+            ResyAPIError::TooLarge { .. } | ResyAPIError::InvalidCredentials(_) | ResyAPIError::Io(_) => false,
+        }
+    }
+
+    /// Whether Resy refused a booking because the account already holds a reservation
+    /// that conflicts with it: a 409, or a 412 whose code or message says so.
+    pub fn is_already_booked(&self) -> bool {
+        match self {
+            ResyAPIError::Status { status: 409, .. } => true,
+            ResyAPIError::Status { status: 412, body: Some(body), .. } => {
+                body.code.as_deref() == Some("reservation_conflict")
+                    || body.message.to_ascii_lowercase().contains("already have a reservation")
+            }
+            _ => false,
+        }
+    }
+
+    /// Resy's reason for rejecting the request, e.g. `Invalid payment method`.
+    pub fn resy_message(&self) -> Option<&str> {
+        match self {
+            ResyAPIError::Status { body: Some(body), .. } => Some(&body.message),
+            _ => None,
+        }
+    }
 
-impl From<std::io::Error> for ResyAPIError {
-    fn from(error: std::io::Error) -> Self {
-        ResyAPIError {
-            message: error.to_string(),
+    /// How long Resy asked us to wait before retrying, if it said.
+    pub fn retry_delay_hint(&self) -> Option<Duration> {
+        match self {
+            ResyAPIError::Status { retry_after, .. } => *retry_after,
+            _ => None,
+        }
+    }
+}
+
+/// Intermediary tokens some venues hand out with a find slot and expect back on details,
+/// next to the slot's config token.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SlotTokens {
+    /// Template the slot was generated from (`template.id`)
+    pub template_id: Option<String>,
+    /// Hold placed on the slot (`lock.id`)
+    pub lock_id: Option<String>,
+}
+
+/// Mode of `get_reservation_details`, sent to Resy as `commit=0|1`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Commit {
+    /// Preview the slot without minting a book token
+    DryRun,
+    /// Mint a book token for `book_reservation`
+    GenerateToken,
+}
+
+impl Serialize for Commit {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Commit::DryRun => serializer.serialize_u8(0),
+            Commit::GenerateToken => serializer.serialize_u8(1),
         }
     }
 }
 
 /// Handles communication with the Resy API.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ResyAPIGateway {
     client: Client,
-    api_key: String,
-    auth_token: String,
+    base_url: String,
+    /// Shared with clones, so credentials set on one are sent by all
+    auth: Arc<RwLock<Credentials>>,
+    extra_headers: HeaderMap,
+    override_auth_headers: bool,
+    device_id: Option<HeaderValue>,
+    send_correlation_id: bool,
+    max_response_bytes: usize,
+    pool_max_idle_per_host: usize,
+    http1_only: bool,
 }
 
 impl ResyAPIGateway {
 
-    /// Creates a new API gateway instance (without authentication)
-    pub fn new() -> Self {
+    /// Creates a new API gateway instance with authentication.
+    pub fn from_auth(api_key: String, auth_token: String) -> Self {
         ResyAPIGateway {
-            client: Client::new(),
-            api_key: String::new(),
-            auth_token: String::new(),
+            client: build_client(DEFAULT_POOL_MAX_IDLE_PER_HOST, false),
+            base_url: String::from(RESY_API_BASE_URL),
+            auth: Arc::new(RwLock::new(Credentials { api_key, auth_token })),
+            extra_headers: HeaderMap::new(),
+            override_auth_headers: false,
+            device_id: None,
+            send_correlation_id: false,
+            max_response_bytes: DEFAULT_MAX_RESPONSE_BYTES,
+            pool_max_idle_per_host: DEFAULT_POOL_MAX_IDLE_PER_HOST,
+            http1_only: false,
         }
     }
 
-    /// Creates a new API gateway instance with authentication.
-    pub fn from_auth(api_key: String, auth_token: String) -> Self {
-        ResyAPIGateway {
-            client: Client::new(),
-            api_key,
-            auth_token,
+    /// Sends `api_key` and `auth_token` from now on, from this gateway and its clones.
+    /// Credentials that can't be sent as headers are refused, keeping the current ones.
+    pub fn set_auth(&self, api_key: String, auth_token: String) -> Result<(), ResyAPIError> {
+        let credentials = Credentials { api_key, auth_token };
+        auth_header_values(&credentials)?;
+        *self.auth.write().unwrap() = credentials;
+        Ok(())
+    }
+
+    /// Points the gateway at a different API host (e.g. a mock server).
+    pub fn set_base_url(&mut self, base_url: String) {
+        self.base_url = base_url.trim_end_matches('/').to_string();
+    }
+
+    /// Sets additional headers sent with every request. Auth headers are only
+    /// replaced when `override_auth_headers` is set.
+    pub fn set_extra_headers(&mut self, extra_headers: HeaderMap, override_auth_headers: bool) {
+        self.extra_headers = extra_headers;
+        self.override_auth_headers = override_auth_headers;
+    }
+
+    /// Sets the device id sent as `x-resy-device-id` on the booking endpoints
+    /// (`/3/details` and `/3/book`), which some clients are asked to identify on.
+    /// The web client sends none, so this is unset by default.
+    pub fn set_device_id(&mut self, device_id: Option<&str>) {
+        self.device_id = device_id.and_then(|id| match HeaderValue::from_str(id) {
+            Ok(value) => Some(value),
+            Err(_) => {
+                warn!("Ignoring invalid device id {}", id);
+                None
+            }
+        });
+    }
+
+    /// Sends the current correlation id (see `correlation::scope`) as `x-correlation-id`,
+    /// so Resy-side logs can be matched to ours. Off by default.
+    pub fn set_send_correlation_id(&mut self, send_correlation_id: bool) {
+        self.send_correlation_id = send_correlation_id;
+    }
+
+    /// Caps how much of a response body is read before failing with `TooLarge`.
+    pub fn set_max_response_bytes(&mut self, max_response_bytes: usize) {
+        self.max_response_bytes = max_response_bytes;
+    }
+
+    /// Caps the idle connections kept open per host. A burst only avoids new
+    /// handshakes if as many connections as it fires were kept warm, so a cap below
+    /// the burst's concurrency costs reconnects. Replaces the connection pool.
+    pub fn set_pool_max_idle_per_host(&mut self, pool_max_idle_per_host: usize) {
+        if pool_max_idle_per_host != self.pool_max_idle_per_host {
+            self.pool_max_idle_per_host = pool_max_idle_per_host;
+            self.client = build_client(pool_max_idle_per_host, self.http1_only);
+        }
+    }
+
+    /// Pins requests to HTTP/1.1 instead of negotiating the protocol, for networks where
+    /// HTTP/2 to Resy is flaky. Off by default. Replaces the connection pool.
+    pub fn set_http1_only(&mut self, http1_only: bool) {
+        if http1_only != self.http1_only {
+            self.http1_only = http1_only;
+            self.client = build_client(self.pool_max_idle_per_host, http1_only);
+        }
+    }
+
+    /// Adds the auth headers for the current credentials.
+    fn apply_auth(&self, headers: &mut HeaderMap) -> Result<(), ResyAPIError> {
+        let (authorization, auth_token) = auth_header_values(&self.auth.read().unwrap())?;
+        headers.insert(AUTHORIZATION, authorization);
+        headers.insert("x-resy-auth-token", auth_token.clone());
+        headers.insert("x-resy-universal-auth", auth_token);
+        Ok(())
+    }
+
+    /// Adds the device id header, when configured.
+    fn apply_device_id(&self, headers: &mut HeaderMap) {
+        if let Some(device_id) = &self.device_id {
+            headers.insert("x-resy-device-id", device_id.clone());
+        }
+    }
+
+    /// Adds the correlation id header, when enabled and inside a correlation scope.
+    fn apply_correlation_id(&self, headers: &mut HeaderMap) {
+        if !self.send_correlation_id {
+            return;
+        }
+        if let Some(value) = correlation::current().and_then(|id| HeaderValue::from_str(&id).ok()) {
+            headers.insert("x-correlation-id", value);
+        }
+    }
+
+    /// Merges the user supplied extra headers into the request headers. An extra header
+    /// replaces the default one of the same name, keeping every value it was given.
+    fn apply_extra_headers(&self, headers: &mut HeaderMap) {
+        for name in self.extra_headers.keys() {
+            if !self.override_auth_headers && AUTH_HEADERS.contains(&name.as_str()) {
+                warn!("Ignoring extra header {} (overrides auth)", name);
+                continue;
+            }
+            headers.remove(name);
+            for value in self.extra_headers.get_all(name) {
+                headers.append(name.clone(), value.clone());
+            }
         }
     }
 
     /// Processes the HTTP response, converting JSON or returning an error.
-    async fn process_response(response: Response) -> Result<Value, Box<dyn Error>> {
+    async fn process_response(&self, response: Response) -> Result<Value, ResyAPIError> {
         if response.status().is_success() {
-            let json = response.json().await?;
-            Ok(json)
+            let body = self.read_body(response).await?;
+            serde_json::from_slice(&body).map_err(|source| ResyAPIError::Deserialize {
+                source,
+                snippet: self.redacted_snippet(&body),
+            })
         } else {
-            Err(Box::new(ResyAPIError {
-                message: format!("API request failed: {}", response.status())
-            }))
+            let retry_after = response.headers().get(RETRY_AFTER)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.trim().parse::<u64>().ok())
+                .map(Duration::from_secs);
+
+            let status = response.status().as_u16();
+            // the status is what matters, so an unreadable body is just left out
+            let body = self.read_body(response).await.ok()
+                .and_then(|body| ResyErrorBody::parse(&body));
+
+            Err(ResyAPIError::Status { status, retry_after, body })
+        }
+    }
+
+    /// Up to `ERROR_SNIPPET_CHARS` of `body`, with the api key and auth token blanked out.
+    fn redacted_snippet(&self, body: &[u8]) -> String {
+        // redacted before truncating, so a secret cut off at the end can't leak
+        let mut body = String::from_utf8_lossy(body).into_owned();
+        let auth = self.auth.read().unwrap();
+        for secret in [&auth.api_key, &auth.auth_token] {
+            if !secret.is_empty() {
+                body = body.replace(secret.as_str(), "[redacted]");
+            }
+        }
+        body.chars().take(ERROR_SNIPPET_CHARS).collect()
+    }
+
+    /// Reads the body, giving up as soon as it grows past `max_response_bytes`.
+    async fn read_body(&self, mut response: Response) -> Result<Vec<u8>, ResyAPIError> {
+        let limit = self.max_response_bytes;
+        if response.content_length().is_some_and(|length| length > limit as u64) {
+            return Err(ResyAPIError::TooLarge { limit });
+        }
+
+        let mut body = Vec::new();
+        while let Some(chunk) = response.chunk().await? {
+            if body.len() + chunk.len() > limit {
+                return Err(ResyAPIError::TooLarge { limit });
+            }
+            body.extend_from_slice(&chunk);
         }
+        Ok(body)
     }
 
     /// Sets up the necessary auth headers for making requests to the Resy API.
-    fn setup_headers(&self) -> HeaderMap {
+    fn setup_headers(&self) -> Result<HeaderMap, ResyAPIError> {
         let mut headers = HeaderMap::new();
 
         // ??
@@ -77,9 +379,7 @@ impl ResyAPIGateway {
         headers.insert(ACCEPT_LANGUAGE, HeaderValue::from_static("en-US,en;q=0.9"));
 
         // auth
-        headers.insert(AUTHORIZATION, HeaderValue::from_str(&format!("ResyAPI api_key=\"{}\"", self.api_key)).unwrap());
-        headers.insert("x-resy-auth-token", HeaderValue::from_str(&self.auth_token).unwrap());
-        headers.insert("x-resy-universal-auth", HeaderValue::from_str(&self.auth_token).unwrap());
+        self.apply_auth(&mut headers)?;
 
         // Additional headers from curl
         headers.insert("cache-control", HeaderValue::from_static("no-cache"));
@@ -96,71 +396,163 @@ impl ResyAPIGateway {
         headers.insert("user-agent", HeaderValue::from_static("Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/124.0.0.0 Safari/537.36"));
         headers.insert("x-origin", HeaderValue::from_static("https://widgets.resy.com"));
 
-        headers
+        self.apply_correlation_id(&mut headers);
+        self.apply_extra_headers(&mut headers);
+        Ok(headers)
     }
 
     /// Fetches user details from the Resy API.
-    pub async fn get_user(&self) -> Result<Value, Box<dyn Error>> {
-        let url = format!("{}/2/user", RESY_API_BASE_URL);
-        let headers = self.setup_headers();
+    pub async fn get_user(&self) -> Result<Value, ResyAPIError> {
+        let url = format!("{}/2/user", self.base_url);
+        let headers = self.setup_headers()?;
 
         let res = self.client.get(url)
             .headers(headers)
             .send()
             .await?;
 
-        Self::process_response(res).await
+        self.process_response(res).await
     }
 
     /// Retrieves details about a venue from the Resy API.
-    pub async fn get_venue(&self, venue_slug: &str) -> Result<Value, Box<dyn Error>> {
-        let url = format!("{}/3/venue?url_slug={}&location=new-york-ny", RESY_API_BASE_URL, venue_slug);
-        let headers = self.setup_headers();
+    pub async fn get_venue(&self, venue_slug: &str, location: &str) -> Result<Value, ResyAPIError> {
+        let url = format!("{}/3/venue?url_slug={}&location={}", self.base_url, venue_slug, location);
+        let headers = self.setup_headers()?;
+
+        let res = self.client.get(url)
+            .headers(headers)
+            .send()
+            .await?;
+
+        self.process_response(res).await
+    }
+
+    /// Retrieves details about a venue by its Resy id.
+    pub async fn get_venue_by_id(&self, venue_id: &str) -> Result<Value, ResyAPIError> {
+        let url = format!("{}/3/venue?id={}", self.base_url, venue_id);
+        let headers = self.setup_headers()?;
 
         let res = self.client.get(url)
             .headers(headers)
             .send()
             .await?;
 
-        Self::process_response(res).await
+        self.process_response(res).await
     }
 
-    /// Finds reservations at a venue.
-    pub async fn find_reservation(&self, venue_id: &str, day: &str, party_size: u8, target_time: Option<&str>) -> Result<Value, Box<dyn Error>> {
-        let mut url = format!("{}/4/find?lat=0&long=0&day={}&party_size={}&venue_id={}", RESY_API_BASE_URL, day, party_size, venue_id);
+    /// Finds reservations at a venue, optionally scoped to one reservation type (e.g. `dining`).
+    pub async fn find_reservation(&self, venue_id: &str, day: &str, party_size: u8, target_time: Option<&str>, reservation_type: Option<&str>, coordinates: Coordinates) -> Result<Value, ResyAPIError> {
+        let mut url = format!("{}/4/find?lat={}&long={}&day={}&party_size={}&venue_id={}", self.base_url, coordinates.latitude, coordinates.longitude, day, party_size, venue_id);
 
         if let Some(time) = target_time {
             let formatted_time = format!("{}:{}", &time[..2], &time[2..]);
             url = format!("{}&time_filter={}", url, formatted_time);
         }
 
-        let headers = self.setup_headers();
+        if let Some(reservation_type) = reservation_type {
+            url = format!("{}&type={}", url, urlencoding::encode(reservation_type));
+        }
+
+        let headers = self.setup_headers()?;
+
+        let res = self.client.get(url)
+            .headers(headers)
+            .send()
+            .await?;
+
+        self.process_response(res).await
+    }
+
+    /// Searches venues around `coordinates`, one page at a time, with availability for `day`.
+    pub async fn search_venues(&self, coordinates: Coordinates, radius_meters: u32, query: Option<&str>, day: &str, party_size: u8, page: u32) -> Result<Value, ResyAPIError> {
+        let url = format!("{}/3/venuesearch/search", self.base_url);
+        let headers = self.setup_headers()?;
+
+        let data = json!({
+            "geo": {
+                "latitude": coordinates.latitude,
+                "longitude": coordinates.longitude,
+                "radius": radius_meters
+            },
+            "query": query.unwrap_or_default(),
+            "page": page,
+            "per_page": SEARCH_PAGE_SIZE,
+            "slot_filter": {
+                "day": day,
+                "party_size": party_size
+            },
+            "types": ["venue"]
+        });
+
+        let res = self.client.post(url)
+            .headers(headers)
+            .json(&data)
+            .send()
+            .await?;
+
+        self.process_response(res).await
+    }
+
+    /// Fetches the venue's day by day availability between two dates (inclusive).
+    pub async fn get_calendar(&self, venue_id: &str, party_size: u8, start_date: &str, end_date: &str) -> Result<Value, ResyAPIError> {
+        let url = format!("{}/4/venue/calendar?venue_id={}&num_seats={}&start_date={}&end_date={}", self.base_url, venue_id, party_size, start_date, end_date);
+        let headers = self.setup_headers()?;
 
         let res = self.client.get(url)
             .headers(headers)
             .send()
             .await?;
 
-        Self::process_response(res).await
+        self.process_response(res).await
     }
 
-    /// Gets reservation details from the Resy API.
+    /// Gets reservation details from the Resy API. The slot's `tokens` are only sent when present.
     pub async fn get_reservation_details(
         &self,
-        commit: u8, // 0 for dry run, 1 for token gen
+        commit: Commit,
         config_id: &str,
+        tokens: &SlotTokens,
         party_size: u8,
         day: &str,
-    ) -> Result<Value, Box<dyn Error>> {
-        let url = format!("{}/3/details", RESY_API_BASE_URL);
-        let headers = self.setup_headers();
+    ) -> Result<Value, ResyAPIError> {
+        let url = format!("{}/3/details", self.base_url);
+        let mut headers = self.setup_headers()?;
+        self.apply_device_id(&mut headers);
 
-        let data = json!({
+        let mut data = json!({
             "commit": commit,
             "config_id": config_id,
             "day": day,
             "party_size": party_size
         });
+        if let Some(template_id) = &tokens.template_id {
+            data["template_id"] = json!(template_id);
+        }
+        if let Some(lock_id) = &tokens.lock_id {
+            data["lock_id"] = json!(lock_id);
+        }
+
+        let res = self.client.post(url)
+            .headers(headers)
+            .json(&data)
+            .send()
+            .await?;
+
+        self.process_response(res).await
+    }
+
+    /// Briefly holds a slot ahead of booking, for venues whose details report locking as
+    /// supported. The returned `lock_token` is passed on to `book_reservation`.
+    pub async fn lock_slot(&self, config_id: &str, party_size: u8, day: &str) -> Result<Value, ResyAPIError> {
+        let url = format!("{}/3/lock", self.base_url);
+        let mut headers = self.setup_headers()?;
+        self.apply_device_id(&mut headers);
+
+        let data = json!({
+            "config_id": config_id,
+            "day": day,
+            "party_size": party_size
+        });
 
         let res = self.client.post(url)
             .headers(headers)
@@ -168,10 +560,10 @@ impl ResyAPIGateway {
             .send()
             .await?;
 
-        Self::process_response(res).await
+        self.process_response(res).await
     }
 
-    fn setup_book_headers(&self) -> HeaderMap {
+    fn setup_book_headers(&self) -> Result<HeaderMap, ResyAPIError> {
         let mut headers = HeaderMap::new();
 
         // Content Type
@@ -184,9 +576,7 @@ impl ResyAPIGateway {
         headers.insert(ACCEPT_LANGUAGE, HeaderValue::from_static("en-US,en;q=0.9"));
 
         // Authorization and Token
-        headers.insert(AUTHORIZATION, HeaderValue::from_str(&format!("ResyAPI api_key=\"{}\"", self.api_key)).unwrap());
-        headers.insert("x-resy-auth-token", HeaderValue::from_str(&self.auth_token).unwrap());
-        headers.insert("x-resy-universal-auth", HeaderValue::from_str(&self.auth_token).unwrap());
+        self.apply_auth(&mut headers)?;
 
         // Additional headers from curl
         headers.insert("cache-control", HeaderValue::from_static("no-cache"));
@@ -203,17 +593,72 @@ impl ResyAPIGateway {
         headers.insert("user-agent", HeaderValue::from_static("Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/124.0.0.0 Safari/537.36"));
         headers.insert("x-origin", HeaderValue::from_static("https://widgets.resy.com"));
 
-        headers
+        self.apply_correlation_id(&mut headers);
+        self.apply_extra_headers(&mut headers);
+        Ok(headers)
     }
 
-    /// Books reservation via the Resy API (dry run possible)
-    pub async fn book_reservation(&self, book_token: &str, payment_id: &str) -> Result<Value, Box<dyn Error>> {
-        let url = format!("{}/3/book", RESY_API_BASE_URL);
-        let headers = self.setup_book_headers();
+    /// Books reservation via the Resy API (dry run possible), optionally under a linked guest account.
+    /// Without a `payment_id` no payment method is sent, for venues that don't take one. A
+    /// `lock_token` from `lock_slot` books the held slot.
+    pub async fn book_reservation(&self, book_token: &str, payment_id: Option<&str>, add_on_id: Option<&str>, guest_id: Option<&str>, lock_token: Option<&str>) -> Result<Value, ResyAPIError> {
+        let url = format!("{}/3/book", self.base_url);
+        let mut headers = self.setup_book_headers()?;
+        self.apply_device_id(&mut headers);
+
+        let mut body = format!("book_token={}", urlencoding::encode(book_token));
+
+        if let Some(payment_id) = payment_id {
+            body = format!("{}&struct_payment_method={{\"id\":{}}}", body, payment_id);
+        }
+
+        if let Some(add_on_id) = add_on_id {
+            let add_ons = json!([{ "id": add_on_id }]).to_string();
+            body = format!("{}&struct_add_ons={}", body, urlencoding::encode(&add_ons));
+        }
+
+        if let Some(guest_id) = guest_id {
+            body = format!("{}&guest_id={}", body, urlencoding::encode(guest_id));
+        }
+
+        if let Some(lock_token) = lock_token {
+            body = format!("{}&lock_token={}", body, urlencoding::encode(lock_token));
+        }
+
+        let res = self.client.post(&url)
+            .headers(headers)
+            .body(body)
+            .send()
+            .await?;
+
+        self.process_response(res).await
+    }
+
+    /// Cancels a booked reservation via the Resy API.
+    pub async fn cancel_reservation(&self, resy_token: &str) -> Result<Value, ResyAPIError> {
+        let url = format!("{}/3/cancel", self.base_url);
+        let headers = self.setup_book_headers()?;
+
+        let body = format!("resy_token={}", urlencoding::encode(resy_token));
+
+        let res = self.client.post(&url)
+            .headers(headers)
+            .body(body)
+            .send()
+            .await?;
+
+        self.process_response(res).await
+    }
+
+    /// Moves a booked reservation to another slot (`config_id`) and/or party size.
+    pub async fn change_reservation(&self, resy_token: &str, config_id: &str, party_size: u8, day: &str) -> Result<Value, ResyAPIError> {
+        let url = format!("{}/3/reservation/change", self.base_url);
+        let mut headers = self.setup_book_headers()?;
+        self.apply_device_id(&mut headers);
 
         let body = format!(
-            "book_token={}&struct_payment_method={{\"id\":{}}}",
-            urlencoding::encode(book_token), payment_id
+            "resy_token={}&config_id={}&party_size={}&day={}",
+            urlencoding::encode(resy_token), urlencoding::encode(config_id), party_size, day
         );
 
         let res = self.client.post(&url)
@@ -222,6 +667,52 @@ impl ResyAPIGateway {
             .send()
             .await?;
 
-        Self::process_response(res).await
+        self.process_response(res).await
+    }
+}
+
+fn build_client(pool_max_idle_per_host: usize, http1_only: bool) -> Client {
+    let mut builder = Client::builder().pool_max_idle_per_host(pool_max_idle_per_host);
+    if http1_only {
+        builder = builder.http1_only();
+    }
+    #[cfg(feature = "native-tls")]
+    {
+        builder = builder.use_native_tls();
+    }
+    #[cfg(all(feature = "rustls-tls", not(feature = "native-tls")))]
+    {
+        builder = builder.use_rustls_tls();
+    }
+    builder
+        .build()
+        .unwrap_or_else(|e| {
+            warn!("Failed to build HTTP client, using defaults: {}", e);
+            Client::new()
+        })
+}
+
+/// Converts `name = value` pairs into a header map, skipping invalid entries.
+/// The `Authorization` and auth token header values for `credentials`.
+fn auth_header_values(credentials: &Credentials) -> Result<(HeaderValue, HeaderValue), ResyAPIError> {
+    let authorization = HeaderValue::from_str(&format!("ResyAPI api_key=\"{}\"", credentials.api_key))
+        .map_err(|_| ResyAPIError::InvalidCredentials("api key is not a valid header value".to_string()))?;
+    let auth_token = HeaderValue::from_str(&credentials.auth_token)
+        .map_err(|_| ResyAPIError::InvalidCredentials("auth token is not a valid header value".to_string()))?;
+    Ok((authorization, auth_token))
+}
+
+pub fn parse_extra_headers<'a>(pairs: impl IntoIterator<Item = (&'a String, &'a String)>) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+
+    for (name, value) in pairs {
+        match (HeaderName::from_bytes(name.as_bytes()), HeaderValue::from_str(value)) {
+            (Ok(name), Ok(value)) => {
+                headers.append(name, value);
+            }
+            _ => warn!("Skipping invalid extra header {}", name),
+        }
     }
-}
\ No newline at end of file
+
+    headers
+}
@@ -1,84 +1,235 @@
-use std::error::Error;
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::atomic::{AtomicBool, Ordering as AtomicOrdering};
+use std::error::Error;
+use std::future::Future;
+use std::io::{self, Write};
+use std::path::Path;
 use std::thread;
 use std::time::{Duration as StdDuration, Instant};
-use futures::future::join_all;
-use chrono::{DateTime, Duration, Local, NaiveDate, NaiveTime, TimeZone, Utc};
-use log::{debug, error, info};
+use chrono::{DateTime, Duration, Local, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use futures::channel::oneshot;
+use futures::stream::{self, Stream};
+use log::{debug, error, info, log_enabled, warn, Level};
 use serde_json::{Value};
-use serde::Deserialize;
-use tokio::sync::Mutex;
-use rand;
-use rand::Rng;
+use serde::{Deserialize, Serialize};
 use tokio::time::{sleep, Duration as TokioDuration};
-use crate::config::Config;
-use crate::resy_api_gateway::ResyAPIGateway;
+use crate::batch::{run_batch, BatchMode, Throttle};
+use crate::cache::TtlCache;
+use crate::calendar::{format_calendar, CalendarDay};
+use crate::config::{Config, Coordinates, FoundAction, SlotCriterion, SnipeTarget};
+use crate::credentials::{CredentialProvider, Credentials};
+use crate::day::Day;
+use crate::discover::{format_venue_summaries, DiscoverFilters, VenueSummary};
+use crate::money::{currency_of, Money};
+use crate::retry::send_with_retry;
+use crate::single_flight::SingleFlight;
+use crate::snapshot::{append_snapshot, AvailabilitySnapshot};
+use crate::store::Store;
+use crate::resy_api_gateway::{parse_extra_headers, Commit, ResyAPIError, ResyAPIGateway, SlotTokens};
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", content = "message")]
 pub enum ResyClientError {
     NotFound(String),
-    NetworkError(String),
     ApiError(String),
-    InternalError(String),
     InvalidInput(String),
-    ParseError(String),
     BookingError(String),
+    RateLimited(String),
+    /// The all-in cost revealed by the reservation details is over `max_total`
+    PriceExceeded { total: Money, max_total: Money },
+    /// `max_total` is set but the reservation details quote no total to check it against
+    PriceUnknown(String),
+    /// The slot needs a deposit and `allow_deposits` is off
+    DepositRequired(String),
+    /// The account already holds a conflicting reservation, `resy_token` when Resy named it
+    AlreadyBooked { resy_token: Option<String> },
 }
 
 impl std::fmt::Display for ResyClientError {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "{:?}", self)
+        match self {
+            ResyClientError::NotFound(msg) => write!(f, "NotFound: {}", msg),
+            ResyClientError::ApiError(msg) => write!(f, "ApiError: {}", msg),
+            ResyClientError::InvalidInput(msg) => write!(f, "InvalidInput: {}", msg),
+            ResyClientError::BookingError(msg) => write!(f, "BookingError: {}", msg),
+            ResyClientError::RateLimited(msg) => write!(f, "RateLimited: {}", msg),
+            ResyClientError::PriceExceeded { total, max_total } => write!(f, "PriceExceeded: total {} is over the {} limit", total, max_total),
+            ResyClientError::PriceUnknown(msg) => write!(f, "PriceUnknown: {}", msg),
+            ResyClientError::DepositRequired(msg) => write!(f, "DepositRequired: {}", msg),
+            ResyClientError::AlreadyBooked { resy_token: Some(token) } => write!(f, "AlreadyBooked: the account already has a conflicting reservation (token: {})", token),
+            ResyClientError::AlreadyBooked { resy_token: None } => write!(f, "AlreadyBooked: the account already has a conflicting reservation"),
+        }
+    }
+}
+
+impl ResyClientError {
+    /// Name of the variant, e.g. `RateLimited`.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ResyClientError::NotFound(_) => "NotFound",
+            ResyClientError::ApiError(_) => "ApiError",
+            ResyClientError::InvalidInput(_) => "InvalidInput",
+            ResyClientError::BookingError(_) => "BookingError",
+            ResyClientError::RateLimited(_) => "RateLimited",
+            ResyClientError::PriceExceeded { .. } => "PriceExceeded",
+            ResyClientError::PriceUnknown(_) => "PriceUnknown",
+            ResyClientError::DepositRequired(_) => "DepositRequired",
+            ResyClientError::AlreadyBooked { .. } => "AlreadyBooked",
+        }
     }
 }
 
 impl Error for ResyClientError {}
 
-type ResyResult<T> = Result<T, ResyClientError>;
+pub type ResyResult<T> = Result<T, ResyClientError>;
 
 #[derive(Debug)]
 pub struct ResyClient {
     pub config: Config,
     api_gateway: ResyAPIGateway,
+    venue_cache: TtlCache<ResyVenue>,
+    find_flights: SingleFlight<Found>,
+    credential_provider: Option<Arc<dyn CredentialProvider>>,
+    slot_selector: Option<SlotSelector>,
+    /// Set by the first `shutdown`, shared with `for_target` clients
+    shut_down: Arc<AtomicBool>,
 }
 
 impl ResyClient {
-    pub(crate) fn new() -> Self {
+    /// Client for `config`, with the venues saved at `venue_cache_path` (if set) cached.
+    pub fn from_config(config: Config) -> Self {
+        let api_gateway = build_gateway(&config);
+        let venue_cache = TtlCache::new(StdDuration::from_secs(config.venue_cache_ttl_secs));
+        if let Some(path) = &config.venue_cache_path {
+            if let Err(e) = venue_cache.load(Path::new(path)) {
+                warn!("Failed to load venue cache from {}: {:#}", path, e);
+            }
+        }
+
         ResyClient {
-            config: Config::default(),
-            api_gateway: ResyAPIGateway::new(),
+            config,
+            api_gateway,
+            venue_cache,
+            find_flights: SingleFlight::new(),
+            credential_provider: None,
+            slot_selector: None,
+            shut_down: Arc::new(AtomicBool::new(false)),
         }
     }
 
-    pub(crate) fn from_config(config: Config) -> Self {
-        let api_key = config.api_key.clone();
-        let auth_token = config.auth_token.clone();
+    /// Client for a daemon target, sharing this client's connection pool, venue cache and in-flight finds.
+    pub fn for_target(&self, target: &SnipeTarget) -> Self {
+        self.with_config(target.apply(&self.config))
+    }
 
+    fn with_config(&self, config: Config) -> Self {
         ResyClient {
             config,
-            api_gateway: ResyAPIGateway::from_auth(api_key, auth_token),
+            api_gateway: self.api_gateway.clone(),
+            venue_cache: self.venue_cache.clone(),
+            find_flights: self.find_flights.clone(),
+            credential_provider: self.credential_provider.clone(),
+            slot_selector: self.slot_selector.clone(),
+            shut_down: self.shut_down.clone(),
+        }
+    }
+
+    /// Takes credentials from `provider` from now on: they are loaded right away and
+    /// reloaded whenever a keepalive or a request finds the current ones rejected.
+    pub async fn set_credential_provider(&mut self, provider: Arc<dyn CredentialProvider>) -> ResyResult<()> {
+        self.credential_provider = Some(provider);
+        self.refresh_credentials().await
+    }
+
+    /// Picks slots with `selector` instead of the configured preferences from now on.
+    pub fn set_slot_selector(&mut self, selector: SlotSelector) {
+        self.slot_selector = Some(selector);
+    }
+
+    /// Preferences from the config, with the slot selector when one is set. With a
+    /// `min_lead_mins`, the venue is looked up (usually cached) for its time zone.
+    pub async fn slot_preferences(&self) -> SlotPreferences {
+        let mut preferences = SlotPreferences { select_fn: self.slot_selector.clone(), ..SlotPreferences::from_config(&self.config) };
+        if preferences.min_lead_mins > 0 {
+            match self.get_venue_by_id(&self.config.venue_id).await {
+                Ok(venue) => preferences.time_zone = venue.time_zone,
+                Err(e) => warn!("Failed to load the venue's time zone, timing the lead in local time: {}", e),
+            }
+        }
+        preferences
+    }
+
+    /// `rank_slots`, explaining the choice in debug logs.
+    fn rank(&self, slots: Vec<ResySlot>, preferences: &SlotPreferences) -> Vec<ResySlot> {
+        if log_enabled!(Level::Debug) {
+            debug!("{}", explain_selection(slots.clone(), preferences));
         }
+        rank_slots(slots, preferences)
     }
 
-    pub(crate) fn load_config(&mut self, config: Config) {
-        let api_key = config.api_key.clone();
-        let auth_token = config.auth_token.clone();
+    /// Reloads credentials from the credential provider, if one is set.
+    pub async fn refresh_credentials(&mut self) -> ResyResult<()> {
+        let Some(provider) = self.credential_provider.clone() else {
+            return Ok(());
+        };
+
+        let credentials = load_credentials(provider.as_ref()).await?;
+        self.update_auth(credentials.api_key, credentials.auth_token)
+    }
+
+    /// Sends `request`, and if it's rejected for auth while a credential provider is set,
+    /// reloads the credentials and sends it once more. Clients sharing the gateway send the
+    /// reloaded credentials too, but this one's `config` keeps the old ones.
+    async fn with_reauth<T, F, Fut>(&self, request: F) -> Result<T, ResyAPIError>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, ResyAPIError>>,
+    {
+        send_with_reauth(&self.api_gateway, self.credential_provider.as_deref(), request).await
+    }
+
+    /// Waits up to `shutdown_timeout_secs` for finds still in flight (also those of
+    /// `for_target` clients), then saves the venue cache to `venue_cache_path`, if set.
+    /// Bookings and attempts are stored and notified as they happen, so nothing else is
+    /// left to flush. Only the first call, across clients sharing this one's caches,
+    /// does anything.
+    pub async fn shutdown(&self) -> ResyResult<()> {
+        if self.shut_down.swap(true, AtomicOrdering::SeqCst) {
+            return Ok(());
+        }
+        info!("Shutting down client");
+
+        let limit = StdDuration::from_secs(self.config.shutdown_timeout_secs);
+        if tokio::time::timeout(limit, self.find_flights.wait_idle()).await.is_err() {
+            warn!("Finds still in flight after {:?}, not waiting for them", limit);
+        }
 
-        self.config = config;
-        self.api_gateway = ResyAPIGateway::from_auth(api_key, auth_token);
+        match &self.config.venue_cache_path {
+            Some(path) => self.venue_cache.save(Path::new(path))
+                .map_err(|e| ResyClientError::InvalidInput(format!("Failed to save venue cache: {:#}", e))),
+            None => Ok(()),
+        }
     }
 
-    pub(crate) fn update_auth(&mut self, api_key: String, auth_token: String) {
-        let api_key_clone = api_key.clone();
-        let auth_token_clone = auth_token.clone();
+    /// Drops every cached venue so the next lookup goes to the API.
+    pub fn invalidate_venue_cache(&self) {
+        self.venue_cache.clear();
+    }
 
+    /// Sends `api_key` and `auth_token` from now on. Values that can't be sent as headers
+    /// are refused, keeping the current credentials.
+    pub fn update_auth(&mut self, api_key: String, auth_token: String) -> ResyResult<()> {
+        self.api_gateway.set_auth(api_key.clone(), auth_token.clone())
+            .map_err(|e| ResyClientError::InvalidInput(e.to_string()))?;
         self.config.api_key = api_key;
         self.config.auth_token = auth_token;
-
-        self.api_gateway = ResyAPIGateway::from_auth(api_key_clone, auth_token_clone)
+        Ok(())
     }
 
-    pub(crate) async fn view_venue(&mut self, url: Option<&str>, date: Option<&str>, party_size: Option<u8>, target_time: Option<&str>) -> ResyResult<(String, Vec<ResySlot>)> {
+    pub async fn view_venue(&mut self, url: Option<&str>, date: Option<&str>, party_size: Option<u8>, target_time: Option<&str>) -> ResyResult<(String, Vec<ResySlot>)> {
         if let Some(url) = url {
             let _ = self.load_venue_id_from_url(url).await?;
         }
@@ -94,10 +245,10 @@ impl ResyClient {
         }
 
         if let Some(target_time) = target_time {
-            if target_time.len() == 4 && target_time.chars().all(|c| c.is_digit(10)) {
-                let hours = &target_time[..2].parse::<u32>().unwrap();
-                let minutes = &target_time[2..].parse::<u32>().unwrap();
-                if *hours < 24 && *minutes < 60 {
+            if target_time.len() == 4 && target_time.chars().all(|c| c.is_ascii_digit()) {
+                let hours = target_time[..2].parse::<u32>().unwrap();
+                let minutes = target_time[2..].parse::<u32>().unwrap();
+                if hours < 24 && minutes < 60 {
                     self.config.target_time = Some(target_time.to_string());
                 } else {
                     return Err(ResyClientError::InvalidInput("Invalid time format. Please use HHMM format, where HH is 00 to 23 and MM is 00 to 59.".to_string()));
@@ -109,16 +260,15 @@ impl ResyClient {
             self.config.target_time = None;
         }
 
-        let mut slots = self._find_reservation_slots().await?;
-        if let Some(target_time) = &self.config.target_time {
-            slots = sort_slots_by_closest_time(slots, target_time);
-        }
+        let slots = self.rank(self._find_reservation_slots().await?, &self.slot_preferences().await);
 
         let venue_id = self.config.venue_id.clone();
         Ok((venue_id, slots))
     }
 
-    pub(crate) async fn run_sniper(&mut self, snipe_time: &str, snipe_date: &str) -> ResyResult<String> {
+    /// Waits for the drop, then watches for slots. Returns the booking, or `None` if
+    /// `on_found` is `NotifyOnly` and the watch ran out.
+    pub async fn run_sniper(&mut self, snipe_time: &str, snipe_date: &str, confirm: bool) -> ResyResult<Option<BookingResult>> {
         // Check if snipe_date is provided and valid, else use the stored config value
         let date = if !snipe_date.is_empty() {
             NaiveDate::parse_from_str(snipe_date, "%Y-%m-%d")
@@ -156,7 +306,9 @@ impl ResyClient {
         }
 
         remaining = datetime - Local::now();
-        while remaining > Duration::seconds(0) {
+        let keepalive_interval = StdDuration::from_secs(self.config.keepalive_interval_secs);
+        let mut last_keepalive = Instant::now();
+        while remaining > Duration::seconds(WARM_UP_SECS) {
             if remaining <= Duration::minutes(2) {
                 // Log more frequently as the time approaches
                 info!("Time remaining: {} seconds", remaining.num_seconds());
@@ -164,73 +316,544 @@ impl ResyClient {
             } else {
                 // Log periodically
                 info!("Time remaining: {} minutes", remaining.num_minutes());
+                if !keepalive_interval.is_zero() && last_keepalive.elapsed() >= keepalive_interval {
+                    self.keepalive().await;
+                    last_keepalive = Instant::now();
+                }
                 sleep(TokioDuration::from_secs(60)).await;
             }
             remaining = datetime - Local::now();
         }
 
+        self.snipe_at(datetime, confirm, |slot| println!("Slot open: {}", slot)).await
+    }
+
+    /// The drop itself, entered at most `WARM_UP_SECS` before `release_at`: warms up the
+    /// connection, waits for the release, then watches for slots (see `watch`).
+    pub async fn snipe_at<F: FnMut(&ResySlot)>(&self, release_at: DateTime<Local>, confirm: bool, notify: F) -> ResyResult<Option<BookingResult>> {
+        if release_at > Local::now() {
+            self.warm_up(release_at).await;
+            if let Ok(wait) = (release_at - Local::now()).to_std() {
+                info!("Releasing in {} ms", wait.as_millis());
+                sleep(wait).await;
+            }
+        }
+
+        self.check_config()?;
+        self.watch(confirm, notify).await
+    }
+
+    /// Polls for slots and acts on them according to `on_found`: `Book` books the best one,
+    /// `NotifyOnly` calls `notify` once per newly seen slot and keeps watching until
+    /// `poll_timeout_secs`, returning `None`.
+    pub async fn watch<F: FnMut(&ResySlot)>(&self, confirm: bool, mut notify: F) -> ResyResult<Option<BookingResult>> {
+        match self.config.on_found {
+            FoundAction::Book => {
+                let slots = self.poll_until_available().await?;
+                self.book_first(slots, confirm).await.map(Some)
+            }
+            FoundAction::NotifyOnly => {
+                let mut seen = HashSet::new();
+                self.poll_slots(|slots| {
+                    for slot in slots.iter().filter(|slot| seen.insert(slot.token.clone())) {
+                        notify(slot);
+                    }
+                    false
+                }).await?;
+                Ok(None)
+            }
+        }
+    }
+
+    /// Pings the API to keep the connection and auth warm during a long wait. Rejected
+    /// credentials are reloaded from the credential provider when there is one.
+    pub async fn keepalive(&mut self) {
+        match self.ensure_valid().await {
+            Ok(true) => info!("Keepalive reloaded rejected credentials"),
+            Ok(false) => debug!("Keepalive ok"),
+            Err(e) => warn!("Keepalive failed: {}", e),
+        }
+    }
+
+    /// Checks the current credentials with one `get_user`. If they are rejected, they are
+    /// reloaded from the credential provider and checked again. Returns whether they were
+    /// reloaded, or an error if there is no provider or the reloaded ones are rejected too.
+    pub async fn ensure_valid(&mut self) -> ResyResult<bool> {
+        match self.api_gateway.get_user().await {
+            Ok(_) => Ok(false),
+            Err(e) if is_auth_error(&e) && self.credential_provider.is_some() => {
+                warn!("Credentials rejected ({}), reloading them", e);
+                self.refresh_credentials().await?;
+                match self.api_gateway.get_user().await {
+                    Ok(_) => Ok(true),
+                    Err(e) => Err(ResyClientError::ApiError(format!("Reloaded credentials were rejected too: {}", e))),
+                }
+            }
+            Err(e) if is_auth_error(&e) => {
+                Err(ResyClientError::ApiError(format!("Auth token looks stale ({}). Run `marksman load` to refresh it", e)))
+            }
+            Err(e) => Err(ResyClientError::ApiError(format!("Error checking credentials: {}", e))),
+        }
+    }
 
-        if !self.config.validate() {
-            return Err(ResyClientError::InvalidInput("reservation config is not complete".to_string()));
+    /// Warms up the connection ahead of the drop. Retryable failures trigger a reconnect
+    /// with the `retry` policy's backoff for as long as there is time left before `deadline`.
+    async fn warm_up(&self, deadline: DateTime<Local>) {
+        match self.with_reauth(|| send_with_retry(&self.config.retry, Some(deadline), || self.api_gateway.get_user())).await {
+            Ok(_) => info!("Connection warmed up"),
+            Err(e) => warn!("Warm up failed: {}", e),
         }
 
-        let mut slots = self._find_reservation_slots().await?;
-        if let Some(target_time) = &self.config.target_time {
-            slots = sort_slots_by_closest_time(slots, target_time);
+        // cached now, so checking service hours costs nothing at the drop
+        if self.config.check_service_hours {
+            if let Err(e) = self.get_venue_by_id(&self.config.venue_id).await {
+                warn!("Failed to load venue ahead of the drop: {}", e);
+            }
         }
+    }
 
+    /// Finds the open slots (closest to the target time first) and books the first one that succeeds.
+    pub async fn book_best(&self, confirm: bool) -> ResyResult<BookingResult> {
+        self.check_config()?;
+
+        let slots = self.rank(self._find_reservation_slots().await?, &self.slot_preferences().await);
         if slots.is_empty() {
             return Err(ResyClientError::NotFound("no reservation slots available".to_string()));
         }
 
-        for slot in slots {
-            match self._sniper_task(&slot.token, &slot.start).await {
-                Ok(tok) => {
-                    return Ok(tok)
+        self.book_first(slots, confirm).await
+    }
+
+    /// Polls find until slots show up: every `burst_interval_ms` for the first
+    /// `burst_window_secs`, then every `poll_interval_ms`, giving up after `poll_timeout_secs`.
+    /// Rate limited polls back off following the `retry` policy, never faster than the
+    /// normal interval, and give up with the rate limit error once the policy runs out.
+    pub async fn poll_until_available(&self) -> ResyResult<Vec<ResySlot>> {
+        let mut found = Vec::new();
+        let stopped = self.poll_slots(|slots| {
+            found = slots;
+            true
+        }).await?;
+
+        if stopped {
+            Ok(found)
+        } else {
+            Err(ResyClientError::NotFound("no reservation slots available".to_string()))
+        }
+    }
+
+    /// Runs the polling schedule behind `poll_until_available`, handing each non-empty
+    /// (ranked) find to `on_slots`. Returns `true` once `on_slots` asks to stop, or
+    /// `false` when the timeout is reached.
+    async fn poll_slots<F: FnMut(Vec<ResySlot>) -> bool>(&self, mut on_slots: F) -> ResyResult<bool> {
+        let started = Instant::now();
+        let burst_window = StdDuration::from_secs(self.config.burst_window_secs);
+        let timeout = StdDuration::from_secs(self.config.poll_timeout_secs);
+        let burst_interval = StdDuration::from_millis(self.config.burst_interval_ms);
+        let poll_interval = StdDuration::from_millis(self.config.poll_interval_ms);
+        let mut rate_limited = 0;
+        let preferences = self.slot_preferences().await;
+
+        loop {
+            let found = self._find_reservation_slots().await;
+            if !matches!(found, Err(ResyClientError::RateLimited(_))) {
+                rate_limited = 0;
+            }
+
+            match found {
+                Ok(slots) if !slots.is_empty() => {
+                    info!("Found {} slots after {} ms", slots.len(), started.elapsed().as_millis());
+                    if on_slots(self.rank(slots, &preferences)) {
+                        return Ok(true);
+                    }
+                }
+                Ok(_) => {}
+                Err(ResyClientError::RateLimited(msg)) => {
+                    rate_limited += 1;
+                    let wait = self.config.retry.delay(rate_limited).max(poll_interval);
+                    if started.elapsed() + wait > timeout {
+                        return Ok(false);
+                    }
+                    if !self.config.retry.allows(rate_limited, started, wait) {
+                        return Err(ResyClientError::RateLimited(msg));
+                    }
+                    warn!("{}, backing off for {:?}", msg, wait);
+                    sleep(wait).await;
+                    continue;
+                }
+                Err(e) => return Err(e),
+            }
+
+            let interval = if started.elapsed() < burst_window { burst_interval } else { poll_interval };
+            if started.elapsed() + interval > timeout {
+                return Ok(false);
+            }
+            sleep(interval).await;
+        }
+    }
+
+    /// Polls find every `interval` and yields the ranked slot list whenever it changes,
+    /// starting with the first one. Rate limited polls back off following the `retry`
+    /// policy's waits, but are never given up on. Other failures are logged and retried.
+    /// Polling stops when the stream is dropped.
+    pub fn watch_slots(&self, interval: StdDuration) -> impl Stream<Item = Vec<ResySlot>> + '_ {
+        stream::unfold((None, StdDuration::ZERO), move |(last, mut wait): (Option<Vec<String>>, StdDuration)| {
+            async move {
+                let preferences = self.slot_preferences().await;
+                let mut rate_limited = 0;
+                loop {
+                    sleep(wait).await;
+                    match self._find_reservation_slots().await {
+                        Ok(slots) => {
+                            wait = interval;
+                            rate_limited = 0;
+                            let slots = rank_slots(slots, &preferences);
+                            let mut tokens: Vec<String> = slots.iter().map(|slot| slot.token.clone()).collect();
+                            tokens.sort();
+                            if last.as_ref() != Some(&tokens) {
+                                return Some((slots, (Some(tokens), wait)));
+                            }
+                        }
+                        Err(ResyClientError::RateLimited(msg)) => {
+                            rate_limited += 1;
+                            wait = self.config.retry.delay(rate_limited).max(interval);
+                            warn!("{}, backing off for {:?}", msg, wait);
+                        }
+                        Err(e) => {
+                            wait = interval;
+                            rate_limited = 0;
+                            warn!("Watching slots failed: {}", e);
+                        }
+                    }
+                }
+            }
+        })
+    }
+
+    /// Polls find every `interval` until a slot matching `wanted` opens, then books it,
+    /// ignoring every other slot. A lost race keeps the watch going. Gives up with
+    /// `NotFound` once `until` passes. Rate limited polls back off like `watch_slots`.
+    pub async fn book_when_open(&self, wanted: &SlotCriterion, until: DateTime<Local>, interval: StdDuration, confirm: bool) -> ResyResult<BookingResult> {
+        self.check_config()?;
+        if wanted.time.as_deref().is_some_and(|time| NaiveTime::parse_from_str(time, "%H%M").is_err()) {
+            return Err(ResyClientError::InvalidInput("Invalid time format".to_string()));
+        }
+
+        let mut rate_limited = 0;
+        loop {
+            let mut wait = interval;
+            match self._find_reservation_slots().await {
+                Ok(slots) => {
+                    rate_limited = 0;
+                    let matching: Vec<ResySlot> = slots.into_iter()
+                        .filter(|slot| criterion_distance(wanted, slot).is_some())
+                        .collect();
+                    if !matching.is_empty() {
+                        info!("{} opened", wanted);
+                        match self.book_first(matching, confirm).await {
+                            Err(ResyClientError::BookingError(msg)) => warn!("{}, still watching", msg),
+                            result => return result,
+                        }
+                    }
+                }
+                Err(ResyClientError::RateLimited(msg)) => {
+                    rate_limited += 1;
+                    wait = self.config.retry.delay(rate_limited).max(interval);
+                    warn!("{}, backing off for {:?}", msg, wait);
+                }
+                Err(e) => warn!("Watching for {} failed: {}", wanted, e),
+            }
+
+            if Duration::from_std(wait).map_or(true, |wait| Local::now() + wait > until) {
+                return Err(ResyClientError::NotFound(format!("{} did not open before {}", wanted, until.format("%Y-%m-%d %H:%M"))));
+            }
+            sleep(wait).await;
+        }
+    }
+
+    /// Swaps `current` for `better`, a slot found with this client's config. The better
+    /// slot is booked first and the original is only cancelled once that succeeds. If the
+    /// cancellation then fails, both reservations are held and the error is logged. The
+    /// new booking and the cancellation are recorded in `store`.
+    pub async fn upgrade(&self, current: &BookingResult, better: &ResySlot, store: &mut impl Store) -> ResyResult<BookingResult> {
+        let upgraded = self.book_first(vec![better.clone()], false).await
+            .map_err(|e| ResyClientError::BookingError(format!("Upgrade to {} failed, keeping {}: {}", better.start, current.slot.start, e)))?;
+        if let Err(e) = store.record(&upgraded) {
+            error!("Failed to record booking {}: {:#}", upgraded.resy_token, e);
+        }
+
+        match self.cancel_reservation(&current.resy_token).await {
+            Ok(()) => {
+                if let Err(e) = store.record_cancellation(&current.resy_token) {
+                    error!("Failed to record cancellation of {}: {:#}", current.resy_token, e);
+                }
+            }
+            Err(e) => error!("Booked {} but failed to cancel {} (token: {}): {}", better.start, current.slot.start, current.resy_token, e),
+        }
+
+        Ok(upgraded)
+    }
+
+    /// Cancels the reservation `resy_token`. Retryable failures, rate limits included,
+    /// are retried following the `retry` policy.
+    pub async fn cancel_reservation(&self, resy_token: &str) -> ResyResult<()> {
+        match self.with_reauth(|| send_with_retry(&self.config.retry, None, || self.api_gateway.cancel_reservation(resy_token))).await {
+            Ok(json) => {
+                debug!("Cancel reservation response {:#?}", json);
+                Ok(())
+            }
+            Err(e) if is_rate_limited(&e) => {
+                Err(ResyClientError::RateLimited(format!("Rate limited cancelling reservation: {}", e)))
+            }
+            Err(e) => {
+                Err(ResyClientError::ApiError(format!("Error cancelling reservation: {:?}", e)))
+            }
+        }
+    }
+
+    /// Cancels every reservation in `resy_tokens`, at most `CANCEL_CONCURRENCY` at once, and
+    /// returns each token with its result, in the order given. A failed cancel doesn't stop the rest.
+    pub async fn cancel_all(&self, resy_tokens: &[&str]) -> Vec<(String, ResyResult<()>)> {
+        let inputs: Vec<(usize, String)> = resy_tokens.iter().map(|token| token.to_string()).enumerate().collect();
+        let results = run_batch(inputs, CANCEL_CONCURRENCY, BatchMode::CollectAll, |(_, token)| async move {
+            self.cancel_reservation(&token).await
+        }).await;
+
+        // CollectAll never stops early
+        let mut results = results.unwrap_or_default();
+        results.sort_by_key(|((position, _), _)| *position);
+        results.into_iter().map(|((_, token), result)| (token, result)).collect()
+    }
+
+    /// Moves `current` to `slot` on `day` for `party_size`, keeping the same reservation
+    /// rather than cancelling and rebooking. A change Resy refuses (e.g. the slot is gone or
+    /// the venue doesn't allow edits) is a `BookingError` and leaves `current` untouched.
+    pub async fn change_reservation(&self, current: &BookingResult, slot: &ResySlot, party_size: u8, day: Day) -> ResyResult<BookingResult> {
+        let date = day.to_api_string();
+        match self.with_reauth(|| self.api_gateway.change_reservation(&current.resy_token, &slot.token, party_size, &date)).await {
+            Ok(json) => {
+                debug!("Change reservation response {:#?}", json);
+                Ok(BookingResult {
+                    resy_token: json["resy_token"].as_str().unwrap_or(&current.resy_token).to_string(),
+                    venue_id: current.venue_id.clone(),
+                    date,
+                    party_size,
+                    slot: slot.clone(),
+                    // the new slot's policy isn't known without a details call
+                    cancellation: None,
+                })
+            }
+            Err(e) if is_rate_limited(&e) => {
+                Err(ResyClientError::RateLimited(format!("Rate limited changing reservation: {}", e)))
+            }
+            Err(e) if is_change_refused(&e) => {
+                Err(ResyClientError::BookingError(format!("Change to {} not allowed, keeping {}: {}", slot.start, current.slot.start, e)))
+            }
+            Err(e) => {
+                Err(ResyClientError::ApiError(format!("Error changing reservation: {:?}", e)))
+            }
+        }
+    }
+
+    /// Re-runs find to check `slot` is still listed right before booking it. A slot that
+    /// reappeared under a new token (same start and seating) is returned with the fresh
+    /// token, one that is gone yields `None`. If the find itself fails, `slot` is kept.
+    async fn revalidate(&self, slot: ResySlot) -> Option<ResySlot> {
+        let current = match self._find_reservation_slots().await {
+            Ok(current) => current,
+            Err(e) => {
+                warn!("Could not revalidate {} ({}), booking it as found", slot.start, e);
+                return Some(slot);
+            }
+        };
+
+        if current.iter().any(|listed| listed.token == slot.token) {
+            return Some(slot);
+        }
+        match current.into_iter().find(|listed| listed.start == slot.start && listed.slot_type == slot.slot_type) {
+            Some(refreshed) => {
+                info!("Slot {} was relisted with a new token, using it", slot.start);
+                Some(refreshed)
+            }
+            None => {
+                info!("Slot {} is no longer listed, skipping", slot.start);
+                None
+            }
+        }
+    }
+
+    fn check_config(&self) -> ResyResult<()> {
+        let problems = self.config.problems(BatchMode::CollectAll);
+        if !problems.is_empty() {
+            return Err(ResyClientError::InvalidInput(format!("reservation config is not complete: {}", problems.join(", "))));
+        }
+        Ok(())
+    }
+
+    /// With `check_service_hours`, drops slots starting outside the venue's service hours,
+    /// which stale find data occasionally returns. Venues without hours, or that can't be
+    /// looked up, are left unchecked.
+    async fn within_service_hours(&self, slots: Vec<ResySlot>) -> Vec<ResySlot> {
+        if !self.config.check_service_hours {
+            return slots;
+        }
+
+        let hours = match self.get_venue_by_id(&self.config.venue_id).await {
+            Ok(venue) if !venue.service_hours.is_empty() => venue.service_hours,
+            Ok(_) => return slots,
+            Err(e) => {
+                warn!("Not checking service hours: {}", e);
+                return slots;
+            }
+        };
+
+        slots.into_iter().filter(|slot| {
+            let open = slot_start_time(slot).is_none_or(|time| hours.iter().any(|hours| hours.contains(time)));
+            if !open {
+                warn!("Skipping {} @ {}: outside the venue's service hours", slot.slot_type, slot.start);
+            }
+            open
+        }).collect()
+    }
+
+    async fn book_first(&self, slots: Vec<ResySlot>, confirm: bool) -> ResyResult<BookingResult> {
+        // a slot refused by the price or deposit guard explains the failure better than the generic error
+        let mut refused = None;
+        for slot in self.within_service_hours(slots).await {
+            let slot = if self.config.revalidate_before_book {
+                match self.revalidate(slot).await {
+                    Some(slot) => slot,
+                    None => continue,
+                }
+            } else {
+                slot
+            };
+
+            match self._sniper_task(&slot, confirm).await {
+                Ok((resy_token, cancellation)) => {
+                    return Ok(BookingResult {
+                        resy_token,
+                        venue_id: self.config.venue_id.clone(),
+                        date: self.config.date.clone(),
+                        party_size: self.config.party_size,
+                        slot,
+                        cancellation,
+                    })
                 }
-                Err(e) => {}
+                Err(e @ (ResyClientError::PriceExceeded { .. } | ResyClientError::PriceUnknown(_) | ResyClientError::DepositRequired(_))) => refused = Some(e),
+                // every other slot would conflict the same way
+                Err(e @ ResyClientError::AlreadyBooked { .. }) => return Err(e),
+                Err(e) => debug!("Snipe @ {} failed: {}", slot.start, e),
             }
         }
 
-        Err(ResyClientError::BookingError("Booking failure: all slots failed".to_string()))
+        Err(refused.unwrap_or_else(|| ResyClientError::BookingError("Booking failure: all slots failed".to_string())))
     }
 
-    async fn _sniper_task(&self, config_id: &str, time_slot: &str) -> ResyResult<String> {
+    /// Books `slot`, returning the resy token and the slot's cancellation policy.
+    async fn _sniper_task(&self, slot: &ResySlot, confirm: bool) -> ResyResult<(String, Option<CancellationPolicy>)> {
+        let (config_id, time_slot) = (slot.token.as_str(), slot.start.as_str());
         info!("Running snipe @ {} (token: {})", time_slot, config_id);
 
-        let book_token = match self.api_gateway.get_reservation_details(1, &config_id, self.config.party_size, &self.config.date).await {
+        // the drop is on, so failed calls get the tight `booking_retry` budget
+        let booking_retry = &self.config.booking_retry;
+        let details = match self.with_reauth(|| send_with_retry(booking_retry, None, || {
+            self.api_gateway.get_reservation_details(Commit::GenerateToken, config_id, &slot.tokens, self.config.party_size, &self.config.date)
+        })).await {
             Ok(json) => {
                 debug!("Reservation details response {:#?}", json);
 
                 if json.get("book_token").is_some() {
-                    match json["book_token"]["value"].as_str() {
-                        Some(token) => token.to_string(),
+                    match ReservationDetails::from_json(&json) {
+                        Some(details) => details,
                         None => return Err(ResyClientError::BookingError("Book token not found".to_string()))
                     }
                 } else {
                     return Err(ResyClientError::BookingError("Error fetching book token".to_string())) // didn't get it in time!
                 }
             }
+            Err(e) if e.is_already_booked() => return Err(already_booked(&e)),
             Err(e) => {
                 error!("Error getting book token {:?}", e);
                 return Err(ResyClientError::BookingError("Error fetching book token".to_string()))
             }
         };
 
-        info!("Book token acquired @ {} (token: {})", time_slot, book_token);
+        let book_token = &details.book_token;
+        info!("Book token acquired @ {} (token: {})", time_slot, book_token.value);
+        if let Some(cancellation) = &details.cancellation {
+            info!("Cancellation policy @ {}: {}", time_slot, cancellation);
+        }
+
+        let add_on = self.config.preferred_add_on.as_deref()
+            .and_then(|preferred| details.find_add_on(preferred));
+        if add_on.is_none() && !details.add_ons.is_empty() {
+            info!("No preferred add-on selected @ {} (available: {:?})", time_slot, details.add_ons);
+        }
+
+        let payment_id = match (details.payment_required, self.config.payment_id.as_str()) {
+            (false, _) => None,
+            (true, "") => return Err(ResyClientError::BookingError("Venue requires a payment method but no payment_id is set".to_string())),
+            (true, payment_id) => Some(payment_id),
+        };
+
+        if details.deposit_required && !self.config.allow_deposits {
+            warn!("Not booking {}: it requires a deposit", time_slot);
+            return Err(ResyClientError::DepositRequired(format!("{} requires a deposit", time_slot)));
+        }
+
+        if let Some(max_total) = self.config.max_total {
+            // open reservations cost nothing to book, anything else unquoted can't be checked
+            let quoted = match (&details.total, details.payment_required) {
+                (Some(total), _) => Some(total.amount),
+                (None, false) => Some(0.0),
+                (None, true) => None,
+            };
+            let amount = quoted.unwrap_or_default() + add_on.and_then(|add_on| add_on.price).unwrap_or_default();
+            let (total, max_total) = (Money::new(amount, &details.currency), Money::new(max_total, &details.currency));
+            if total.amount > max_total.amount {
+                warn!("Not booking {}: total {} is over the {} limit", time_slot, total, max_total);
+                return Err(ResyClientError::PriceExceeded { total, max_total });
+            }
+            if quoted.is_none() {
+                warn!("Not booking {}: no total is quoted to check against the {} limit", time_slot, max_total);
+                return Err(ResyClientError::PriceUnknown(format!("{} quotes no total to check against the {} limit", time_slot, max_total)));
+            }
+        }
+
+        if confirm && !confirm_booking(book_token, time_slot).await {
+            return Err(ResyClientError::BookingError("Booking aborted".to_string()));
+        }
+
+        // a lock only narrows the race window, so the booking goes ahead without one if it fails
+        let lock_token = if details.lock_supported {
+            match self.lock_slot(config_id).await {
+                Ok(lock_token) => Some(lock_token),
+                Err(e) => {
+                    warn!("Locking {} failed, booking without a lock: {}", time_slot, e);
+                    None
+                }
+            }
+        } else {
+            None
+        };
 
-        return match self.api_gateway.book_reservation(&book_token, &self.config.payment_id).await {
+        let add_on_id = add_on.map(|add_on| add_on.id.as_str());
+        return match self.with_reauth(|| send_with_retry(booking_retry, None, || {
+            self.api_gateway.book_reservation(&book_token.value, payment_id, add_on_id, self.config.guest_id.as_deref(), lock_token.as_deref())
+        })).await {
             Ok(json) => {
                 debug!("Booking reservation response {:#?}", json);
 
-                match json.get("resy_token") {
+                match json["resy_token"].as_str() {
                     Some(token) => {
                         info!("acquired {} (token: {})", time_slot, token);
-                        Ok(token.to_string())
+                        Ok((token.to_string(), details.cancellation.clone()))
                     },
                     None => Err(ResyClientError::BookingError("Error booking reservation".to_string())),
                 }
             }
+            Err(e) if e.is_already_booked() => Err(already_booked(&e)),
             Err(e) => {
                 error!("Error booking reservation {:?}", e);
                 Err(ResyClientError::BookingError("Error booking reservation".to_string()))
@@ -238,7 +861,24 @@ impl ResyClient {
         };
     }
 
-    // pub(crate) async fn run_snipe(self: Arc<ResyClient>) -> ResyResult<String> {
+    /// Holds the slot with config token `config_token` for the configured day and party
+    /// size, returning the lock token to book it with. Only venues whose details report
+    /// locking as supported accept this.
+    pub async fn lock_slot(&self, config_token: &str) -> ResyResult<String> {
+        match self.with_reauth(|| self.api_gateway.lock_slot(config_token, self.config.party_size, &self.config.date)).await {
+            Ok(json) => match json["lock_token"].as_str() {
+                Some(lock_token) => {
+                    info!("Locked slot (lock: {})", lock_token);
+                    Ok(lock_token.to_string())
+                }
+                None => Err(ResyClientError::BookingError("Lock token not found".to_string())),
+            },
+            Err(e) if is_rate_limited(&e) => Err(ResyClientError::RateLimited(format!("Rate limited locking slot: {}", e))),
+            Err(e) => Err(ResyClientError::BookingError(format!("Error locking slot: {}", e))),
+        }
+    }
+
+    // pub async fn run_snipe(self: Arc<ResyClient>) -> ResyResult<String> {
     //     if !self.config.validate() {
     //         return Err(ResyClientError::InvalidInput("reservation config is not complete".to_string()));
     //     }
@@ -274,7 +914,7 @@ impl ResyClient {
     // async fn _snipe_task(&self, config_id: String, time_slot: String, book_mutex: Arc<Mutex<()>>, booking_successful: Arc<AtomicBool>) -> Option<String> {
     //     info!("Running snipe @ {} (token: {})", time_slot, config_id);
     //
-    //     let book_token = match self.api_gateway.get_reservation_details(1, &config_id, self.config.party_size, &self.config.date).await {
+    //     let book_token = match self.api_gateway.get_reservation_details(1, config_id, self.config.party_size, &self.config.date).await {
     //         Ok(json) => {
     //             debug!("Reservation details response {:#?}", json);
     //
@@ -339,14 +979,62 @@ impl ResyClient {
     //     None
     // }
 
-    pub(crate) async fn get_payment_id(&mut self) -> ResyResult<String> {
-        match self.api_gateway.get_user().await {
+    /// What sniping `target` would do: the resolved venue, when it fires and how slots
+    /// are chosen and paid for. Only the venue is looked up, availability is not.
+    pub async fn plan(&self, target: &SnipeTarget) -> ResyResult<SnipePlan> {
+        let venue = self.get_venue_by_id(&target.venue_id).await?;
+        let config = target.apply(&self.config);
+
+        Ok(SnipePlan {
+            venue_id: target.venue_id.clone(),
+            venue_name: venue.name,
+            date: config.date.clone(),
+            party_size: config.party_size,
+            release_at: target.release_at(),
+            preferences: SlotPreferences::from_config(&config),
+            preferred_add_on: config.preferred_add_on.clone(),
+            payment_id: Some(config.payment_id.clone()).filter(|payment_id| !payment_id.is_empty()),
+            max_total: config.max_total,
+            allow_deposits: config.allow_deposits,
+            on_found: config.on_found,
+        })
+    }
+
+    /// Checks credentials, payment method and API reachability.
+    pub async fn healthcheck(&self) -> HealthReport {
+        let mut report = HealthReport::default();
+
+        let has_credentials = !self.config.api_key.is_empty() && !self.config.auth_token.is_empty();
+        report.add("credentials configured", has_credentials, "api_key and auth_token are set");
+
+        let started = Instant::now();
+        let user = self.api_gateway.get_user().await;
+        report.latency = Some(started.elapsed());
+
+        match user {
+            Ok(user_data) => {
+                report.add("credentials valid", true, "fetched user");
+
+                let has_payment = user_data["payment_methods"].as_array().is_some_and(|methods| !methods.is_empty());
+                report.add("payment method", has_payment, "account has a payment method");
+            }
+            Err(e) => {
+                report.add("credentials valid", false, &format!("fetching user failed: {}", e));
+                report.add("payment method", false, "unknown (user not fetched)");
+            }
+        }
+
+        report
+    }
+
+    pub async fn get_payment_id(&mut self) -> ResyResult<String> {
+        match self.with_reauth(|| self.api_gateway.get_user()).await {
             Ok(user_data) => {
                 let payment_methods = user_data["payment_methods"]
                     .as_array()
                     .ok_or_else(|| ResyClientError::NotFound("No payment method found in resy account".to_string()))?;
 
-                let payment_id = payment_methods.get(0)
+                let payment_id = payment_methods.first()
                     .ok_or_else(|| ResyClientError::NotFound("Payment method list is empty".to_string()))?
                     .get("id")
                     .and_then(|id| id.as_i64())
@@ -362,82 +1050,1221 @@ impl ResyClient {
         }
     }
 
+    /// Accounts linked to the user that a booking can be made for, set one's id as
+    /// `guest_id` to book under it. Empty when the account has none.
+    pub async fn linked_accounts(&self) -> ResyResult<Vec<LinkedAccount>> {
+        match self.with_reauth(|| self.api_gateway.get_user()).await {
+            Ok(user_data) => Ok(format_linked_accounts(&user_data)),
+            Err(e) => {
+                Err(ResyClientError::ApiError(format!("Error fetching linked accounts: {:?}", e)))
+            }
+        }
+    }
+
     async fn load_venue_id_from_url(&mut self, url: &str) -> ResyResult<u64> {
-        let venue_slug = extract_venue_slug(url)?;
+        let venue_slug = extract_venue_slug(url)
+            .ok_or_else(|| ResyClientError::InvalidInput("invalid resy url".to_string()))?;
         self.config.venue_slug = venue_slug.clone();
 
-        match self.api_gateway.get_venue(venue_slug.as_str()).await {
-            Ok(venue_info) => {
-                if let Some(venue_id) = venue_info["id"]["resy"].as_u64() {
-                    self.config.venue_id = venue_id.to_string();
+        let venue = self.get_venue(&venue_slug).await?;
 
-                    Ok(venue_id)
-                } else {
-                    Err(ResyClientError::NotFound("Venue ID not found".to_string()))
-                }
+        self.config.venue_id = venue.id.to_string();
+        self.config.venue_coordinates = venue.coordinates;
+        Ok(venue.id)
+    }
+
+    /// Looks up a venue by its url slug in the configured location, served from the
+    /// venue cache when possible.
+    async fn get_venue(&self, venue_slug: &str) -> ResyResult<ResyVenue> {
+        let key = format!("slug:{}/{}", self.config.venue_location, venue_slug);
+        if let Some(venue) = self.venue_cache.get(&key) {
+            return Ok(venue);
+        }
+
+        let venue = match self.with_reauth(|| self.api_gateway.get_venue(venue_slug, &self.config.venue_location)).await {
+            Ok(venue_info) => format_venue(&venue_info)?,
+            Err(e) => {
+                return Err(ResyClientError::ApiError(format!("Error fetching venue: {:?}", e)))
             }
+        };
+        self.cache_venue(Some(key), &venue);
+        Ok(venue)
+    }
+
+    /// Looks up a venue by its Resy id, without needing the url slug. Served from the
+    /// venue cache when possible.
+    pub async fn get_venue_by_id(&self, venue_id: &str) -> ResyResult<ResyVenue> {
+        if let Some(venue) = self.venue_cache.get(&format!("id:{}", venue_id)) {
+            return Ok(venue);
+        }
+
+        let venue = match self.with_reauth(|| self.api_gateway.get_venue_by_id(venue_id)).await {
+            Ok(venue_info) => format_venue(&venue_info)?,
             Err(e) => {
-                Err(ResyClientError::ApiError(format!("Error fetching venue: {:?}", e)))
+                return Err(ResyClientError::ApiError(format!("Error fetching venue: {:?}", e)))
             }
+        };
+        self.cache_venue(None, &venue);
+        Ok(venue)
+    }
+
+    fn cache_venue(&self, slug_key: Option<String>, venue: &ResyVenue) {
+        self.venue_cache.insert(format!("id:{}", venue.id), venue.clone());
+        if let Some(key) = slug_key {
+            self.venue_cache.insert(key, venue.clone());
         }
     }
 
-    async fn _find_reservation_slots(&self) -> ResyResult<Vec<ResySlot>> {
-        match self.api_gateway.find_reservation(self.config.venue_id.as_str(), self.config.date.as_str(), self.config.party_size, self.config.target_time.as_deref()).await {
-            Ok(json) => Ok(format_slots(json)),
+    pub async fn get_calendar(&self, start_date: Day, end_date: Day) -> ResyResult<Vec<CalendarDay>> {
+        let (start_date, end_date) = (start_date.to_api_string(), end_date.to_api_string());
+        match self.with_reauth(|| self.api_gateway.get_calendar(&self.config.venue_id, self.config.party_size, &start_date, &end_date)).await {
+            Ok(json) => Ok(format_calendar(json)),
             Err(e) => {
-                Err(ResyClientError::ApiError(format!("Error fetching venue: {:?}", e)))
+                Err(ResyClientError::ApiError(format!("Error fetching calendar: {:?}", e)))
             }
         }
     }
-}
 
-// UTILS
+    /// Finds venues around `location` matching `filters`, paging through the search
+    /// results until `max_results` venues are collected or the results run out.
+    pub async fn discover(&self, location: &Coordinates, filters: &DiscoverFilters) -> ResyResult<Vec<VenueSummary>> {
+        let day = filters.day.to_api_string();
+        let mut venues: Vec<VenueSummary> = Vec::new();
+        let mut seen = 0;
+        let mut page = 1;
+
+        while venues.len() < filters.max_results {
+            let json = match self.with_reauth(|| self.api_gateway.search_venues(*location, filters.radius_meters, filters.query.as_deref(), &day, filters.party_size, page)).await {
+                Ok(json) => json,
+                Err(e) => {
+                    return Err(ResyClientError::ApiError(format!("Error searching venues: {:?}", e)))
+                }
+            };
 
-fn extract_venue_slug(url: &str) -> ResyResult<String> {
-    if let Some(start) = url.find("venues/") {
-        let start = start + "venues/".len();
-        let end = url[start..].find('?').unwrap_or_else(|| url[start..].len());
-        return Ok(url[start..start + end].to_string());
+            let (summaries, total) = format_venue_summaries(&json);
+            if summaries.is_empty() {
+                break;
+            }
+            seen += summaries.len() as u64;
+            venues.extend(summaries.into_iter().filter(|venue| !filters.only_available || venue.is_available()));
+
+            if total.is_some_and(|total| seen >= total) {
+                break;
+            }
+            page += 1;
+        }
+
+        venues.truncate(filters.max_results);
+        Ok(venues)
     }
-    Err(ResyClientError::InvalidInput("invalid resy url".to_string()))
-}
 
-#[derive(Deserialize, Debug)]
-pub(crate) struct ResySlot {
-    pub(crate) id: String,
-    pub(crate) token: String,
-    pub(crate) slot_type: String,
-    pub(crate) start: String,
-    pub(crate) end: String,
-    pub(crate) min_size: u64,
-    pub(crate) max_size: u64,
-    pub(crate) quantity: u64,
-}
+    /// Finds open slots at the configured venue, falling back to the configured day and party size.
+    pub async fn find(&self, day: Option<Day>, party_size: Option<u8>) -> ResyResult<Vec<ResySlot>> {
+        let day = day.map(|day| day.to_api_string()).unwrap_or_else(|| self.config.date.clone());
+        self.find_slots(&self.config.venue_id, &day, party_size.unwrap_or(self.config.party_size)).await
+    }
 
-fn format_slots(json: Value) -> Vec<ResySlot> {
-    if let Some(slots) = json["results"]["venues"][0]["slots"].as_array() {
-        let summarized: Vec<ResySlot> = slots.iter().filter_map(|slot| {
+    /// Whether the configured venue has any open slot on `day` for `party_size`, from one
+    /// find that ignores the target time, seating and other preferences. A sold out
+    /// venue is `false`, not an error.
+    pub async fn is_available(&self, day: Day, party_size: u8) -> ResyResult<bool> {
+        let client = self.with_config(Config {
+            target_time: None,
+            reservation_type: None,
+            ..self.config.clone()
+        });
+        let slots = client.find_slots(&self.config.venue_id, &day.to_api_string(), party_size).await?;
+        Ok(!slots.is_empty())
+    }
 
-            let config = slot["config"].as_object()?;
-            let date = slot["date"].as_object()?;
-            let size = slot["size"].as_object()?;
+    /// Finds every open slot at the configured venue on `day` for `party_size`, ignoring
+    /// preferences as `is_available` does, and appends them with the time to the JSONL
+    /// file at `path`. Read them back with `load_snapshots`.
+    pub async fn snapshot_to(&self, path: &Path, day: Day, party_size: u8) -> ResyResult<AvailabilitySnapshot> {
+        let client = self.with_config(Config {
+            target_time: None,
+            reservation_type: None,
+            ..self.config.clone()
+        });
+        let date = day.to_api_string();
+        let slots = client.find_slots(&self.config.venue_id, &date, party_size).await?;
 
-            Some(ResySlot {
-                id: config.get("id")?.as_number()?.to_string(),
-                token: config.get("token")?.as_str()?.to_string(),
-                slot_type: config.get("type")?.as_str()?.to_string(),
-                start: date.get("start")?.as_str()?.to_string(),
-                end: date.get("end")?.as_str()?.to_string(),
-                min_size: size.get("min")?.as_u64()?,
-                max_size: size.get("max")?.as_u64()?,
-                quantity: slot.get("quantity")?.as_u64()?,
-            })
-        }).collect();
+        let snapshot = AvailabilitySnapshot {
+            taken_at: Local::now(),
+            venue_id: self.config.venue_id.clone(),
+            date,
+            party_size,
+            slots,
+        };
+        append_snapshot(path, &snapshot)
+            .map_err(|e| ResyClientError::InvalidInput(format!("Failed to save snapshot: {:#}", e)))?;
+        Ok(snapshot)
+    }
 
-        summarized
-    } else {
-        Vec::new()
+    /// Seating types (`config.type`) with open slots at `venue_id`, sorted and de-duplicated.
+    pub async fn list_seating_types(&self, venue_id: &str, day: Day, party_size: u8) -> ResyResult<Vec<String>> {
+        let slots = self.find_slots(venue_id, &day.to_api_string(), party_size).await?;
+        Ok(seating_types(&slots))
+    }
+
+    /// Finds open slots for each of `days`, running at most `max_concurrency` finds at once.
+    /// Uses the configured party size unless one is given.
+    pub async fn find_range(&self, venue_id: &str, days: &[Day], party_size: Option<u8>, max_concurrency: usize, mode: BatchMode) -> ResyResult<HashMap<Day, ResyResult<Vec<ResySlot>>>> {
+        let party_size = party_size.unwrap_or(self.config.party_size);
+        let throttle = Throttle::default();
+        let results = run_batch(days.to_vec(), max_concurrency, mode, |day| {
+            let throttle = &throttle;
+            async move { self.find_slots_throttled(throttle, venue_id, &day.to_api_string(), party_size).await }
+        }).await?;
+
+        Ok(results.into_iter().collect())
+    }
+
+    /// Searches every combination of `days` and `party_sizes` at `venue_id`, at most
+    /// `max_concurrency` finds at once, and returns the best slot overall: each
+    /// combination's top slot, ranked against the others by `preferences`. Ties go to the
+    /// earlier combination (days first, both in the given order). Combinations that fail
+    /// are skipped; if nothing is found, the first failure is returned (e.g.
+    /// `RateLimited`), else `NotFound`.
+    pub async fn find_anything(&self, venue_id: &str, days: &[Day], party_sizes: &[u8], preferences: &SlotPreferences, max_concurrency: usize) -> ResyResult<SlotMatch> {
+        let combinations: Vec<(usize, Day, u8)> = days.iter()
+            .flat_map(|day| party_sizes.iter().map(move |party_size| (*day, *party_size)))
+            .enumerate()
+            .map(|(priority, (day, party_size))| (priority, day, party_size))
+            .collect();
+
+        let throttle = Throttle::default();
+        let mut results = run_batch(combinations, max_concurrency, BatchMode::CollectAll, |(_, day, party_size)| {
+            let throttle = &throttle;
+            async move { self.find_slots_throttled(throttle, venue_id, &day.to_api_string(), party_size).await }
+        }).await?;
+        results.sort_by_key(|((priority, _, _), _)| *priority);
+
+        let mut first_error = None;
+        let mut best: Vec<SlotMatch> = Vec::new();
+        for ((_, day, party_size), result) in results {
+            match result {
+                Ok(slots) => {
+                    if let Some(slot) = select_slot(slots, preferences) {
+                        best.push(SlotMatch { venue_id: venue_id.to_string(), day, party_size, slot });
+                    }
+                }
+                Err(e) => {
+                    debug!("Find for {} on {} (party of {}) failed: {}", venue_id, day, party_size, e);
+                    first_error.get_or_insert(e);
+                }
+            }
+        }
+
+        // slot tokens name their day and party size, so each ranked slot leads back to its match
+        let top = select_slot(best.iter().map(|found| found.slot.clone()).collect(), preferences);
+        match top.and_then(|top| best.into_iter().find(|found| found.slot.token == top.token)) {
+            Some(found) => Ok(found),
+            None => Err(first_error.unwrap_or_else(|| ResyClientError::NotFound("no reservation slots available".to_string()))),
+        }
+    }
+
+    /// Books a slot found by `find_anything`, for its venue, day and party size.
+    pub async fn book_match(&self, found: &SlotMatch, confirm: bool) -> ResyResult<BookingResult> {
+        let client = self.with_config(Config {
+            venue_id: found.venue_id.clone(),
+            date: found.day.to_api_string(),
+            party_size: found.party_size,
+            ..self.config.clone()
+        });
+        client.check_config()?;
+
+        client.book_first(vec![found.slot.clone()], confirm).await
+    }
+
+    async fn _find_reservation_slots(&self) -> ResyResult<Vec<ResySlot>> {
+        self.find_slots(&self.config.venue_id, &self.config.date, self.config.party_size).await
+    }
+
+    /// With `dedupe_finds`, concurrent identical finds (same venue, day, party size and
+    /// filters) share a single request.
+    async fn find_slots(&self, venue_id: &str, day: &str, party_size: u8) -> ResyResult<Vec<ResySlot>> {
+        self.find_slots_hinted(venue_id, day, party_size).await.0
+    }
+
+    /// `find_slots` for one find of a fan-out. A rate limit holds back every find sharing
+    /// `throttle` for the wait Resy asked for (else the `retry` policy's backoff), then the
+    /// find is sent again, for as long as the `retry` policy allows.
+    async fn find_slots_throttled(&self, throttle: &Throttle, venue_id: &str, day: &str, party_size: u8) -> ResyResult<Vec<ResySlot>> {
+        let started = Instant::now();
+        let mut attempts = 1;
+        loop {
+            throttle.ready().await;
+            match self.find_slots_hinted(venue_id, day, party_size).await {
+                (Err(ResyClientError::RateLimited(msg)), retry_after) => {
+                    let wait = retry_after.unwrap_or_else(|| self.config.retry.delay(attempts));
+                    if !self.config.retry.allows(attempts, started, wait) {
+                        return Err(ResyClientError::RateLimited(msg));
+                    }
+                    warn!("{}, holding back the batch for {:?}", msg, wait);
+                    throttle.hold(wait);
+                    attempts += 1;
+                }
+                (found, _) => return found,
+            }
+        }
+    }
+
+    async fn find_slots_hinted(&self, venue_id: &str, day: &str, party_size: u8) -> Found {
+        let api_gateway = self.api_gateway.clone();
+        let credential_provider = self.credential_provider.clone();
+        let (venue_id, day) = (venue_id.to_string(), day.to_string());
+        let target_time = self.config.target_time.clone();
+        let reservation_type = self.config.reservation_type.clone();
+        let coordinates = self.config.search_coordinates();
+        let key = format!("{}|{}|{}|{:?}|{:?}|{},{}", venue_id, day, party_size, target_time, reservation_type, coordinates.latitude, coordinates.longitude);
+
+        let find = async move {
+            let found = send_with_reauth(&api_gateway, credential_provider.as_deref(), || {
+                api_gateway.find_reservation(&venue_id, &day, party_size, target_time.as_deref(), reservation_type.as_deref(), coordinates)
+            }).await;
+            match found {
+                Ok(json) => (Ok(format_slots(json)), None),
+                Err(e) if is_rate_limited(&e) => {
+                    (Err(ResyClientError::RateLimited(format!("Rate limited fetching venue: {}", e))), e.retry_delay_hint())
+                }
+                Err(e) => {
+                    (Err(ResyClientError::ApiError(format!("Error fetching venue: {:?}", e))), None)
+                }
+            }
+        };
+
+        if self.config.dedupe_finds {
+            self.find_flights.run(key, find).await
+        } else {
+            find.await
+        }
+    }
+}
+
+// UTILS
+
+/// A find's slots, with the wait Resy asked for when it rate limited the find.
+type Found = (ResyResult<Vec<ResySlot>>, Option<StdDuration>);
+
+/// Seconds before the drop at which the connection is warmed up.
+pub const WARM_UP_SECS: i64 = 30;
+
+/// Cancels `cancel_all` sends at once.
+const CANCEL_CONCURRENCY: usize = 3;
+
+fn is_rate_limited(error: &ResyAPIError) -> bool {
+    error.status() == Some(429)
+}
+
+fn is_auth_error(error: &ResyAPIError) -> bool {
+    matches!(error.status(), Some(401) | Some(419))
+}
+
+async fn load_credentials(provider: &dyn CredentialProvider) -> ResyResult<Credentials> {
+    provider.credentials().await
+        .map_err(|e| ResyClientError::InvalidInput(format!("Failed to load credentials: {:#}", e)))
+}
+
+/// `ResyClient::with_reauth` for futures that can't borrow the client.
+async fn send_with_reauth<T, F, Fut>(api_gateway: &ResyAPIGateway, provider: Option<&dyn CredentialProvider>, mut request: F) -> Result<T, ResyAPIError>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, ResyAPIError>>,
+{
+    match (request().await, provider) {
+        (Err(e), Some(provider)) if is_auth_error(&e) => {
+            warn!("Credentials rejected ({}), reloading them and retrying", e);
+            let reloaded = load_credentials(provider).await.and_then(|credentials| {
+                api_gateway.set_auth(credentials.api_key, credentials.auth_token)
+                    .map_err(|e| ResyClientError::InvalidInput(format!("Reloaded credentials refused: {}", e)))
+            });
+            match reloaded {
+                Ok(()) => request().await,
+                Err(reload) => {
+                    warn!("{}", reload);
+                    Err(e)
+                }
+            }
+        }
+        (result, _) => result,
+    }
+}
+
+/// Resy rejected the request itself, as opposed to auth, rate limiting or a failed request.
+fn is_change_refused(error: &ResyAPIError) -> bool {
+    error.status().is_some_and(|status| (400..500).contains(&status)) && !is_auth_error(error) && !is_rate_limited(error)
+}
+
+fn already_booked(error: &ResyAPIError) -> ResyClientError {
+    let resy_token = match error {
+        ResyAPIError::Status { body: Some(body), .. } => body.reservation.clone(),
+        _ => None,
+    };
+    warn!("Not booking, the account already has a conflicting reservation ({})", error);
+    ResyClientError::AlreadyBooked { resy_token }
+}
+
+fn build_gateway(config: &Config) -> ResyAPIGateway {
+    let mut api_gateway = ResyAPIGateway::from_auth(config.api_key.clone(), config.auth_token.clone());
+    api_gateway.set_base_url(config.api_base_url.clone());
+    api_gateway.set_device_id(config.device_id.as_deref());
+    api_gateway.set_send_correlation_id(config.send_correlation_id);
+    api_gateway.set_max_response_bytes(config.max_response_bytes);
+    if let Some(pool_max_idle_per_host) = config.pool_max_idle_per_host {
+        api_gateway.set_pool_max_idle_per_host(pool_max_idle_per_host);
+    }
+    api_gateway.set_http1_only(config.http1_only);
+    api_gateway.set_extra_headers(parse_extra_headers(&config.extra_headers), config.override_auth_headers);
+    api_gateway
+}
+
+/// Pulls the venue slug out of a pasted Resy url, e.g. `carbone` from
+/// `https://resy.com/cities/ny/venues/carbone?date=2024-06-01`.
+///
+/// Returns `None` unless the segment after `venues/` is a non-empty run of
+/// ascii letters, digits, `-` or `_`. The slug is lowercased.
+pub fn extract_venue_slug(url: &str) -> Option<String> {
+    let start = url.find("venues/")? + "venues/".len();
+    let rest = &url[start..];
+    let end = rest.find(['?', '#', '/']).unwrap_or(rest.len());
+    let slug = rest[..end].trim();
+
+    if slug.is_empty() || !slug.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+        return None;
+    }
+    Some(slug.to_ascii_lowercase())
+}
+
+/// Another Resy user the account can book on behalf of.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LinkedAccount {
+    pub id: String,
+    pub first_name: String,
+    pub last_name: String,
+}
+
+fn format_linked_accounts(user_data: &Value) -> Vec<LinkedAccount> {
+    user_data["linked_accounts"].as_array()
+        .map(|accounts| accounts.iter().filter_map(|account| {
+            let id = match &account["id"] {
+                Value::Number(id) => id.to_string(),
+                Value::String(id) => id.clone(),
+                _ => return None,
+            };
+            Some(LinkedAccount {
+                id,
+                first_name: account["first_name"].as_str().unwrap_or_default().to_string(),
+                last_name: account["last_name"].as_str().unwrap_or_default().to_string(),
+            })
+        }).collect())
+        .unwrap_or_default()
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResyVenue {
+    pub id: u64,
+    pub name: String,
+    pub url_slug: String,
+    pub time_zone: Option<String>,
+    pub coordinates: Option<Coordinates>,
+    /// Daily service windows, empty when the venue doesn't list them
+    pub service_hours: Vec<ServiceHours>,
+}
+
+/// A daily service window in the venue's time zone, e.g. dinner from 17:00 to 23:00.
+/// A `close` before `open` runs past midnight.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ServiceHours {
+    pub open: NaiveTime,
+    pub close: NaiveTime,
+}
+
+impl ServiceHours {
+    /// Whether a slot starting at `time` falls in this window.
+    pub fn contains(&self, time: NaiveTime) -> bool {
+        if self.open <= self.close {
+            self.open <= time && time < self.close
+        } else {
+            self.open <= time || time < self.close
+        }
+    }
+}
+
+fn format_service_hours(json: &Value) -> Vec<ServiceHours> {
+    let parse = |time: &Value| {
+        let time = time.as_str()?;
+        NaiveTime::parse_from_str(time, "%H:%M:%S").or_else(|_| NaiveTime::parse_from_str(time, "%H:%M")).ok()
+    };
+
+    json.as_array().map(|hours| hours.iter().filter_map(|hours| {
+        Some(ServiceHours { open: parse(&hours["open"])?, close: parse(&hours["close"])? })
+    }).collect()).unwrap_or_default()
+}
+
+fn format_venue(json: &Value) -> ResyResult<ResyVenue> {
+    let id = json["id"]["resy"].as_u64()
+        .ok_or_else(|| ResyClientError::NotFound("Venue ID not found".to_string()))?;
+
+    Ok(ResyVenue {
+        id,
+        name: json["name"].as_str().unwrap_or_default().to_string(),
+        url_slug: json["url_slug"].as_str().unwrap_or_default().to_string(),
+        time_zone: json["location"]["time_zone"].as_str().map(String::from),
+        coordinates: parse_coordinates(&json["location"]),
+        service_hours: format_service_hours(&json["service_hours"]),
+    })
+}
+
+fn parse_coordinates(location: &Value) -> Option<Coordinates> {
+    Some(Coordinates {
+        latitude: location["latitude"].as_f64()?,
+        longitude: location["longitude"].as_f64()?,
+    })
+}
+
+/// Parsed `get_reservation_details` response.
+#[derive(Debug)]
+pub struct ReservationDetails {
+    pub book_token: BookToken,
+    pub add_ons: Vec<AddOn>,
+    /// All-in cost of the reservation before add-ons, when Resy quotes one
+    pub total: Option<Money>,
+    /// Currency of `total` and of the add-on prices
+    pub currency: String,
+    /// False for open reservations (payment type `free`), which are booked without a payment method
+    pub payment_required: bool,
+    /// Payment type `deposit`, or a deposit fee is quoted
+    pub deposit_required: bool,
+    /// The venue lets the slot be held with `lock_slot` before booking
+    pub lock_supported: bool,
+    /// Terms for cancelling the reservation, when Resy states them
+    pub cancellation: Option<CancellationPolicy>,
+}
+
+impl ReservationDetails {
+    fn from_json(json: &Value) -> Option<Self> {
+        let currency = currency_of(&json["payment"]);
+        Some(ReservationDetails {
+            book_token: BookToken::from_json(&json["book_token"])?,
+            add_ons: format_add_ons(&json["add_ons"]),
+            total: json["payment"]["amounts"]["total"].as_f64().map(|total| Money::new(total, currency)),
+            currency: currency.to_string(),
+            payment_required: json["payment"]["config"]["type"].as_str() != Some("free"),
+            deposit_required: json["payment"]["config"]["type"].as_str() == Some("deposit")
+                || json["payment"]["amounts"]["deposit_fee"].as_f64().is_some_and(|fee| fee > 0.0),
+            lock_supported: json["lock"]["supported"].as_bool() == Some(true),
+            cancellation: CancellationPolicy::from_json(&json["cancellation"], currency),
+        })
+    }
+
+    /// Add-on matching `preferred` by id or (case-insensitive) name.
+    pub fn find_add_on(&self, preferred: &str) -> Option<&AddOn> {
+        self.add_ons.iter().find(|add_on| add_on.id == preferred || add_on.name.eq_ignore_ascii_case(preferred))
+    }
+}
+
+/// Terms for cancelling a reservation, from the `cancellation` object of the details response.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CancellationPolicy {
+    /// Cancelling up to this many hours before the reservation is free
+    pub cutoff_hours: Option<u32>,
+    /// Charged for cancelling past the cutoff (or at any time, without one)
+    pub penalty: Option<Money>,
+    /// Resy's wording of the policy
+    pub description: Option<String>,
+}
+
+impl CancellationPolicy {
+    /// `None` unless `json` is an object. A zero fee is no penalty.
+    fn from_json(json: &Value, currency: &str) -> Option<Self> {
+        json.as_object()?;
+        let description = json["display"]["policy"].as_array()
+            .map(|lines| lines.iter().filter_map(Value::as_str).collect::<Vec<_>>().join(" "))
+            .filter(|description| !description.is_empty());
+
+        Some(CancellationPolicy {
+            cutoff_hours: json["fee"]["cut_off_hours"].as_u64().and_then(|hours| u32::try_from(hours).ok()),
+            penalty: json["fee"]["amount"].as_f64().filter(|amount| *amount > 0.0).map(|amount| Money::new(amount, currency)),
+            description,
+        })
+    }
+
+    /// Whether cancelling never costs anything.
+    pub fn is_free(&self) -> bool {
+        self.penalty.is_none()
+    }
+}
+
+impl std::fmt::Display for CancellationPolicy {
+    /// e.g. `free cancellation until 24h before, then $25.00`
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match (&self.penalty, self.cutoff_hours) {
+            (None, _) => write!(f, "free cancellation"),
+            (Some(penalty), Some(hours)) => write!(f, "free cancellation until {}h before, then {}", hours, penalty),
+            (Some(penalty), None) => write!(f, "{} to cancel", penalty),
+        }
+    }
+}
+
+/// Optional extra (e.g. prix-fixe menu) offered with a reservation.
+#[derive(Debug, Clone)]
+pub struct AddOn {
+    pub id: String,
+    pub name: String,
+    pub price: Option<f64>,
+}
+
+fn format_add_ons(json: &Value) -> Vec<AddOn> {
+    json.as_array().map(|add_ons| add_ons.iter().filter_map(|add_on| {
+        Some(AddOn {
+            id: json_id(&add_on["id"])?,
+            name: add_on["name"].as_str().unwrap_or_default().to_string(),
+            price: add_on["price"].as_f64(),
+        })
+    }).collect()).unwrap_or_default()
+}
+
+/// An id Resy sends either as a number or a string.
+fn json_id(id: &Value) -> Option<String> {
+    match id {
+        Value::Number(id) => Some(id.to_string()),
+        id => id.as_str().map(String::from),
+    }
+}
+
+/// Token minted by `get_reservation_details`, valid for a limited window.
+#[derive(Debug)]
+pub struct BookToken {
+    pub value: String,
+    pub date_expires: Option<DateTime<Utc>>,
+}
+
+impl BookToken {
+    fn from_json(json: &Value) -> Option<Self> {
+        let value = json["value"].as_str()?.to_string();
+        let date_expires = json["date_expires"].as_str().and_then(|date| {
+            NaiveDateTime::parse_from_str(date, "%Y-%m-%d %H:%M:%S")
+                .map(|naive| naive.and_utc())
+                .or_else(|_| DateTime::parse_from_rfc3339(date).map(|dt| dt.with_timezone(&Utc)))
+                .ok()
+        });
+
+        Some(BookToken { value, date_expires })
+    }
+
+    /// Remaining validity of the token (zero once expired), `None` if Resy sent no expiry.
+    pub fn time_remaining(&self) -> Option<Duration> {
+        self.date_expires.map(|expires| (expires - Utc::now()).max(Duration::zero()))
+    }
+}
+
+/// Asks the user to confirm the booking, counting down the token validity.
+/// Returns `false` if the user declines or the token expires first.
+async fn confirm_booking(book_token: &BookToken, time_slot: &str) -> bool {
+    let (tx, mut rx) = oneshot::channel();
+
+    // plain thread so a pending read doesn't block shutdown
+    thread::spawn(move || {
+        let mut input = String::new();
+        let _ = io::stdin().read_line(&mut input);
+        let _ = tx.send(input);
+    });
+
+    println!(">> Book {}? [y/N]", time_slot);
+
+    loop {
+        match book_token.time_remaining() {
+            Some(remaining) if remaining <= Duration::zero() => {
+                println!("\nBook token expired, aborting");
+                return false;
+            }
+            Some(remaining) => {
+                print!("\rtoken expires in {}s ", remaining.num_seconds());
+                // only the countdown is lost if stdout is gone (e.g. a closed pipe)
+                if let Err(e) = io::stdout().flush() {
+                    debug!("Failed to flush stdout: {}", e);
+                }
+            }
+            None => {}
+        }
+
+        tokio::select! {
+            input = &mut rx => {
+                let confirmed = input.map(|s| s.trim().eq_ignore_ascii_case("y")).unwrap_or(false);
+                return confirmed && book_token.time_remaining() != Some(Duration::zero());
+            }
+            _ = sleep(TokioDuration::from_secs(1)) => {}
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HealthCheck {
+    pub name: String,
+    pub passed: bool,
+    pub detail: String,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct HealthReport {
+    pub checks: Vec<HealthCheck>,
+    /// Round trip of the user request, if one was made
+    pub latency: Option<StdDuration>,
+}
+
+impl HealthReport {
+    fn add(&mut self, name: &str, passed: bool, detail: &str) {
+        self.checks.push(HealthCheck {
+            name: name.to_string(),
+            passed,
+            detail: detail.to_string(),
+        });
+    }
+
+    pub fn is_healthy(&self) -> bool {
+        self.checks.iter().all(|check| check.passed)
+    }
+}
+
+/// Dry inspection of a snipe, see `ResyClient::plan`.
+#[derive(Debug, Clone, Serialize)]
+pub struct SnipePlan {
+    pub venue_id: String,
+    pub venue_name: String,
+    pub date: String,
+    pub party_size: u8,
+    /// `None` when the release time is invalid
+    pub release_at: Option<DateTime<Local>>,
+    pub preferences: SlotPreferences,
+    pub preferred_add_on: Option<String>,
+    /// Only sent for venues that require a payment method
+    pub payment_id: Option<String>,
+    pub max_total: Option<f64>,
+    pub allow_deposits: bool,
+    pub on_found: FoundAction,
+}
+
+impl std::fmt::Display for SnipePlan {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        writeln!(f, "Venue:       {} ({})", self.venue_name, self.venue_id)?;
+        writeln!(f, "Reservation: {}, party of {}", self.date, self.party_size)?;
+        match self.release_at {
+            Some(release_at) => writeln!(f, "Fires at:    {}", release_at.format("%Y-%m-%d %H:%M:%S %Z"))?,
+            None => writeln!(f, "Fires at:    never (invalid release time)")?,
+        }
+
+        let preferences = &self.preferences;
+        let mut order = vec![match &preferences.target_time {
+            _ if !preferences.criteria.is_empty() => preferences.criteria.iter().enumerate()
+                .map(|(i, criterion)| format!("{}. {}", i + 1, criterion))
+                .collect::<Vec<_>>()
+                .join(", "),
+            Some(target_time) => format!("closest to {}", target_time),
+            None => "as listed by Resy".to_string(),
+        }];
+        if preferences.prefer_fewer_fees {
+            order.push("fewer fees on ties".to_string());
+        }
+        writeln!(f, "Order:       {}", order.join(", "))?;
+
+        let mut seating = Vec::new();
+        if !preferences.allowed_types.is_empty() {
+            seating.push(format!("only {}", preferences.allowed_types.join(", ")));
+        }
+        if !preferences.blocked_types.is_empty() {
+            seating.push(format!("never {}", preferences.blocked_types.join(", ")));
+        }
+        writeln!(f, "Seating:     {}", if seating.is_empty() { "any".to_string() } else { seating.join("; ") })?;
+        writeln!(f, "Add-on:      {}", self.preferred_add_on.as_deref().unwrap_or("none"))?;
+
+        match &self.payment_id {
+            Some(payment_id) => writeln!(f, "Payment:     method {} (when the venue requires one)", payment_id)?,
+            None => writeln!(f, "Payment:     none set (only open reservations can be booked)")?,
+        }
+        let mut limits = vec![if self.allow_deposits { "deposits allowed" } else { "no deposits" }.to_string()];
+        if let Some(max_total) = self.max_total {
+            limits.insert(0, format!("max total {:.2}", max_total));
+        }
+        writeln!(f, "Limits:      {}", limits.join(", "))?;
+        write!(f, "On found:    {}", match self.on_found {
+            FoundAction::Book => "book the best slot",
+            FoundAction::NotifyOnly => "notify and keep watching",
+        })
+    }
+}
+
+/// Slot found by `ResyClient::find_anything`, with where and for whom it was found.
+#[derive(Debug, Clone)]
+pub struct SlotMatch {
+    pub venue_id: String,
+    pub day: Day,
+    pub party_size: u8,
+    pub slot: ResySlot,
+}
+
+/// Outcome of a successful booking.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BookingResult {
+    pub resy_token: String,
+    pub venue_id: String,
+    pub date: String,
+    pub party_size: u8,
+    pub slot: ResySlot,
+    /// Terms for cancelling, when Resy stated them at booking
+    #[serde(default)]
+    pub cancellation: Option<CancellationPolicy>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ResySlot {
+    pub id: String,
+    pub token: String,
+    pub slot_type: String,
+    pub start: String,
+    pub end: String,
+    pub min_size: u64,
+    pub max_size: u64,
+    pub quantity: u64,
+    /// Up-front charges, in the venue's currency
+    #[serde(default)]
+    pub fees: Money,
+    /// Extra tokens from find that details needs for this slot
+    #[serde(default)]
+    pub tokens: SlotTokens,
+}
+
+impl std::fmt::Display for ResySlot {
+    /// e.g. `7:30 PM · Dining Room · party of 2 · $25.00`. Resy reports slot
+    /// times in the venue's own zone, so they are shown as-is.
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{} · {} · {}", display_time(self), self.slot_type, display_party(self.min_size, self.max_size))?;
+        if !self.fees.is_zero() {
+            write!(f, " · {}", self.fees)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for BookingResult {
+    /// e.g. `2024-06-01 7:30 PM · Dining Room · party of 2 · free cancellation`
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{} {} · {} · party of {}", self.date, display_time(&self.slot), self.slot.slot_type, self.party_size)?;
+        if !self.slot.fees.is_zero() {
+            write!(f, " · {}", self.slot.fees)?;
+        }
+        if let Some(cancellation) = &self.cancellation {
+            write!(f, " · {}", cancellation)?;
+        }
+        Ok(())
+    }
+}
+
+fn display_time(slot: &ResySlot) -> String {
+    slot_start_time(slot)
+        .map(|time| time.format("%-I:%M %p").to_string())
+        .unwrap_or_else(|| slot.start.clone())
+}
+
+fn display_party(min_size: u64, max_size: u64) -> String {
+    if min_size == max_size {
+        format!("party of {}", min_size)
+    } else {
+        format!("party of {}-{}", min_size, max_size)
+    }
+}
+
+/// Up-front charges (deposit and service charge) attached to a slot.
+fn slot_fees(payment: &Value, currency: &str) -> Money {
+    let amount = ["deposit_fee", "service_charge"].iter()
+        .filter_map(|fee| payment[fee].as_f64())
+        .sum();
+    Money::new(amount, currency)
+}
+
+fn format_slots(json: Value) -> Vec<ResySlot> {
+    let currency = currency_of(&json["results"]["venues"][0]["venue"]);
+    if let Some(slots) = json["results"]["venues"][0]["slots"].as_array() {
+        let summarized: Vec<ResySlot> = slots.iter().filter_map(|slot| {
+
+            let config = slot["config"].as_object()?;
+            let date = slot["date"].as_object()?;
+            let size = slot["size"].as_object()?;
+
+            Some(ResySlot {
+                id: config.get("id")?.as_number()?.to_string(),
+                token: config.get("token")?.as_str()?.to_string(),
+                slot_type: config.get("type")?.as_str()?.to_string(),
+                start: date.get("start")?.as_str()?.to_string(),
+                end: date.get("end")?.as_str()?.to_string(),
+                min_size: size.get("min")?.as_u64()?,
+                max_size: size.get("max")?.as_u64()?,
+                quantity: slot.get("quantity")?.as_u64()?,
+                fees: slot_fees(&slot["payment"], currency),
+                tokens: SlotTokens {
+                    template_id: json_id(&slot["template"]["id"]),
+                    lock_id: json_id(&slot["lock"]["id"]),
+                },
+            })
+        }).collect();
+
+        summarized
+    } else {
+        Vec::new()
+    }
+}
+
+/// Distinct seating types among `slots`, sorted.
+pub fn seating_types(slots: &[ResySlot]) -> Vec<String> {
+    let mut types: Vec<String> = slots.iter().map(|slot| slot.slot_type.clone()).collect();
+    types.sort();
+    types.dedup();
+    types
+}
+
+type SelectFn = dyn Fn(&[ResySlot]) -> Option<&ResySlot> + Send + Sync;
+
+/// Custom slot choice, given every open slot and returning the one to book, if any.
+#[derive(Clone)]
+pub struct SlotSelector(Arc<SelectFn>);
+
+impl SlotSelector {
+    pub fn new<F>(select: F) -> Self
+    where
+        F: Fn(&[ResySlot]) -> Option<&ResySlot> + Send + Sync + 'static,
+    {
+        SlotSelector(Arc::new(select))
+    }
+
+    pub fn select<'a>(&self, slots: &'a [ResySlot]) -> Option<&'a ResySlot> {
+        (self.0)(slots)
+    }
+}
+
+impl std::fmt::Debug for SlotSelector {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str("SlotSelector(..)")
+    }
+}
+
+/// Ordering applied to open slots before booking.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SlotPreferences {
+    /// Preferred start time (HHMM), closest slots first
+    pub target_time: Option<String>,
+    /// Among equally close slots, prefer the one with the lowest fees
+    pub prefer_fewer_fees: bool,
+    /// Seating types to keep (case-insensitive), empty keeps all
+    pub allowed_types: Vec<String>,
+    /// Seating types to drop (case-insensitive), applied after the allowlist
+    pub blocked_types: Vec<String>,
+    /// Acceptable time and seating combinations, most preferred first. When set, these
+    /// take the place of `target_time` and slots matching none of them are dropped.
+    pub criteria: Vec<SlotCriterion>,
+    /// Slots starting sooner than this many minutes from now are dropped, 0 keeps all
+    pub min_lead_mins: u64,
+    /// Venue's time zone (e.g. `America/New_York`), which slot start times are in.
+    /// `min_lead_mins` counts from the machine's local time without one.
+    pub time_zone: Option<String>,
+    /// Overrides everything above: the slot it picks is the only one booked
+    #[serde(skip)]
+    pub select_fn: Option<SlotSelector>,
+}
+
+impl SlotPreferences {
+    pub fn from_config(config: &Config) -> Self {
+        SlotPreferences {
+            target_time: config.target_time.clone(),
+            prefer_fewer_fees: config.prefer_fewer_fees,
+            allowed_types: config.allowed_types.clone(),
+            blocked_types: config.blocked_types.clone(),
+            criteria: config.criteria.clone(),
+            min_lead_mins: config.min_lead_mins,
+            time_zone: None,
+            select_fn: None,
+        }
+    }
+
+    pub fn allows(&self, slot: &ResySlot) -> bool {
+        let matches = |types: &[String]| types.iter().any(|t| t.eq_ignore_ascii_case(&slot.slot_type));
+        (self.allowed_types.is_empty() || matches(&self.allowed_types)) && !matches(&self.blocked_types) && !self.too_soon(slot)
+    }
+
+    /// Whether `slot` starts sooner than `min_lead_mins` from now. Unparseable start
+    /// times never do.
+    pub fn too_soon(&self, slot: &ResySlot) -> bool {
+        if self.min_lead_mins == 0 {
+            return false;
+        }
+        let Ok(start) = NaiveDateTime::parse_from_str(&slot.start, "%Y-%m-%d %H:%M:%S") else {
+            return false;
+        };
+        start < self.venue_now() + Duration::minutes(self.min_lead_mins as i64)
+    }
+
+    /// Current wall-clock time in `time_zone`, or the local one when it's unset or unknown.
+    fn venue_now(&self) -> NaiveDateTime {
+        match self.time_zone.as_deref().map(str::parse::<Tz>) {
+            Some(Ok(tz)) => Utc::now().with_timezone(&tz).naive_local(),
+            Some(Err(_)) => {
+                warn!("Unknown time zone {:?}, using local time", self.time_zone);
+                Local::now().naive_local()
+            }
+            None => Local::now().naive_local(),
+        }
+    }
+}
+
+/// Slots to try booking, best first. With a `select_fn`, just the slot it picks.
+pub fn rank_slots(slots: Vec<ResySlot>, preferences: &SlotPreferences) -> Vec<ResySlot> {
+    if let Some(selector) = &preferences.select_fn {
+        return selector.select(&slots).cloned().into_iter().collect();
+    }
+
+    let slots = dedup_slots(slots.into_iter().filter(|slot| preferences.allows(slot)).collect());
+    if !preferences.criteria.is_empty() {
+        return rank_slots_by_criteria(slots, preferences);
+    }
+
+    let mut slots = match &preferences.target_time {
+        Some(target_time) => sort_slots_by_closest_time(slots, target_time),
+        None => slots,
+    };
+
+    if preferences.prefer_fewer_fees {
+        let target_time = preferences.target_time.as_deref()
+            .and_then(|time| NaiveTime::parse_from_str(time, "%H%M").ok());
+
+        // stable sort, so only slots equally close to the target are reordered
+        slots.sort_by(|a, b| {
+            minutes_from_target(a, target_time).cmp(&minutes_from_target(b, target_time))
+                .then(a.fees.amount.total_cmp(&b.fees.amount))
+        });
+    }
+
+    slots
+}
+
+/// The slot to book: the `select_fn`'s pick when set, else one satisfying the earliest
+/// criterion any slot satisfies, see `rank_slots`.
+pub fn select_slot(slots: Vec<ResySlot>, preferences: &SlotPreferences) -> Option<ResySlot> {
+    rank_slots(slots, preferences).into_iter().next()
+}
+
+/// Why `explain_selection` chose its slot.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum SelectionReason {
+    /// Picked by the `select_fn`
+    Custom,
+    /// Satisfies `criterion`, the `priority`th (0 is most preferred), `minutes_off` from its time
+    Criterion { priority: usize, criterion: SlotCriterion, minutes_off: u64 },
+    /// Closest slot to the target time
+    ClosestToTarget { target_time: String, minutes_off: u64 },
+    /// First open slot, without a time preference
+    FirstListed,
+    /// No slot was acceptable
+    NoneAcceptable,
+}
+
+impl std::fmt::Display for SelectionReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            SelectionReason::Custom => write!(f, "picked by the custom selector"),
+            SelectionReason::Criterion { priority, criterion, minutes_off } => {
+                write!(f, "matches preference {} ({}), {} min off", priority + 1, criterion, minutes_off)
+            }
+            SelectionReason::ClosestToTarget { target_time, minutes_off } => {
+                write!(f, "closest to {} ({} min off)", target_time, minutes_off)
+            }
+            SelectionReason::FirstListed => write!(f, "first open slot, no time preference"),
+            SelectionReason::NoneAcceptable => write!(f, "no acceptable slot"),
+        }
+    }
+}
+
+/// Why `explain_selection` passed over a slot.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", content = "detail", rename_all = "snake_case")]
+pub enum Rejection {
+    /// Seating type not in `allowed_types`
+    SeatingNotAllowed(String),
+    /// Seating type in `blocked_types`
+    SeatingBlocked(String),
+    /// Starts sooner than `min_lead_mins` from now, in the venue's time zone
+    TooSoon,
+    /// Satisfies none of the criteria
+    OutsideCriteria,
+    /// Same start time and seating as a slot kept instead, see `dedup_slots`
+    Duplicate,
+    /// Acceptable, but ranked below the chosen slot
+    Outranked,
+    /// Not picked by the `select_fn`
+    NotPicked,
+}
+
+impl std::fmt::Display for Rejection {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Rejection::SeatingNotAllowed(seating) => write!(f, "{} seating not allowed", seating),
+            Rejection::SeatingBlocked(seating) => write!(f, "{} seating blocked", seating),
+            Rejection::TooSoon => write!(f, "starts too soon"),
+            Rejection::OutsideCriteria => write!(f, "matches no preference"),
+            Rejection::Duplicate => write!(f, "duplicate of a cheaper or roomier slot"),
+            Rejection::Outranked => write!(f, "ranked lower"),
+            Rejection::NotPicked => write!(f, "not picked by the custom selector"),
+        }
+    }
+}
+
+/// The slot `select_slot` would book, why, and why every other slot wasn't chosen.
+#[derive(Debug, Clone, Serialize)]
+pub struct SlotSelection {
+    /// `None` when every slot was rejected
+    pub slot: Option<ResySlot>,
+    pub reason: SelectionReason,
+    /// In the order the slots were given
+    pub rejected: Vec<(ResySlot, Rejection)>,
+}
+
+impl std::fmt::Display for SlotSelection {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match &self.slot {
+            Some(slot) => write!(f, "Would book {}: {}", slot, self.reason)?,
+            None => write!(f, "Would book nothing: {}", self.reason)?,
+        }
+        for (slot, rejection) in &self.rejected {
+            write!(f, "\n  skipped {}: {}", slot, rejection)?;
+        }
+        Ok(())
+    }
+}
+
+/// `select_slot` with the reasoning behind it.
+pub fn explain_selection(slots: Vec<ResySlot>, preferences: &SlotPreferences) -> SlotSelection {
+    let ranked = rank_slots(slots.clone(), preferences);
+    let slot = ranked.first().cloned();
+
+    let reason = match &slot {
+        None => SelectionReason::NoneAcceptable,
+        Some(_) if preferences.select_fn.is_some() => SelectionReason::Custom,
+        Some(slot) if !preferences.criteria.is_empty() => preferences.criteria.iter().enumerate()
+            .find_map(|(priority, criterion)| {
+                let minutes_off = criterion_distance(criterion, slot)?;
+                Some(SelectionReason::Criterion { priority, criterion: criterion.clone(), minutes_off })
+            })
+            .unwrap_or(SelectionReason::FirstListed),
+        Some(slot) => match &preferences.target_time {
+            Some(target_time) => SelectionReason::ClosestToTarget {
+                target_time: target_time.clone(),
+                minutes_off: minutes_from_target(slot, NaiveTime::parse_from_str(target_time, "%H%M").ok()),
+            },
+            None => SelectionReason::FirstListed,
+        },
+    };
+
+    let chosen = slot.as_ref().map(|slot| slot.token.clone());
+    let kept: HashSet<String> = if preferences.select_fn.is_some() {
+        HashSet::new()
+    } else {
+        dedup_slots(slots.iter().filter(|slot| preferences.allows(slot)).cloned().collect())
+            .into_iter().map(|slot| slot.token).collect()
+    };
+    let matches_type = |types: &[String], slot: &ResySlot| types.iter().any(|t| t.eq_ignore_ascii_case(&slot.slot_type));
+
+    let rejected = slots.into_iter()
+        .filter(|slot| chosen.as_ref() != Some(&slot.token))
+        .map(|slot| {
+            let rejection = if preferences.select_fn.is_some() {
+                Rejection::NotPicked
+            } else if !preferences.allowed_types.is_empty() && !matches_type(&preferences.allowed_types, &slot) {
+                Rejection::SeatingNotAllowed(slot.slot_type.clone())
+            } else if matches_type(&preferences.blocked_types, &slot) {
+                Rejection::SeatingBlocked(slot.slot_type.clone())
+            } else if preferences.too_soon(&slot) {
+                Rejection::TooSoon
+            } else if !kept.contains(&slot.token) {
+                Rejection::Duplicate
+            } else if ranked.iter().all(|ranked| ranked.token != slot.token) {
+                Rejection::OutsideCriteria
+            } else {
+                Rejection::Outranked
+            };
+            (slot, rejection)
+        })
+        .collect();
+
+    SlotSelection { slot, reason, rejected }
+}
+
+/// Groups slots by the first criterion they satisfy, in criterion order, each group
+/// closest to its criterion's time first.
+fn rank_slots_by_criteria(slots: Vec<ResySlot>, preferences: &SlotPreferences) -> Vec<ResySlot> {
+    let mut ranked: Vec<(usize, u64, ResySlot)> = slots.into_iter().filter_map(|slot| {
+        let (priority, distance) = preferences.criteria.iter().enumerate()
+            .find_map(|(priority, criterion)| criterion_distance(criterion, &slot).map(|distance| (priority, distance)))?;
+        Some((priority, distance, slot))
+    }).collect();
+
+    ranked.sort_by(|(a_priority, a_distance, a), (b_priority, b_distance, b)| {
+        let fees = if preferences.prefer_fewer_fees { a.fees.amount.total_cmp(&b.fees.amount) } else { Ordering::Equal };
+        a_priority.cmp(b_priority).then(a_distance.cmp(b_distance)).then(fees)
+    });
+
+    ranked.into_iter().map(|(_, _, slot)| slot).collect()
+}
+
+/// Minutes between `slot` and the criterion's time (0 without one), or `None` if the
+/// slot doesn't satisfy `criterion`.
+fn criterion_distance(criterion: &SlotCriterion, slot: &ResySlot) -> Option<u64> {
+    if criterion.seating.as_ref().is_some_and(|seating| !seating.eq_ignore_ascii_case(&slot.slot_type)) {
+        return None;
+    }
+
+    match &criterion.time {
+        Some(time) => {
+            let time = NaiveTime::parse_from_str(time, "%H%M").ok()?;
+            let distance = minutes_from_target(slot, Some(time));
+            (slot_start_time(slot).is_some() && distance <= u64::from(criterion.within_mins)).then_some(distance)
+        }
+        None => Some(0),
+    }
+}
+
+/// Collapses slots sharing a start time and seating type (`slot_type`), which show up
+/// when find results are merged under different tokens or party sizes. The variant with
+/// the lowest fees, then the highest quantity, is kept in the position first seen.
+pub fn dedup_slots(slots: Vec<ResySlot>) -> Vec<ResySlot> {
+    let mut deduped: Vec<ResySlot> = Vec::with_capacity(slots.len());
+    let mut positions: HashMap<(String, String), usize> = HashMap::new();
+
+    for slot in slots {
+        let key = (slot.start.clone(), slot.slot_type.clone());
+        match positions.get(&key) {
+            Some(&position) => {
+                let kept = &deduped[position];
+                if slot.fees.amount < kept.fees.amount || (slot.fees.amount == kept.fees.amount && slot.quantity > kept.quantity) {
+                    deduped[position] = slot;
+                }
+            }
+            None => {
+                positions.insert(key, deduped.len());
+                deduped.push(slot);
+            }
+        }
+    }
+
+    deduped
+}
+
+fn slot_start_time(slot: &ResySlot) -> Option<NaiveTime> {
+    NaiveTime::parse_from_str(slot.start.get(11..16)?, "%H:%M").ok()
+}
+
+fn minutes_from_target(slot: &ResySlot, target_time: Option<NaiveTime>) -> u64 {
+    match (target_time, slot_start_time(slot)) {
+        (Some(target_time), Some(time)) => time.signed_duration_since(target_time).num_minutes().unsigned_abs(),
+        _ => 0,
     }
 }
 
@@ -448,7 +2275,7 @@ fn sort_slots_by_closest_time(slots: Vec<ResySlot>, target_time: &str) -> Vec<Re
     };
 
     let mut slots_with_time: Vec<(ResySlot, NaiveTime)> = slots.into_iter().filter_map(|slot| {
-        NaiveTime::parse_from_str(&slot.start[11..16], "%H:%M").ok().map(|time| (slot, time))
+        slot_start_time(&slot).map(|time| (slot, time))
     }).collect();
 
     slots_with_time.sort_by_key(|(_, time)| {
@@ -457,7 +2284,7 @@ fn sort_slots_by_closest_time(slots: Vec<ResySlot>, target_time: &str) -> Vec<Re
         } else {
             target_time.signed_duration_since(*time)
         };
-        duration.num_minutes().abs() as u64 // Abs to avoid panic on negative durations
+        duration.num_minutes().unsigned_abs() // Abs to avoid panic on negative durations
     });
 
     slots_with_time.into_iter().map(|(slot, _)| slot).collect()
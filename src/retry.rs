@@ -0,0 +1,106 @@
+use std::future::Future;
+use std::time::{Duration, Instant};
+use chrono::{DateTime, Local};
+use log::warn;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use tokio::time::sleep;
+use crate::resy_api_gateway::ResyAPIError;
+
+/// How failed requests are retried, shared by single requests (`send_with_retry`) and
+/// the slot polling loops.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(default)]
+pub struct RetryPolicy {
+    /// Tries in total, counting the first
+    pub max_attempts: u32,
+    /// Wait after the first failure, doubled after each further one
+    pub base_ms: u64,
+    /// Longest wait between tries
+    pub max_ms: u64,
+    /// Fraction (0 to 1) of each wait that is randomly cut, so clients failing together
+    /// don't retry together
+    pub jitter: f64,
+    /// Time budget from the first try, no retry starts past it
+    pub deadline_secs: Option<u64>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 10,
+            base_ms: 500,
+            max_ms: 5_000,
+            jitter: 0.0,
+            deadline_secs: None,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Tight budget for calls racing other diners once slots are out: a few quick
+    /// retries, none outlasting the second it takes the slot to be gone.
+    pub fn tight() -> Self {
+        RetryPolicy {
+            max_attempts: 3,
+            base_ms: 50,
+            max_ms: 200,
+            jitter: 0.0,
+            deadline_secs: Some(1),
+        }
+    }
+
+    /// Wait before retry number `retry` (1 for the first retry), without jitter.
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let doublings = retry.saturating_sub(1).min(32);
+        let wait = self.base_ms.saturating_mul(1 << doublings).min(self.max_ms);
+        Duration::from_millis(wait)
+    }
+
+    /// `backoff` with jitter applied, never longer than `backoff`.
+    pub fn delay(&self, retry: u32) -> Duration {
+        let backoff = self.backoff(retry);
+        let jitter = self.jitter.clamp(0.0, 1.0);
+        if jitter == 0.0 {
+            return backoff;
+        }
+        backoff.mul_f64(1.0 - rand::thread_rng().gen_range(0.0..=jitter))
+    }
+
+    /// Whether another try may follow `attempts` tries, the first made at `started`,
+    /// after waiting `wait`.
+    pub fn allows(&self, attempts: u32, started: Instant, wait: Duration) -> bool {
+        attempts < self.max_attempts
+            && self.deadline_secs.is_none_or(|secs| started.elapsed() + wait <= Duration::from_secs(secs))
+    }
+}
+
+/// Sends `request` until it succeeds, fails with an error that isn't retryable, `policy`
+/// runs out, or the next wait would run past `deadline`. Waits follow the error's delay
+/// hint, else the policy's backoff.
+pub async fn send_with_retry<T, F, Fut>(policy: &RetryPolicy, deadline: Option<DateTime<Local>>, mut request: F) -> Result<T, ResyAPIError>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, ResyAPIError>>,
+{
+    let started = Instant::now();
+    let mut attempts = 1;
+
+    loop {
+        match request().await {
+            Err(e) if e.is_retryable() => {
+                let wait = e.retry_delay_hint().unwrap_or_else(|| policy.delay(attempts));
+                let past_deadline = deadline.is_some_and(|deadline| {
+                    chrono::Duration::from_std(wait).map_or(true, |wait| Local::now() + wait >= deadline)
+                });
+                if past_deadline || !policy.allows(attempts, started, wait) {
+                    return Err(e);
+                }
+                warn!("Request failed ({}), retry {} in {:?}", e, attempts, wait);
+                sleep(wait).await;
+                attempts += 1;
+            }
+            result => return result,
+        }
+    }
+}
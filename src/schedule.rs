@@ -0,0 +1,104 @@
+use chrono::{DateTime, Datelike, Duration, LocalResult, NaiveDate, NaiveDateTime, NaiveTime, Offset, TimeZone, Utc, Weekday};
+use serde::{Deserialize, Serialize};
+use chrono_tz::Tz;
+use crate::day::Day;
+
+/// Longest stretch of wall-clock time a zone's clocks are searched back over for the
+/// offset before a gap, well past any gap on record.
+const MAX_GAP_MINS: i64 = 48 * 60;
+
+/// When a venue releases reservations, as it describes it: "`days_out` days ahead,
+/// at `local_time` in the venue's time zone".
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SnipeSchedule {
+    pub days_out: u32,
+    pub local_time: NaiveTime,
+    pub tz: Tz,
+}
+
+/// A concrete release: the instant to snipe at and the reservation day it opens.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScheduledDrop {
+    pub release_at: DateTime<Tz>,
+    pub day: Day,
+}
+
+impl SnipeSchedule {
+    pub fn relative(days_out: u32, local_time: NaiveTime, tz: Tz) -> Self {
+        SnipeSchedule { days_out, local_time, tz }
+    }
+
+    /// The next release from now.
+    pub fn next(&self) -> ScheduledDrop {
+        self.next_after(Utc::now())
+    }
+
+    /// The first release at or after `now`.
+    pub fn next_after(&self, now: DateTime<Utc>) -> ScheduledDrop {
+        let today = now.with_timezone(&self.tz).date_naive();
+        let release_at = self.release_on(today);
+
+        if release_at >= now {
+            self.drop_on(today, release_at)
+        } else {
+            let tomorrow = today + Duration::days(1);
+            self.drop_on(tomorrow, self.release_on(tomorrow))
+        }
+    }
+
+    fn drop_on(&self, release_date: NaiveDate, release_at: DateTime<Tz>) -> ScheduledDrop {
+        ScheduledDrop {
+            release_at,
+            day: Day(release_date + Duration::days(self.days_out as i64)),
+        }
+    }
+
+    /// `local_time` on `date`. A time repeated when clocks fall back resolves to its first
+    /// occurrence, one skipped when they spring forward moves later by the length of the
+    /// gap (an hour for most zones, two for Antarctica/Troll, a day for Samoa in 2011).
+    fn release_on(&self, date: NaiveDate) -> DateTime<Tz> {
+        let local = date.and_time(self.local_time);
+        match self.tz.from_local_datetime(&local) {
+            LocalResult::Single(release_at) => release_at,
+            LocalResult::Ambiguous(earliest, _) => earliest,
+            LocalResult::None => {
+                // read the skipped time with the offset in force before the clocks jumped
+                let offset = (1..=MAX_GAP_MINS)
+                    .find_map(|mins| self.tz.from_local_datetime(&(local - Duration::minutes(mins))).latest())
+                    .map_or(0, |before| before.offset().fix().local_minus_utc());
+                self.tz.from_utc_datetime(&(local - Duration::seconds(offset as i64)))
+            }
+        }
+    }
+}
+
+/// How a daemon target repeats after a failed snipe, e.g. `recurrence = "daily"` or
+/// `recurrence = { weekly = "Sat" }`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Recurrence {
+    /// Every day, at the same time
+    Daily,
+    /// Every week on this day, at the same time
+    Weekly(Weekday),
+}
+
+impl Recurrence {
+    /// The first occurrence later than both `last` and `now`, at `last`'s time of day.
+    pub fn next_after(&self, last: NaiveDateTime, now: NaiveDateTime) -> NaiveDateTime {
+        let (next, step) = match self {
+            Recurrence::Daily => (last + Duration::days(1), 1),
+            Recurrence::Weekly(weekday) => {
+                let ahead = (7 + weekday.num_days_from_monday() - last.weekday().num_days_from_monday()) % 7;
+                let ahead = if ahead == 0 { 7 } else { ahead };
+                (last + Duration::days(ahead as i64), 7)
+            }
+        };
+
+        if next > now {
+            return next;
+        }
+        let behind = (now - next).num_days() / step + 1;
+        next + Duration::days(behind * step)
+    }
+}
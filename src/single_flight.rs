@@ -0,0 +1,95 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::panic;
+use std::sync::{Arc, Mutex};
+use futures::future::{join_all, BoxFuture, FutureExt, Shared};
+use crate::correlation;
+
+/// Coalesces concurrent calls sharing a key into one in-flight call whose result every
+/// caller gets. Calls made after it completes start a new one. Clones share in-flight calls.
+/// Calls run as their own tasks, so they finish even if every caller stops waiting.
+#[derive(Clone)]
+pub struct SingleFlight<V> {
+    in_flight: Arc<Mutex<HashMap<String, Shared<BoxFuture<'static, V>>>>>,
+}
+
+impl<V: Clone + Send + Sync + 'static> SingleFlight<V> {
+    pub fn new() -> Self {
+        SingleFlight {
+            in_flight: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Joins the call in flight for `key`, or spawns `call` if there is none. Must be
+    /// called within a tokio runtime.
+    pub async fn run<F>(&self, key: String, call: F) -> V
+    where
+        F: Future<Output = V> + Send + 'static,
+    {
+        let shared = {
+            let mut in_flight = self.in_flight.lock().unwrap();
+            match in_flight.get(&key) {
+                Some(shared) => shared.clone(),
+                None => {
+                    let forget = Forget { entries: self.in_flight.clone(), key: key.clone() };
+                    // task locals don't cross into the task, so the starter's correlation id is carried over
+                    let correlation_id = correlation::current();
+                    // spawned, so the call always runs to its end and drops `forget`, even
+                    // once every caller gave up on it; a later call then starts afresh
+                    let handle = tokio::spawn(async move {
+                        let _forget = forget;
+                        match correlation_id {
+                            Some(id) => correlation::scope(id, call).await,
+                            None => call.await,
+                        }
+                    });
+                    let shared = async move {
+                        match handle.await {
+                            Ok(value) => value,
+                            Err(e) if e.is_panic() => panic::resume_unwind(e.into_panic()),
+                            Err(e) => panic!("single flight call was cancelled: {}", e),
+                        }
+                    }.boxed().shared();
+                    in_flight.insert(key, shared.clone());
+                    shared
+                }
+            }
+        };
+
+        shared.await
+    }
+
+    /// Waits for every call in flight right now to complete.
+    pub async fn wait_idle(&self) {
+        let in_flight: Vec<_> = self.in_flight.lock().unwrap().values().cloned().collect();
+        join_all(in_flight).await;
+    }
+}
+
+/// Removes a call's entry once it is done, whether it completed or panicked.
+struct Forget<V> {
+    entries: Arc<Mutex<HashMap<String, Shared<BoxFuture<'static, V>>>>>,
+    key: String,
+}
+
+impl<V> Drop for Forget<V> {
+    fn drop(&mut self) {
+        if let Ok(mut entries) = self.entries.lock() {
+            entries.remove(&self.key);
+        }
+    }
+}
+
+impl<V: Clone + Send + Sync + 'static> Default for SingleFlight<V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<V> std::fmt::Debug for SingleFlight<V> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("SingleFlight")
+            .field("in_flight", &self.in_flight.lock().unwrap().len())
+            .finish()
+    }
+}
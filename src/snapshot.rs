@@ -0,0 +1,42 @@
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use crate::resy_client::ResySlot;
+
+/// Open slots of one venue, day and party size at one moment.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AvailabilitySnapshot {
+    pub taken_at: DateTime<Local>,
+    pub venue_id: String,
+    pub date: String,
+    pub party_size: u8,
+    pub slots: Vec<ResySlot>,
+}
+
+/// Appends `snapshot` as one JSON line to `path`, creating the file if needed.
+pub fn append_snapshot(path: &Path, snapshot: &AvailabilitySnapshot) -> Result<()> {
+    let mut line = serde_json::to_string(snapshot).context("Failed to serialize snapshot")?;
+    line.push('\n');
+
+    // one write per line, so snapshots appended concurrently don't interleave
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .and_then(|mut file| file.write_all(line.as_bytes()))
+        .context("Failed to write snapshot file")
+}
+
+/// Every snapshot in `path`, oldest first. Blank lines are skipped.
+pub fn load_snapshots(path: &Path) -> Result<Vec<AvailabilitySnapshot>> {
+    let content = fs::read_to_string(path).context("Failed to read snapshot file")?;
+
+    content.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| serde_json::from_str(line).with_context(|| format!("Failed to parse snapshot on line {}", i + 1)))
+        .collect()
+}
@@ -0,0 +1,209 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::PathBuf;
+use anyhow::{Context, Result};
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use crate::config::SnipeTarget;
+use crate::resy_client::BookingResult;
+
+/// Persistent record of bookings and snipe attempts, shared by the CLI and the daemon.
+pub trait Store {
+    fn record(&mut self, result: &BookingResult) -> Result<()>;
+
+    fn record_attempt(&mut self, attempt: SnipeAttempt) -> Result<()>;
+
+    /// Every booking recorded, cancelled ones included.
+    fn history(&self) -> Result<Vec<BookingResult>>;
+
+    /// Marks the booking `resy_token` as cancelled. It stays in `history`.
+    fn record_cancellation(&mut self, resy_token: &str) -> Result<()>;
+
+    /// Resy tokens of the bookings marked cancelled.
+    fn cancelled(&self) -> Result<HashSet<String>>;
+
+    /// Bookings in `history` that weren't cancelled.
+    fn active_bookings(&self) -> Result<Vec<BookingResult>> {
+        let cancelled = self.cancelled()?;
+        Ok(self.history()?.into_iter().filter(|booking| !cancelled.contains(&booking.resy_token)).collect())
+    }
+
+    fn attempts(&self) -> Result<Vec<SnipeAttempt>>;
+
+    /// Attempt counts per venue id, kept up to date by `record_attempt`.
+    fn venue_stats(&self) -> Result<HashMap<String, VenueStats>>;
+
+    /// Share of attempts at `venue_id` that booked, `None` if it was never attempted.
+    fn success_rate(&self, venue_id: &str) -> Result<Option<f64>> {
+        Ok(self.venue_stats()?.get(venue_id).and_then(VenueStats::success_rate))
+    }
+
+    /// Next occurrence of each recurring target whose last snipe failed, keyed by the
+    /// configured target it repeats.
+    fn rescheduled(&self) -> Result<HashMap<String, SnipeTarget>>;
+
+    /// Sets the next occurrence of the recurring target `key`, or clears it with `None`.
+    fn reschedule(&mut self, key: &str, next: Option<SnipeTarget>) -> Result<()>;
+
+    /// Whether a reservation is still held at the venue on `date`.
+    fn is_booked(&self, venue_id: &str, date: &str) -> Result<bool> {
+        Ok(self.active_bookings()?.iter().any(|booking| booking.venue_id == venue_id && booking.date == date))
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SnipeAttempt {
+    pub venue_id: String,
+    pub date: String,
+    pub party_size: u8,
+    pub attempted_at: String,
+    pub error: Option<String>,
+}
+
+impl SnipeAttempt {
+    pub fn new(venue_id: &str, date: &str, party_size: u8, error: Option<String>) -> Self {
+        SnipeAttempt {
+            venue_id: venue_id.to_string(),
+            date: date.to_string(),
+            party_size,
+            attempted_at: Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+            error,
+        }
+    }
+}
+
+/// How often sniping a venue paid off.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+pub struct VenueStats {
+    pub attempts: u64,
+    /// Attempts that ended in a booking
+    pub successes: u64,
+}
+
+impl VenueStats {
+    pub fn add(&mut self, attempt: &SnipeAttempt) {
+        self.attempts += 1;
+        if attempt.error.is_none() {
+            self.successes += 1;
+        }
+    }
+
+    /// `None` before the first attempt.
+    pub fn success_rate(&self) -> Option<f64> {
+        (self.attempts > 0).then(|| self.successes as f64 / self.attempts as f64)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct StoreContents {
+    #[serde(default)]
+    bookings: Vec<BookingResult>,
+
+    #[serde(default)]
+    attempts: Vec<SnipeAttempt>,
+
+    /// Absent from stores written before stats were kept, then rebuilt from `attempts`
+    venue_stats: Option<HashMap<String, VenueStats>>,
+
+    #[serde(default)]
+    rescheduled: HashMap<String, SnipeTarget>,
+
+    /// Resy tokens of cancelled bookings
+    #[serde(default)]
+    cancelled: HashSet<String>,
+}
+
+impl StoreContents {
+    fn venue_stats(&mut self) -> &mut HashMap<String, VenueStats> {
+        let attempts = &self.attempts;
+        self.venue_stats.get_or_insert_with(|| {
+            let mut stats: HashMap<String, VenueStats> = HashMap::new();
+            for attempt in attempts {
+                stats.entry(attempt.venue_id.clone()).or_default().add(attempt);
+            }
+            stats
+        })
+    }
+}
+
+/// Store backed by a single JSON file.
+#[derive(Debug)]
+pub struct JsonFileStore {
+    path: PathBuf,
+}
+
+impl JsonFileStore {
+    pub fn new(path: PathBuf) -> Self {
+        JsonFileStore { path }
+    }
+
+    fn load(&self) -> Result<StoreContents> {
+        if !self.path.exists() {
+            return Ok(StoreContents::default());
+        }
+
+        let content = fs::read_to_string(&self.path).context("Failed to read store file")?;
+        serde_json::from_str(&content).context("Failed to deserialize store")
+    }
+
+    fn save(&self, contents: &StoreContents) -> Result<()> {
+        let content = serde_json::to_string_pretty(contents).context("Failed to serialize store")?;
+        fs::write(&self.path, content).context("Failed to write store file")
+    }
+}
+
+impl Store for JsonFileStore {
+    fn record(&mut self, result: &BookingResult) -> Result<()> {
+        let mut contents = self.load()?;
+        contents.bookings.push(result.clone());
+        self.save(&contents)
+    }
+
+    fn record_attempt(&mut self, attempt: SnipeAttempt) -> Result<()> {
+        let mut contents = self.load()?;
+        contents.venue_stats().entry(attempt.venue_id.clone()).or_default().add(&attempt);
+        contents.attempts.push(attempt);
+        self.save(&contents)
+    }
+
+    fn history(&self) -> Result<Vec<BookingResult>> {
+        Ok(self.load()?.bookings)
+    }
+
+    fn record_cancellation(&mut self, resy_token: &str) -> Result<()> {
+        let mut contents = self.load()?;
+        contents.cancelled.insert(resy_token.to_string());
+        self.save(&contents)
+    }
+
+    fn cancelled(&self) -> Result<HashSet<String>> {
+        Ok(self.load()?.cancelled)
+    }
+
+    fn attempts(&self) -> Result<Vec<SnipeAttempt>> {
+        Ok(self.load()?.attempts)
+    }
+
+    fn venue_stats(&self) -> Result<HashMap<String, VenueStats>> {
+        Ok(self.load()?.venue_stats().clone())
+    }
+
+    fn rescheduled(&self) -> Result<HashMap<String, SnipeTarget>> {
+        Ok(self.load()?.rescheduled)
+    }
+
+    fn reschedule(&mut self, key: &str, next: Option<SnipeTarget>) -> Result<()> {
+        let mut contents = self.load()?;
+        match next {
+            Some(next) => contents.rescheduled.insert(key.to_string(), next),
+            None => contents.rescheduled.remove(key),
+        };
+        self.save(&contents)
+    }
+}
+
+pub fn get_store_path() -> Result<PathBuf> {
+    dirs::home_dir()
+        .map(|path| path.join(".marksman.history.json"))
+        .context("Could not find home directory")
+}
@@ -1,7 +1,5 @@
 // view_utils.rs
-use prettytable::{row, Table};
-use prettytable::row::Row;
-use prettytable::cell::Cell;
+use prettytable::{row, Cell, Row, Table};
 use crate::resy_client::ResySlot;
 
 pub fn print_table(slots: &[ResySlot]) {
@@ -23,3 +21,35 @@ pub fn print_table(slots: &[ResySlot]) {
 
     table.printstd();
 }
+
+/// Availability view of `slots`, one row per slot ordered by start time.
+pub fn slots_table(slots: &[ResySlot]) -> Table {
+    let mut sorted: Vec<&ResySlot> = slots.iter().collect();
+    sorted.sort_by(|a, b| a.start.cmp(&b.start));
+
+    let mut table = Table::new();
+    table.add_row(row!["Time", "Seating", "Party Size Range", "Price"]);
+
+    for slot in sorted {
+        let time = slot.start.get(11..16).unwrap_or(&slot.start);
+        let party_size = if slot.min_size == slot.max_size {
+            slot.min_size.to_string()
+        } else {
+            format!("{}-{}", slot.min_size, slot.max_size)
+        };
+        let price = if slot.fees.is_zero() { "-".to_string() } else { slot.fees.to_string() };
+
+        table.add_row(Row::new(vec![
+            Cell::new(time),
+            Cell::new(&slot.slot_type),
+            Cell::new(&party_size),
+            Cell::new(&price),
+        ]));
+    }
+
+    table
+}
+
+pub fn print_slots_table(slots: &[ResySlot]) {
+    slots_table(slots).printstd();
+}
@@ -0,0 +1,55 @@
+use marksman::calendar::{diff_calendars, format_calendar, CalendarChange, CalendarDay, CalendarStatus};
+
+fn day(date: &str, status: CalendarStatus) -> CalendarDay {
+    CalendarDay { date: date.to_string(), status }
+}
+
+#[test]
+fn format_calendar_parses_inventory() {
+    let json = serde_json::from_str(include_str!("fixtures/calendar.json")).unwrap();
+    let calendar = format_calendar(json);
+
+    assert_eq!(calendar, vec![
+        day("2024-06-01", CalendarStatus::SoldOut),
+        day("2024-06-02", CalendarStatus::Available),
+        day("2024-06-03", CalendarStatus::Closed),
+    ]);
+}
+
+#[test]
+fn diff_reports_newly_available_days() {
+    let old = vec![day("2024-06-01", CalendarStatus::SoldOut)];
+    let new = vec![
+        day("2024-06-01", CalendarStatus::Available),
+        day("2024-06-02", CalendarStatus::Available),
+    ];
+
+    assert_eq!(diff_calendars(&old, &new), vec![
+        CalendarChange::Opened("2024-06-01".to_string()),
+        CalendarChange::Opened("2024-06-02".to_string()),
+    ]);
+}
+
+#[test]
+fn diff_reports_closed_and_removed_days() {
+    let old = vec![
+        day("2024-06-01", CalendarStatus::Available),
+        day("2024-06-02", CalendarStatus::Available),
+    ];
+    let new = vec![day("2024-06-01", CalendarStatus::SoldOut)];
+
+    assert_eq!(diff_calendars(&old, &new), vec![
+        CalendarChange::Closed("2024-06-01".to_string()),
+        CalendarChange::Closed("2024-06-02".to_string()),
+    ]);
+}
+
+#[test]
+fn diff_ignores_unchanged_days() {
+    let old = vec![
+        day("2024-06-01", CalendarStatus::Available),
+        day("2024-06-02", CalendarStatus::SoldOut),
+    ];
+
+    assert!(diff_calendars(&old, &old.clone()).is_empty());
+}
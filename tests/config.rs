@@ -0,0 +1,106 @@
+use marksman::batch::BatchMode;
+use chrono::{Local, TimeZone, Utc, Weekday};
+use marksman::config::{parse_release_at, Config, SnipeTarget};
+use marksman::schedule::Recurrence;
+
+fn incomplete_config() -> Config {
+    Config {
+        venue_id: "1505".to_string(),
+        party_size: 0,
+        ..Config::default()
+    }
+}
+
+#[test]
+fn problems_collect_all_lists_every_problem() {
+    let problems = incomplete_config().problems(BatchMode::CollectAll);
+
+    assert_eq!(problems, vec![
+        "api_key is missing",
+        "auth_token is missing",
+        "party_size must be at least 1",
+    ]);
+}
+
+#[test]
+fn problems_fail_fast_stops_at_first_problem() {
+    let config = incomplete_config();
+
+    assert_eq!(config.problems(BatchMode::FailFast), vec!["api_key is missing"]);
+    assert!(!config.validate());
+}
+
+fn target(release_at: Option<&str>) -> SnipeTarget {
+    toml::from_str::<SnipeTarget>(r#"
+        venue_id = "1505"
+        date = "2024-06-01"
+        snipe_date = "2024-05-18"
+        snipe_time = "0900"
+    "#).map(|target| SnipeTarget { release_at: release_at.map(str::to_string), ..target }).unwrap()
+}
+
+#[test]
+fn explicit_release_at_overrides_snipe_date_and_time() {
+    let expected = Utc.with_ymd_and_hms(2024, 5, 17, 14, 30, 0).unwrap();
+
+    assert_eq!(target(Some("2024-05-17 10:30 America/New_York")).release_at().unwrap(), expected);
+    assert_eq!(target(Some("2024-05-17T10:30:00-04:00")).release_at().unwrap(), expected);
+    assert_eq!(target(None).release_at(), Local.with_ymd_and_hms(2024, 5, 18, 9, 0, 0).single());
+}
+
+#[test]
+fn release_at_can_stand_in_for_snipe_date_and_time() {
+    let target: SnipeTarget = toml::from_str(r#"
+        venue_id = "1505"
+        date = "2024-06-01"
+        release_at = "2024-05-17 10:30 Europe/London"
+    "#).unwrap();
+
+    assert_eq!(target.release_at().unwrap(), Utc.with_ymd_and_hms(2024, 5, 17, 9, 30, 0).unwrap());
+}
+
+#[test]
+fn invalid_release_at_is_rejected() {
+    assert!(target(Some("2024-05-17 10:30 Mars/Olympus")).release_at().is_none());
+
+    let unknown_zone = parse_release_at("2024-05-17 10:30 Mars/Olympus").unwrap_err();
+    assert_eq!(unknown_zone.to_string(), "Unknown time zone Mars/Olympus in release_at");
+    assert!(parse_release_at("2024-05-17 10am America/New_York").is_err());
+    assert!(parse_release_at("2024-05-17 10:30").is_err());
+    // skipped when clocks spring forward
+    assert!(parse_release_at("2024-03-10 02:30 America/New_York").is_err());
+}
+
+#[test]
+fn next_occurrence_shifts_release_and_reservation_together() {
+    let target: SnipeTarget = toml::from_str(r#"
+        venue_id = "1505"
+        date = "2024-06-01"
+        snipe_date = "2024-05-18"
+        snipe_time = "0900"
+        recurrence = { weekly = "Sat" }
+    "#).unwrap();
+    assert_eq!(target.recurrence, Some(Recurrence::Weekly(Weekday::Sat)));
+
+    let now = Local.with_ymd_and_hms(2024, 5, 18, 9, 5, 0).unwrap();
+    let next = target.next_occurrence(now).unwrap();
+
+    assert_eq!(next.snipe_date, "2024-05-25");
+    assert_eq!(next.snipe_time, "0900");
+    assert_eq!(next.date, "2024-06-08");
+    assert_eq!(next.recurrence, target.recurrence);
+}
+
+#[test]
+fn next_occurrence_needs_a_recurrence() {
+    assert!(target(None).next_occurrence(Local::now()).is_none());
+}
+
+#[test]
+fn default_config_round_trips_through_toml() {
+    let written = toml::to_string(&Config::default()).unwrap();
+    let read: Config = toml::from_str(&written).unwrap();
+
+    assert_eq!(read.pool_max_idle_per_host, None);
+    assert_eq!(toml::to_string(&read).unwrap(), written);
+}
@@ -0,0 +1,203 @@
+use std::env;
+use std::fs;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use futures::future::BoxFuture;
+use marksman::config::Config;
+use marksman::credentials::{CredentialProvider, Credentials, FileCredentials};
+use marksman::resy_client::{ResyClient, ResyClientError};
+use wiremock::matchers::{header, method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+/// Hands out `token-1`, `token-2`, ... on successive calls.
+#[derive(Debug, Default)]
+struct RotatingProvider {
+    calls: AtomicUsize,
+}
+
+impl CredentialProvider for RotatingProvider {
+    fn credentials(&self) -> BoxFuture<'_, anyhow::Result<Credentials>> {
+        Box::pin(async move {
+            let call = self.calls.fetch_add(1, Ordering::SeqCst) + 1;
+            Ok(Credentials {
+                api_key: "api-key".to_string(),
+                auth_token: format!("token-{}", call),
+            })
+        })
+    }
+}
+
+async fn user_server() -> MockServer {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/2/user"))
+        .and(header("x-resy-auth-token", "token-2"))
+        .respond_with(ResponseTemplate::new(200).set_body_raw("{}", "application/json"))
+        .with_priority(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/2/user"))
+        .respond_with(ResponseTemplate::new(419))
+        .mount(&server)
+        .await;
+    server
+}
+
+fn client_for(server: &MockServer) -> ResyClient {
+    ResyClient::from_config(Config {
+        api_base_url: server.uri(),
+        ..Config::default()
+    })
+}
+
+#[tokio::test]
+async fn provider_credentials_are_loaded_when_set() {
+    let server = user_server().await;
+    let mut client = client_for(&server);
+
+    client.set_credential_provider(Arc::new(RotatingProvider::default())).await.unwrap();
+
+    assert_eq!(client.config.auth_token, "token-1");
+}
+
+#[tokio::test]
+async fn rejected_keepalive_refreshes_from_provider() {
+    let server = user_server().await;
+    let provider = Arc::new(RotatingProvider::default());
+    let mut client = client_for(&server);
+    client.set_credential_provider(provider.clone()).await.unwrap();
+
+    client.keepalive().await;
+    client.keepalive().await;
+
+    assert_eq!(client.config.auth_token, "token-2");
+    assert_eq!(provider.calls.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn ensure_valid_reports_whether_credentials_were_reloaded() {
+    let server = user_server().await;
+    let provider = Arc::new(RotatingProvider::default());
+    let mut client = client_for(&server);
+    client.set_credential_provider(provider.clone()).await.unwrap();
+
+    assert!(client.ensure_valid().await.unwrap());
+    assert!(!client.ensure_valid().await.unwrap());
+    assert_eq!(client.config.auth_token, "token-2");
+    assert_eq!(provider.calls.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn ensure_valid_fails_without_provider() {
+    let server = user_server().await;
+    let mut client = client_for(&server);
+
+    assert!(matches!(client.ensure_valid().await, Err(ResyClientError::ApiError(_))));
+}
+
+/// Answers finds sent with `token-2` and rejects the rest with a 419.
+async fn find_server() -> MockServer {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/4/find"))
+        .and(header("x-resy-auth-token", "token-2"))
+        .respond_with(ResponseTemplate::new(200).set_body_raw(include_str!("fixtures/find.json"), "application/json"))
+        .with_priority(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/4/find"))
+        .respond_with(ResponseTemplate::new(419))
+        .mount(&server)
+        .await;
+    server
+}
+
+async fn finds(server: &MockServer) -> usize {
+    server.received_requests().await.unwrap().iter().filter(|request| request.url.path() == "/4/find").count()
+}
+
+#[tokio::test]
+async fn rejected_request_is_retried_with_reloaded_credentials() {
+    let server = find_server().await;
+    let provider = Arc::new(RotatingProvider::default());
+    let mut client = client_for(&server);
+    client.set_credential_provider(provider.clone()).await.unwrap();
+
+    let slots = client.find(None, None).await.expect("the retry should succeed");
+
+    assert!(!slots.is_empty());
+    assert_eq!(finds(&server).await, 2);
+    assert_eq!(provider.calls.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn rejected_request_is_retried_only_once() {
+    let server = find_server().await;
+    let provider = Arc::new(RotatingProvider::default());
+    let mut client = client_for(&server);
+    client.set_credential_provider(provider.clone()).await.unwrap();
+    // token-3 onwards is rejected too
+    provider.calls.store(2, Ordering::SeqCst);
+
+    assert!(client.find(None, None).await.is_err());
+    assert_eq!(finds(&server).await, 2);
+    assert_eq!(provider.calls.load(Ordering::SeqCst), 3);
+}
+
+#[tokio::test]
+async fn rejected_request_is_not_retried_without_provider() {
+    let server = find_server().await;
+    let client = client_for(&server);
+
+    assert!(client.find(None, None).await.is_err());
+    assert_eq!(finds(&server).await, 1);
+}
+
+#[tokio::test]
+async fn file_provider_reads_toml() {
+    let path = env::temp_dir().join(format!("marksman-credentials-{}.toml", std::process::id()));
+    fs::write(&path, "api_key = \"key\"\nauth_token = \"token\"\n").unwrap();
+
+    let credentials = FileCredentials { path: path.clone() }.credentials().await.unwrap();
+
+    assert_eq!(credentials, Credentials { api_key: "key".to_string(), auth_token: "token".to_string() });
+    let _ = fs::remove_file(&path);
+}
+
+#[tokio::test]
+async fn file_provider_trims_values() {
+    let path = env::temp_dir().join(format!("marksman-credentials-trim-{}.toml", std::process::id()));
+    fs::write(&path, "api_key = \" key \"\nauth_token = \"token\\n\"\n").unwrap();
+
+    let credentials = FileCredentials { path: path.clone() }.credentials().await.unwrap();
+
+    assert_eq!(credentials, Credentials { api_key: "key".to_string(), auth_token: "token".to_string() });
+    let _ = fs::remove_file(&path);
+}
+
+/// Hands out an auth token no header can carry.
+#[derive(Debug)]
+struct InvalidProvider;
+
+impl CredentialProvider for InvalidProvider {
+    fn credentials(&self) -> BoxFuture<'_, anyhow::Result<Credentials>> {
+        Box::pin(async { Ok(Credentials { api_key: "api-key".to_string(), auth_token: "token\u{7}".to_string() }) })
+    }
+}
+
+#[tokio::test]
+async fn invalid_provider_credentials_are_refused() {
+    let server = find_server().await;
+    let mut client = client_for(&server);
+    client.update_auth("api-key".to_string(), "token-1".to_string()).unwrap();
+
+    let result = client.set_credential_provider(Arc::new(InvalidProvider)).await;
+
+    assert!(matches!(result, Err(ResyClientError::InvalidInput(_))));
+    assert_eq!(client.config.auth_token, "token-1");
+    // the reload on rejection is refused too, leaving the original error
+    assert!(client.find(None, None).await.is_err());
+    assert_eq!(finds(&server).await, 1);
+}
@@ -0,0 +1,23 @@
+use marksman::day::{DateRange, Day};
+
+#[test]
+fn day_parses_and_formats_api_string() {
+    let day: Day = "2024-06-01".parse().unwrap();
+
+    assert_eq!(day.to_api_string(), "2024-06-01");
+    assert_eq!(Day::try_from("2024-06-01").unwrap(), day);
+}
+
+#[test]
+fn day_rejects_invalid_dates() {
+    assert!(Day::try_from("06/01/2024").is_err());
+    assert!(Day::try_from("2024-02-30").is_err());
+}
+
+#[test]
+fn date_range_is_inclusive_across_months() {
+    let range = DateRange::new("2024-01-30".parse().unwrap(), "2024-02-02".parse().unwrap());
+    let days: Vec<String> = range.map(|day| day.to_api_string()).collect();
+
+    assert_eq!(days, vec!["2024-01-30", "2024-01-31", "2024-02-01", "2024-02-02"]);
+}
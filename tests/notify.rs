@@ -0,0 +1,47 @@
+use std::env;
+use std::fs;
+use marksman::config::Config;
+use marksman::notify::{notify, NotifyOn, SnipeEvent};
+use marksman::resy_client::ResyClientError;
+
+fn failed(error: &ResyClientError) -> SnipeEvent<'_> {
+    SnipeEvent::Failed { venue_id: "1505", date: "2024-06-01", error }
+}
+
+#[test]
+fn success_only_skips_failures() {
+    let error = ResyClientError::NotFound("sold out".to_string());
+
+    assert!(!NotifyOn::SuccessOnly.allows(&failed(&error)));
+    assert!(NotifyOn::Both.allows(&failed(&error)));
+}
+
+#[test]
+fn failure_runs_notify_command_with_error_details() {
+    let path = env::temp_dir().join(format!("marksman-notify-{}.txt", std::process::id()));
+    let _ = fs::remove_file(&path);
+    let config = Config {
+        notify_command: Some(format!(
+            "echo \"$MARKSMAN_EVENT $MARKSMAN_ERROR_KIND $MARKSMAN_VENUE_ID $MARKSMAN_DATE\" > {}",
+            path.display()
+        )),
+        ..Config::default()
+    };
+
+    let error = ResyClientError::RateLimited("slow down".to_string());
+    notify(&config, failed(&error)).unwrap();
+
+    assert_eq!(fs::read_to_string(&path).unwrap().trim(), "failed RateLimited 1505 2024-06-01");
+    let _ = fs::remove_file(&path);
+}
+
+#[test]
+fn failing_notify_command_is_an_error() {
+    let config = Config {
+        notify_command: Some("exit 3".to_string()),
+        ..Config::default()
+    };
+
+    let error = ResyClientError::ApiError("boom".to_string());
+    assert!(notify(&config, failed(&error)).is_err());
+}
@@ -0,0 +1,380 @@
+use std::error::Error;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use marksman::resy_api_gateway::{parse_extra_headers, ResyAPIError, ResyAPIGateway, ResyErrorBody};
+use reqwest::header::{HeaderMap, HeaderValue};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+const DETAILS_SLOT_TAKEN: &str = include_str!("fixtures/details_slot_taken.json");
+const BOOK_INVALID_PAYMENT: &str = include_str!("fixtures/book_invalid_payment.json");
+const UNAUTHORIZED: &str = include_str!("fixtures/unauthorized.json");
+const BOOK_ALREADY_BOOKED: &str = include_str!("fixtures/book_already_booked.json");
+
+fn gateway_for(base_url: String) -> ResyAPIGateway {
+    let mut gateway = ResyAPIGateway::from_auth("api-key".to_string(), "auth-token".to_string());
+    gateway.set_base_url(base_url);
+    gateway
+}
+
+#[tokio::test]
+async fn network_error_keeps_reqwest_source() {
+    // nothing listens on port 1, so the connect fails
+    let gateway = gateway_for("http://127.0.0.1:1".to_string());
+
+    let error = gateway.get_user().await.unwrap_err();
+
+    assert!(matches!(error, ResyAPIError::Network(_)));
+    let source = error.source().expect("network error should have a source");
+    assert!(source.downcast_ref::<reqwest::Error>().is_some());
+}
+
+#[tokio::test]
+async fn invalid_json_keeps_serde_source() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/2/user"))
+        .respond_with(ResponseTemplate::new(200).set_body_raw("<html>not json</html>", "text/html"))
+        .mount(&server)
+        .await;
+    let gateway = gateway_for(server.uri());
+
+    let error = gateway.get_user().await.unwrap_err();
+
+    assert!(matches!(error, ResyAPIError::Deserialize { ref snippet, .. } if snippet == "<html>not json</html>"));
+    let source = error.source().expect("deserialize error should have a source");
+    assert!(source.downcast_ref::<serde_json::Error>().is_some());
+}
+
+#[tokio::test]
+async fn status_error_exposes_status() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/2/user"))
+        .respond_with(ResponseTemplate::new(419))
+        .mount(&server)
+        .await;
+    let gateway = gateway_for(server.uri());
+
+    let error = gateway.get_user().await.unwrap_err();
+
+    assert_eq!(error.status(), Some(419));
+    assert!(error.source().is_none());
+}
+
+fn status(status: u16) -> ResyAPIError {
+    ResyAPIError::Status { status, retry_after: None, body: None }
+}
+
+#[tokio::test]
+async fn network_errors_are_retryable() {
+    let gateway = gateway_for("http://127.0.0.1:1".to_string());
+
+    let error = gateway.get_user().await.unwrap_err();
+
+    assert!(error.is_retryable());
+    assert_eq!(error.retry_delay_hint(), None);
+}
+
+#[test]
+fn rate_limits_and_server_errors_are_retryable() {
+    for code in [429, 500, 502, 503] {
+        assert!(status(code).is_retryable(), "{code}");
+    }
+}
+
+#[test]
+fn rejected_requests_are_not_retryable() {
+    // bad request, unauthorized, forbidden, missing, slot taken, stale auth
+    for code in [400, 401, 403, 404, 412, 419] {
+        assert!(!status(code).is_retryable(), "{code}");
+    }
+}
+
+fn deserialize_error(body: &str) -> ResyAPIError {
+    let source = serde_json::from_str::<serde_json::Value>(body).unwrap_err();
+    ResyAPIError::Deserialize { source, snippet: body.to_string() }
+}
+
+#[test]
+fn deserialize_errors_are_not_retryable() {
+    assert!(!deserialize_error("<html>not json</html>").is_retryable());
+}
+
+#[test]
+fn truncated_bodies_are_retryable() {
+    assert!(deserialize_error(r#"{"results": {"venues": [{"#).is_retryable());
+}
+
+#[tokio::test]
+async fn truncated_body_keeps_redacted_snippet() {
+    let server = MockServer::start().await;
+    let body = r#"{"api_key": "api-key", "token": "auth-token", "results": {"venues": ["#;
+    Mock::given(method("GET"))
+        .and(path("/2/user"))
+        .respond_with(ResponseTemplate::new(200).set_body_raw(body, "application/json"))
+        .mount(&server)
+        .await;
+    let gateway = gateway_for(server.uri());
+
+    let error = gateway.get_user().await.unwrap_err();
+
+    assert!(error.is_retryable());
+    match error {
+        ResyAPIError::Deserialize { snippet, .. } => {
+            assert_eq!(snippet, r#"{"api_key": "[redacted]", "token": "[redacted]", "results": {"venues": ["#);
+        }
+        other => panic!("expected Deserialize, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn long_bodies_are_cut_to_a_snippet() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/2/user"))
+        .respond_with(ResponseTemplate::new(200).set_body_raw("x".repeat(1000), "application/json"))
+        .mount(&server)
+        .await;
+
+    let error = gateway_for(server.uri()).get_user().await.unwrap_err();
+
+    assert!(matches!(error, ResyAPIError::Deserialize { ref snippet, .. } if snippet.len() == 200));
+}
+
+#[tokio::test]
+async fn rate_limit_carries_retry_after_hint() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/2/user"))
+        .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "7"))
+        .mount(&server)
+        .await;
+    let gateway = gateway_for(server.uri());
+
+    let error = gateway.get_user().await.unwrap_err();
+
+    assert!(error.is_retryable());
+    assert_eq!(error.retry_delay_hint(), Some(Duration::from_secs(7)));
+}
+
+#[tokio::test]
+async fn oversized_responses_are_rejected() {
+    let server = MockServer::start().await;
+    let body = format!("{{\"padding\": \"{}\"}}", "x".repeat(2048));
+    Mock::given(method("GET"))
+        .and(path("/2/user"))
+        .respond_with(ResponseTemplate::new(200).set_body_raw(body, "application/json"))
+        .mount(&server)
+        .await;
+    let mut gateway = gateway_for(server.uri());
+    gateway.set_max_response_bytes(1024);
+
+    let error = gateway.get_user().await.unwrap_err();
+
+    assert!(matches!(error, ResyAPIError::TooLarge { limit: 1024 }));
+    assert!(!error.is_retryable());
+}
+
+#[tokio::test]
+async fn responses_within_the_limit_are_parsed() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/2/user"))
+        .respond_with(ResponseTemplate::new(200).set_body_raw(r#"{"id": 1}"#, "application/json"))
+        .mount(&server)
+        .await;
+    let mut gateway = gateway_for(server.uri());
+    gateway.set_max_response_bytes(9);
+
+    assert_eq!(gateway.get_user().await.unwrap()["id"], 1);
+}
+
+/// Minimal keep-alive HTTP/1.1 server answering every request with a user after
+/// `delay`, counting the connections it accepts. Returns its url and the count.
+async fn counting_server(delay: Duration) -> (String, Arc<AtomicUsize>) {
+    const RESPONSE: &[u8] = b"HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: 9\r\n\r\n{\"id\": 1}";
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let connections = Arc::new(AtomicUsize::new(0));
+    let accepted = connections.clone();
+
+    tokio::spawn(async move {
+        while let Ok((mut socket, _)) = listener.accept().await {
+            accepted.fetch_add(1, Ordering::SeqCst);
+            tokio::spawn(async move {
+                let mut received = Vec::new();
+                let mut chunk = [0; 4096];
+                loop {
+                    match socket.read(&mut chunk).await {
+                        Ok(0) | Err(_) => return,
+                        Ok(n) => received.extend_from_slice(&chunk[..n]),
+                    }
+                    // GETs only, so a request ends with its headers
+                    while let Some(end) = received.windows(4).position(|window| window == b"\r\n\r\n") {
+                        received.drain(..end + 4);
+                        tokio::time::sleep(delay).await;
+                        if socket.write_all(RESPONSE).await.is_err() {
+                            return;
+                        }
+                    }
+                }
+            });
+        }
+    });
+
+    (url, connections)
+}
+
+/// Connections opened by two bursts of 8 parallel requests through `gateway`.
+async fn connections_for_two_bursts(gateway: &mut ResyAPIGateway) -> usize {
+    let (url, connections) = counting_server(Duration::from_millis(50)).await;
+    gateway.set_base_url(url);
+
+    for _ in 0..2 {
+        let results = futures::future::join_all((0..8).map(|_| gateway.get_user())).await;
+        assert!(results.iter().all(|result| result.is_ok()));
+    }
+    connections.load(Ordering::SeqCst)
+}
+
+#[tokio::test]
+async fn default_pool_reuses_every_connection_of_a_burst() {
+    let mut gateway = gateway_for(String::new());
+
+    assert_eq!(connections_for_two_bursts(&mut gateway).await, 8);
+}
+
+#[tokio::test]
+async fn capped_pool_reconnects_past_the_cap() {
+    let mut gateway = gateway_for(String::new());
+    gateway.set_pool_max_idle_per_host(2);
+
+    // 2 of the first burst's 8 connections are kept, the second burst opens 6 more
+    assert_eq!(connections_for_two_bursts(&mut gateway).await, 14);
+}
+
+#[tokio::test]
+async fn client_builds_with_each_protocol_setting() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/2/user"))
+        .respond_with(ResponseTemplate::new(200).set_body_raw(r#"{"id": 1}"#, "application/json"))
+        .mount(&server)
+        .await;
+    let mut gateway = gateway_for(server.uri());
+
+    for http1_only in [true, false] {
+        gateway.set_http1_only(http1_only);
+        assert!(gateway.get_user().await.is_ok(), "http1_only = {}", http1_only);
+    }
+}
+
+async fn user_error(response: ResponseTemplate) -> ResyAPIError {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/2/user"))
+        .respond_with(response)
+        .mount(&server)
+        .await;
+    gateway_for(server.uri()).get_user().await.unwrap_err()
+}
+
+#[tokio::test]
+async fn error_body_carries_resy_reason() {
+    let error = user_error(ResponseTemplate::new(412).set_body_raw(DETAILS_SLOT_TAKEN, "application/json")).await;
+
+    assert_eq!(error.resy_message(), Some("This reservation is no longer available"));
+    assert_eq!(error.to_string(), "API request failed: 412 (This reservation is no longer available)");
+}
+
+#[tokio::test]
+async fn error_body_keeps_numeric_and_string_codes() {
+    let error = user_error(ResponseTemplate::new(402).set_body_raw(BOOK_INVALID_PAYMENT, "application/json")).await;
+    assert!(matches!(error, ResyAPIError::Status { body: Some(ResyErrorBody { ref code, .. }), .. } if code.as_deref() == Some("4002")));
+    assert_eq!(error.resy_message(), Some("Invalid payment method"));
+
+    let error = user_error(ResponseTemplate::new(401).set_body_raw(UNAUTHORIZED, "application/json")).await;
+    assert!(matches!(error, ResyAPIError::Status { status: 401, body: Some(ResyErrorBody { ref code, .. }), .. } if code.as_deref() == Some("auth_token_invalid")));
+}
+
+#[tokio::test]
+async fn reservation_conflict_is_already_booked() {
+    let error = user_error(ResponseTemplate::new(412).set_body_raw(BOOK_ALREADY_BOOKED, "application/json")).await;
+    assert!(error.is_already_booked());
+    assert!(!error.is_retryable());
+    assert!(matches!(error, ResyAPIError::Status { body: Some(ResyErrorBody { ref reservation, .. }), .. } if reservation.as_deref() == Some("resy-token-existing")));
+
+    let error = user_error(ResponseTemplate::new(412).set_body_raw(DETAILS_SLOT_TAKEN, "application/json")).await;
+    assert!(!error.is_already_booked());
+}
+
+#[tokio::test]
+async fn unexpected_error_bodies_are_left_out() {
+    let error = user_error(ResponseTemplate::new(502).set_body_raw("<html>Bad Gateway</html>", "text/html")).await;
+    assert!(matches!(error, ResyAPIError::Status { status: 502, body: None, .. }));
+    assert_eq!(error.to_string(), "API request failed: 502");
+
+    let error = user_error(ResponseTemplate::new(400).set_body_raw(r#"{"status": 400, "specs": {}}"#, "application/json")).await;
+    assert!(error.resy_message().is_none());
+}
+
+#[tokio::test]
+async fn extra_headers_keep_every_value_and_replace_defaults() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/2/user"))
+        .respond_with(ResponseTemplate::new(200).set_body_raw("{}", "application/json"))
+        .mount(&server)
+        .await;
+    let mut extra_headers = HeaderMap::new();
+    extra_headers.append("x-experiment", HeaderValue::from_static("a"));
+    extra_headers.append("x-experiment", HeaderValue::from_static("b"));
+    extra_headers.append("cache-control", HeaderValue::from_static("max-age=0"));
+    let mut gateway = gateway_for(server.uri());
+    gateway.set_extra_headers(extra_headers, false);
+
+    gateway.get_user().await.unwrap();
+
+    let requests = server.received_requests().await.unwrap();
+    let values = |name: &str| -> Vec<String> {
+        requests[0].headers.get_all(name).iter().map(|value| value.to_str().unwrap().to_string()).collect()
+    };
+    assert_eq!(values("x-experiment"), vec!["a", "b"]);
+    assert_eq!(values("cache-control"), vec!["max-age=0"]);
+}
+
+#[test]
+fn parsed_extra_headers_keep_values_of_names_differing_in_case() {
+    let pairs = [("X-Experiment".to_string(), "a".to_string()), ("x-experiment".to_string(), "b".to_string())];
+
+    let headers = parse_extra_headers(pairs.iter().map(|(name, value)| (name, value)));
+
+    assert_eq!(headers.get_all("x-experiment").iter().count(), 2);
+}
+
+#[tokio::test]
+async fn unsendable_credentials_fail_the_request() {
+    let server = MockServer::start().await;
+    let mut gateway = ResyAPIGateway::from_auth("api-key".to_string(), "token\n".to_string());
+    gateway.set_base_url(server.uri());
+
+    let error = gateway.get_user().await.unwrap_err();
+
+    assert!(matches!(error, ResyAPIError::InvalidCredentials(_)));
+    assert!(!error.is_retryable());
+    assert!(server.received_requests().await.unwrap().is_empty());
+}
+
+#[test]
+fn unsendable_credentials_are_refused() {
+    let gateway = gateway_for("http://127.0.0.1:1".to_string());
+
+    assert!(matches!(gateway.set_auth("api-key".to_string(), "token\u{7}".to_string()), Err(ResyAPIError::InvalidCredentials(_))));
+    assert!(gateway.set_auth("api-key".to_string(), "token".to_string()).is_ok());
+}
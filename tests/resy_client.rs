@@ -0,0 +1,1803 @@
+use chrono::NaiveTime;
+use marksman::batch::BatchMode;
+use marksman::config::{Config, Coordinates, FoundAction, SlotCriterion, SnipeTarget};
+use marksman::correlation;
+use marksman::discover::DiscoverFilters;
+use marksman::day::Day;
+use marksman::money::Money;
+use marksman::retry::{send_with_retry, RetryPolicy};
+use marksman::snapshot::load_snapshots;
+use marksman::store::{JsonFileStore, Store};
+use marksman::resy_api_gateway::{ResyAPIGateway, SlotTokens};
+use marksman::resy_client::{BookingResult, ResyClient, ResyClientError, ResyResult, ResySlot, ServiceHours, SlotPreferences, SlotSelector};
+use futures::StreamExt;
+use std::time::Duration;
+use serde_json::json;
+use wiremock::matchers::{body_partial_json, body_string_contains, header, method, path, query_param};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+const VENUE: &str = include_str!("fixtures/venue.json");
+const VENUE_HOURS: &str = include_str!("fixtures/venue_hours.json");
+const FIND: &str = include_str!("fixtures/find.json");
+const FIND_GBP: &str = include_str!("fixtures/find_gbp.json");
+const FIND_SEATING_TYPES: &str = include_str!("fixtures/find_seating_types.json");
+const FIND_SOLD_OUT: &str = include_str!("fixtures/find_sold_out.json");
+const FIND_TEMPLATE: &str = include_str!("fixtures/find_template.json");
+const FIND_OUT_OF_HOURS: &str = include_str!("fixtures/find_out_of_hours.json");
+const DETAILS: &str = include_str!("fixtures/details.json");
+const DETAILS_ADD_ONS: &str = include_str!("fixtures/details_add_ons.json");
+const DETAILS_GBP: &str = include_str!("fixtures/details_gbp.json");
+const DETAILS_DEPOSIT: &str = include_str!("fixtures/details_deposit.json");
+const DETAILS_FREE: &str = include_str!("fixtures/details_free.json");
+const DETAILS_LOCK: &str = include_str!("fixtures/details_lock.json");
+const DETAILS_CANCEL_FEE: &str = include_str!("fixtures/details_cancel_fee.json");
+const DETAILS_CANCEL_FREE: &str = include_str!("fixtures/details_cancel_free.json");
+const LOCK: &str = include_str!("fixtures/lock.json");
+const DETAILS_PRICED: &str = include_str!("fixtures/details_priced.json");
+const DETAILS_SLOT_TAKEN: &str = include_str!("fixtures/details_slot_taken.json");
+const BOOK: &str = include_str!("fixtures/book.json");
+const BOOK_TOKEN_EXPIRED: &str = include_str!("fixtures/book_token_expired.json");
+const BOOK_ALREADY_BOOKED: &str = include_str!("fixtures/book_already_booked.json");
+const RATE_LIMITED: &str = include_str!("fixtures/rate_limited.json");
+const VENUE_SEARCH_PAGE1: &str = include_str!("fixtures/venue_search_page1.json");
+const VENUE_SEARCH_PAGE2: &str = include_str!("fixtures/venue_search_page2.json");
+
+fn json_response(status: u16, body: &str) -> ResponseTemplate {
+    ResponseTemplate::new(status).set_body_raw(body, "application/json")
+}
+
+fn client_for(server: &MockServer) -> ResyClient {
+    let config = Config {
+        api_key: "api-key".to_string(),
+        auth_token: "auth-token".to_string(),
+        venue_id: "1505".to_string(),
+        date: "2024-06-01".to_string(),
+        party_size: 2,
+        payment_id: "123456".to_string(),
+        api_base_url: server.uri(),
+        ..Config::default()
+    };
+
+    ResyClient::from_config(config)
+}
+
+async fn mount(server: &MockServer, http_method: &str, endpoint: &str, response: ResponseTemplate) {
+    Mock::given(method(http_method))
+        .and(path(endpoint))
+        .respond_with(response)
+        .mount(server)
+        .await;
+}
+
+#[tokio::test]
+async fn book_best_books_slot_closest_to_target() {
+    let server = MockServer::start().await;
+    mount(&server, "GET", "/4/find", json_response(200, FIND)).await;
+    Mock::given(method("POST"))
+        .and(path("/3/details"))
+        .and(body_partial_json(json!({ "commit": 1, "party_size": 2 })))
+        .respond_with(json_response(200, DETAILS))
+        .mount(&server)
+        .await;
+    mount(&server, "POST", "/3/book", json_response(201, BOOK)).await;
+
+    let mut client = client_for(&server);
+    client.config.target_time = Some("1930".to_string());
+
+    let booking = client.book_best(false).await.expect("booking should succeed");
+    assert_eq!(booking.resy_token, "resy-token-abc");
+    assert_eq!(booking.venue_id, "1505");
+    assert_eq!(booking.slot.start, "2024-06-01 19:30:00");
+}
+
+#[tokio::test]
+async fn booking_retries_details_and_book_within_the_tight_budget() {
+    let server = MockServer::start().await;
+    mount(&server, "GET", "/4/find", json_response(200, FIND)).await;
+    for endpoint in ["/3/details", "/3/book"] {
+        Mock::given(method("POST"))
+            .and(path(endpoint))
+            .respond_with(ResponseTemplate::new(503))
+            .up_to_n_times(1)
+            .with_priority(1)
+            .mount(&server)
+            .await;
+    }
+    mount(&server, "POST", "/3/details", json_response(200, DETAILS)).await;
+    mount(&server, "POST", "/3/book", json_response(201, BOOK)).await;
+
+    let client = client_for(&server);
+
+    let booking = client.book_best(false).await.expect("a 503 should be retried");
+    assert_eq!(booking.resy_token, "resy-token-abc");
+    let paths: Vec<String> = server.received_requests().await.unwrap()
+        .iter().map(|request| request.url.path().to_string()).collect();
+    assert_eq!(paths, vec!["/4/find", "/3/details", "/3/details", "/3/book", "/3/book"]);
+}
+
+#[tokio::test]
+async fn booking_gives_up_once_the_booking_retry_budget_is_spent() {
+    let server = MockServer::start().await;
+    mount(&server, "GET", "/4/find", json_response(200, FIND)).await;
+    mount(&server, "POST", "/3/details", ResponseTemplate::new(503)).await;
+
+    let mut client = client_for(&server);
+    client.config.target_time = Some("1930".to_string());
+    client.config.booking_retry.max_attempts = 2;
+
+    assert!(client.book_best(false).await.is_err());
+    let details = server.received_requests().await.unwrap()
+        .iter().filter(|request| request.url.path() == "/3/details").count();
+    // both slots are tried, each with two attempts
+    assert_eq!(details, 4);
+}
+
+#[tokio::test]
+async fn template_and_lock_tokens_are_carried_from_find_to_details() {
+    let server = MockServer::start().await;
+    mount(&server, "GET", "/4/find", json_response(200, FIND_TEMPLATE)).await;
+    Mock::given(method("POST"))
+        .and(path("/3/details"))
+        .and(body_partial_json(json!({ "config_id": "rgs://resy/1505/4350/2/2024-06-01/2024-06-01/19:30:00/2/Dining Room", "template_id": "88121", "lock_id": "lock-7f3a" })))
+        .respond_with(json_response(200, DETAILS))
+        .expect(1)
+        .mount(&server)
+        .await;
+    mount(&server, "POST", "/3/book", json_response(201, BOOK)).await;
+
+    let booking = client_for(&server).book_best(false).await.unwrap();
+
+    assert_eq!(booking.slot.tokens, SlotTokens { template_id: Some("88121".to_string()), lock_id: Some("lock-7f3a".to_string()) });
+}
+
+#[tokio::test]
+async fn details_omit_tokens_the_slot_does_not_have() {
+    let server = MockServer::start().await;
+    mount(&server, "GET", "/4/find", json_response(200, FIND)).await;
+    mount(&server, "POST", "/3/details", json_response(200, DETAILS)).await;
+    mount(&server, "POST", "/3/book", json_response(201, BOOK)).await;
+
+    client_for(&server).book_best(false).await.unwrap();
+
+    let requests = server.received_requests().await.unwrap();
+    let details = requests.iter().find(|request| request.url.path() == "/3/details").unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&details.body).unwrap();
+    assert!(body.get("template_id").is_none() && body.get("lock_id").is_none());
+}
+
+#[tokio::test]
+async fn slot_is_locked_before_booking_when_supported() {
+    let server = MockServer::start().await;
+    mount(&server, "GET", "/4/find", json_response(200, FIND)).await;
+    mount(&server, "POST", "/3/details", json_response(200, DETAILS_LOCK)).await;
+    Mock::given(method("POST"))
+        .and(path("/3/lock"))
+        .and(body_partial_json(json!({ "config_id": "rgs://resy/1505/4349/2/2024-06-01/2024-06-01/17:30:00/2/Dining Room", "party_size": 2, "day": "2024-06-01" })))
+        .respond_with(json_response(200, LOCK))
+        .expect(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/3/book"))
+        .and(body_string_contains("lock_token=lock-token-1930"))
+        .respond_with(json_response(201, BOOK))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let booking = client_for(&server).book_best(false).await.unwrap();
+    assert_eq!(booking.resy_token, "resy-token-abc");
+
+    let paths: Vec<String> = server.received_requests().await.unwrap().iter().map(|request| request.url.path().to_string()).collect();
+    assert_eq!(paths, vec!["/4/find", "/3/details", "/3/lock", "/3/book"]);
+}
+
+#[tokio::test]
+async fn failed_lock_still_books_without_one() {
+    let server = MockServer::start().await;
+    mount(&server, "GET", "/4/find", json_response(200, FIND)).await;
+    mount(&server, "POST", "/3/details", json_response(200, DETAILS_LOCK)).await;
+    mount(&server, "POST", "/3/lock", json_response(500, "{}")).await;
+    mount(&server, "POST", "/3/book", json_response(201, BOOK)).await;
+
+    client_for(&server).book_best(false).await.unwrap();
+
+    let requests = server.received_requests().await.unwrap();
+    let book = requests.iter().find(|request| request.url.path() == "/3/book").unwrap();
+    assert!(!String::from_utf8_lossy(&book.body).contains("lock_token"));
+}
+
+#[tokio::test]
+async fn slots_are_not_locked_unless_supported() {
+    let server = MockServer::start().await;
+    mount(&server, "GET", "/4/find", json_response(200, FIND)).await;
+    mount(&server, "POST", "/3/details", json_response(200, DETAILS_FREE)).await;
+    mount(&server, "POST", "/3/book", json_response(201, BOOK)).await;
+
+    client_for(&server).book_best(false).await.unwrap();
+
+    let requests = server.received_requests().await.unwrap();
+    assert!(requests.iter().all(|request| request.url.path() != "/3/lock"));
+}
+
+#[tokio::test]
+async fn slots_outside_service_hours_are_skipped_when_checked() {
+    let server = MockServer::start().await;
+    mount(&server, "GET", "/4/find", json_response(200, FIND_OUT_OF_HOURS)).await;
+    mount(&server, "GET", "/3/venue", json_response(200, VENUE_HOURS)).await;
+    mount(&server, "POST", "/3/details", json_response(200, DETAILS)).await;
+    mount(&server, "POST", "/3/book", json_response(201, BOOK)).await;
+
+    let mut client = client_for(&server);
+    client.config.check_service_hours = true;
+    let booking = client.book_best(false).await.unwrap();
+
+    assert_eq!(booking.slot.start, "2024-06-01 19:30:00");
+    let requests = server.received_requests().await.unwrap();
+    assert_eq!(requests.iter().filter(|request| request.url.path() == "/3/details").count(), 1);
+}
+
+#[test]
+fn service_hours_can_run_past_midnight() {
+    let time = |time: &str| NaiveTime::parse_from_str(time, "%H:%M").unwrap();
+    let late_night = ServiceHours { open: time("22:00"), close: time("02:00") };
+
+    assert!(late_night.contains(time("23:30")));
+    assert!(late_night.contains(time("01:00")));
+    assert!(!late_night.contains(time("02:00")));
+    assert!(!late_night.contains(time("19:00")));
+}
+
+#[tokio::test]
+async fn service_hours_are_not_checked_by_default() {
+    let server = MockServer::start().await;
+    mount(&server, "GET", "/4/find", json_response(200, FIND_OUT_OF_HOURS)).await;
+    mount(&server, "POST", "/3/details", json_response(200, DETAILS)).await;
+    mount(&server, "POST", "/3/book", json_response(201, BOOK)).await;
+
+    let booking = client_for(&server).book_best(false).await.unwrap();
+
+    assert_eq!(booking.slot.start, "2024-06-01 04:15:00");
+    assert_eq!(venue_requests(&server).await, 0);
+}
+
+#[tokio::test]
+async fn venues_without_hours_leave_slots_unchecked() {
+    let server = MockServer::start().await;
+    mount(&server, "GET", "/4/find", json_response(200, FIND_OUT_OF_HOURS)).await;
+    mount(&server, "GET", "/3/venue", json_response(200, VENUE)).await;
+    mount(&server, "POST", "/3/details", json_response(200, DETAILS)).await;
+    mount(&server, "POST", "/3/book", json_response(201, BOOK)).await;
+
+    let mut client = client_for(&server);
+    client.config.check_service_hours = true;
+    let booking = client.book_best(false).await.unwrap();
+
+    assert_eq!(booking.slot.start, "2024-06-01 04:15:00");
+}
+
+#[tokio::test]
+async fn view_venue_resolves_venue_from_url() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/3/venue"))
+        .and(query_param("url_slug", "don-angie"))
+        .respond_with(json_response(200, VENUE))
+        .mount(&server)
+        .await;
+    mount(&server, "GET", "/4/find", json_response(200, FIND)).await;
+
+    let mut client = client_for(&server);
+    client.config.venue_id = String::new();
+
+    let url = "https://resy.com/cities/ny/venues/don-angie?date=2024-06-01&seats=2";
+    let (venue_id, slots) = client.view_venue(Some(url), None, None, None).await.unwrap();
+    assert_eq!(venue_id, "1505");
+    assert_eq!(client.config.venue_slug, "don-angie");
+    assert_eq!(slots.len(), 2);
+}
+
+#[tokio::test]
+async fn book_best_sold_out() {
+    let server = MockServer::start().await;
+    mount(&server, "GET", "/4/find", json_response(200, FIND_SOLD_OUT)).await;
+
+    let result = client_for(&server).book_best(false).await;
+    assert!(matches!(result, Err(ResyClientError::NotFound(_))));
+}
+
+#[tokio::test]
+async fn book_best_slot_taken() {
+    let server = MockServer::start().await;
+    mount(&server, "GET", "/4/find", json_response(200, FIND)).await;
+    mount(&server, "POST", "/3/details", json_response(412, DETAILS_SLOT_TAKEN)).await;
+
+    let result = client_for(&server).book_best(false).await;
+    assert!(matches!(result, Err(ResyClientError::BookingError(_))));
+}
+
+#[tokio::test]
+async fn book_best_rate_limited() {
+    let server = MockServer::start().await;
+    mount(&server, "GET", "/4/find", json_response(429, RATE_LIMITED)).await;
+
+    let result = client_for(&server).book_best(false).await;
+    assert!(matches!(result, Err(ResyClientError::RateLimited(_))));
+}
+
+#[tokio::test]
+async fn book_best_uses_slot_selector_over_target_time() {
+    let server = MockServer::start().await;
+    mount(&server, "GET", "/4/find", json_response(200, FIND)).await;
+    Mock::given(method("POST"))
+        .and(path("/3/details"))
+        .and(body_partial_json(json!({ "config_id": "rgs://resy/1505/4349/2/2024-06-01/2024-06-01/17:30:00/2/Dining Room" })))
+        .respond_with(json_response(200, DETAILS))
+        .expect(1)
+        .mount(&server)
+        .await;
+    mount(&server, "POST", "/3/book", json_response(201, BOOK)).await;
+
+    let mut client = client_for(&server);
+    client.config.target_time = Some("1930".to_string());
+    client.set_slot_selector(SlotSelector::new(|slots| slots.iter().find(|slot| slot.token.contains("/4349/"))));
+
+    let booking = client.book_best(false).await.expect("booking should succeed");
+    assert_eq!(booking.slot.start, "2024-06-01 17:30:00");
+}
+
+async fn book_with_details(details: &str) -> BookingResult {
+    let server = MockServer::start().await;
+    mount(&server, "GET", "/4/find", json_response(200, FIND)).await;
+    mount(&server, "POST", "/3/details", json_response(200, details)).await;
+    mount(&server, "POST", "/3/book", json_response(201, BOOK)).await;
+
+    client_for(&server).book_best(false).await.expect("booking should succeed")
+}
+
+#[tokio::test]
+async fn booking_carries_cancellation_fee_policy() {
+    let cancellation = book_with_details(DETAILS_CANCEL_FEE).await.cancellation.expect("policy should be parsed");
+
+    assert_eq!(cancellation.cutoff_hours, Some(24));
+    assert_eq!(cancellation.penalty, Some(Money::usd(25.0)));
+    assert!(!cancellation.is_free());
+    assert_eq!(cancellation.description.as_deref(), Some("Cancel for free up to 24 hours before your reservation. A $25 fee applies after that."));
+    assert_eq!(cancellation.to_string(), "free cancellation until 24h before, then $25.00");
+}
+
+#[tokio::test]
+async fn booking_carries_free_cancellation_policy() {
+    let cancellation = book_with_details(DETAILS_CANCEL_FREE).await.cancellation.expect("policy should be parsed");
+
+    assert!(cancellation.is_free());
+    assert_eq!(cancellation.cutoff_hours, None);
+    assert_eq!(cancellation.to_string(), "free cancellation");
+}
+
+#[tokio::test]
+async fn booking_without_cancellation_terms_has_no_policy() {
+    assert!(book_with_details(DETAILS).await.cancellation.is_none());
+}
+
+#[tokio::test]
+async fn book_best_token_expired() {
+    let server = MockServer::start().await;
+    mount(&server, "GET", "/4/find", json_response(200, FIND)).await;
+    mount(&server, "POST", "/3/details", json_response(200, DETAILS)).await;
+    mount(&server, "POST", "/3/book", json_response(404, BOOK_TOKEN_EXPIRED)).await;
+
+    let result = client_for(&server).book_best(false).await;
+    assert!(matches!(result, Err(ResyClientError::BookingError(_))));
+}
+
+#[tokio::test]
+async fn book_best_stops_when_already_booked() {
+    let server = MockServer::start().await;
+    mount(&server, "GET", "/4/find", json_response(200, FIND)).await;
+    mount(&server, "POST", "/3/details", json_response(200, DETAILS)).await;
+    mount(&server, "POST", "/3/book", json_response(412, BOOK_ALREADY_BOOKED)).await;
+
+    let result = client_for(&server).book_best(false).await;
+
+    assert!(matches!(result, Err(ResyClientError::AlreadyBooked { resy_token: Some(ref token) }) if token == "resy-token-existing"));
+    let requests = server.received_requests().await.unwrap();
+    assert_eq!(requests.iter().filter(|request| request.url.path() == "/3/book").count(), 1);
+}
+
+#[tokio::test]
+async fn view_venue_searches_from_venue_coordinates() {
+    let server = MockServer::start().await;
+    mount(&server, "GET", "/3/venue", json_response(200, VENUE)).await;
+    Mock::given(method("GET"))
+        .and(path("/4/find"))
+        .and(query_param("lat", "40.7377"))
+        .and(query_param("long", "-74.0059"))
+        .respond_with(json_response(200, FIND))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let mut client = client_for(&server);
+    let url = "https://resy.com/cities/ny/venues/don-angie";
+    let (venue_id, _) = client.view_venue(Some(url), None, None, None).await.unwrap();
+    assert_eq!(venue_id, "1505");
+    assert_eq!(client.config.search_coordinates(), client.config.venue_coordinates.unwrap());
+}
+
+async fn range_server() -> MockServer {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/4/find"))
+        .and(query_param("day", "2024-06-02"))
+        .respond_with(json_response(200, FIND_SOLD_OUT))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/4/find"))
+        .and(query_param("day", "2024-06-04"))
+        .respond_with(json_response(500, "{}"))
+        .mount(&server)
+        .await;
+    mount(&server, "GET", "/4/find", json_response(200, FIND)).await;
+    server
+}
+
+fn day(day: &str) -> Day {
+    day.parse().unwrap()
+}
+
+fn days(days: &[&str]) -> Vec<Day> {
+    days.iter().map(|d| day(d)).collect()
+}
+
+#[tokio::test]
+async fn find_range_collects_slots_per_day() {
+    let server = range_server().await;
+
+    let days = days(&["2024-06-01", "2024-06-02", "2024-06-03"]);
+    let slots = client_for(&server).find_range("1505", &days, Some(2), 2, BatchMode::FailFast).await.unwrap();
+
+    assert_eq!(slots.len(), 3);
+    assert_eq!(slots[&day("2024-06-01")].as_ref().unwrap().len(), 2);
+    assert!(slots[&day("2024-06-02")].as_ref().unwrap().is_empty());
+    assert_eq!(slots[&day("2024-06-03")].as_ref().unwrap().len(), 2);
+    assert_eq!(server.received_requests().await.unwrap().len(), 3);
+}
+
+#[tokio::test]
+async fn find_range_fail_fast_returns_first_error() {
+    let server = range_server().await;
+
+    let days = days(&["2024-06-01", "2024-06-04", "2024-06-03"]);
+    let result = client_for(&server).find_range("1505", &days, Some(2), 1, BatchMode::FailFast).await;
+
+    assert!(matches!(result, Err(ResyClientError::ApiError(_))));
+}
+
+#[tokio::test]
+async fn find_range_collect_all_pairs_days_with_results() {
+    let server = range_server().await;
+
+    let days = days(&["2024-06-01", "2024-06-04", "2024-06-03"]);
+    let slots = client_for(&server).find_range("1505", &days, Some(2), 1, BatchMode::CollectAll).await.unwrap();
+
+    assert_eq!(slots.len(), 3);
+    assert!(slots[&day("2024-06-01")].is_ok());
+    assert!(matches!(slots[&day("2024-06-04")], Err(ResyClientError::ApiError(_))));
+    assert!(slots[&day("2024-06-03")].is_ok());
+}
+
+#[tokio::test]
+async fn find_range_holds_back_the_batch_when_rate_limited() {
+    let server = MockServer::start().await;
+    mount_first(&server, 1, json_response(429, RATE_LIMITED).insert_header("retry-after", "1")).await;
+    mount(&server, "GET", "/4/find", json_response(200, FIND)).await;
+
+    let started = std::time::Instant::now();
+    let days = days(&["2024-06-01", "2024-06-02"]);
+    let slots = client_for(&server).find_range("1505", &days, Some(2), 2, BatchMode::FailFast).await.unwrap();
+
+    assert!(slots.values().all(|found| found.is_ok()));
+    assert_eq!(server.received_requests().await.unwrap().len(), 3);
+    assert!(started.elapsed() >= Duration::from_secs(1));
+}
+
+#[tokio::test]
+async fn find_anything_gives_up_on_rate_limits_once_the_retry_policy_is_spent() {
+    let server = MockServer::start().await;
+    mount(&server, "GET", "/4/find", json_response(429, RATE_LIMITED)).await;
+
+    let mut client = client_for(&server);
+    client.config.retry = RetryPolicy { max_attempts: 2, base_ms: 10, max_ms: 10, ..RetryPolicy::default() };
+    let days = days(&["2024-06-01", "2024-06-02"]);
+    let result = client.find_anything("1505", &days, &[2], &SlotPreferences::from_config(&client.config), 2).await;
+
+    assert!(matches!(result, Err(ResyClientError::RateLimited(_))));
+    assert_eq!(server.received_requests().await.unwrap().len(), 4);
+}
+
+#[tokio::test]
+async fn find_anything_returns_first_day_then_size_with_a_preferred_slot() {
+    let server = range_server().await;
+
+    let days = days(&["2024-06-04", "2024-06-02", "2024-06-03", "2024-06-01"]);
+    let preferences = SlotPreferences { target_time: Some("2100".to_string()), ..SlotPreferences::default() };
+    let found = client_for(&server).find_anything("1505", &days, &[4, 2], &preferences, 3).await.unwrap();
+
+    assert_eq!(found.day, day("2024-06-03"));
+    assert_eq!(found.party_size, 4);
+    assert_eq!(found.slot.start, "2024-06-01 19:30:00");
+    assert_eq!(server.received_requests().await.unwrap().len(), 8);
+}
+
+#[tokio::test]
+async fn find_anything_ranks_the_best_slot_of_every_combination() {
+    let server = MockServer::start().await;
+    for (day, fixture) in [("2024-06-01", FIND), ("2024-06-02", FIND_SEATING_TYPES)] {
+        Mock::given(method("GET"))
+            .and(path("/4/find"))
+            .and(query_param("day", day))
+            .respond_with(json_response(200, fixture))
+            .mount(&server)
+            .await;
+    }
+
+    let days = days(&["2024-06-01", "2024-06-02"]);
+    let preferences = SlotPreferences { target_time: Some("2100".to_string()), ..SlotPreferences::default() };
+    let found = client_for(&server).find_anything("1505", &days, &[2], &preferences, 2).await.unwrap();
+
+    // the first day's best is 19:30, the second day has 21:00
+    assert_eq!(found.day, day("2024-06-02"));
+    assert_eq!(found.slot.start, "2024-06-01 21:00:00");
+}
+
+#[tokio::test]
+async fn find_anything_returns_first_failure_when_nothing_is_found() {
+    let server = range_server().await;
+
+    let days = days(&["2024-06-02", "2024-06-04"]);
+    let result = client_for(&server).find_anything("1505", &days, &[2], &SlotPreferences::default(), 2).await;
+    assert!(matches!(result, Err(ResyClientError::ApiError(_))));
+
+    let result = client_for(&server).find_anything("1505", &days[..1], &[2], &SlotPreferences::default(), 2).await;
+    assert!(matches!(result, Err(ResyClientError::NotFound(_))));
+}
+
+#[tokio::test]
+async fn book_match_books_for_the_matched_day_and_size() {
+    let server = range_server().await;
+    Mock::given(method("POST"))
+        .and(path("/3/details"))
+        .and(body_partial_json(json!({ "commit": 1, "party_size": 4, "day": "2024-06-03" })))
+        .respond_with(json_response(200, DETAILS))
+        .mount(&server)
+        .await;
+    mount(&server, "POST", "/3/book", json_response(201, BOOK)).await;
+
+    let client = client_for(&server);
+    let found = client.find_anything("1505", &days(&["2024-06-03"]), &[4], &SlotPreferences::default(), 1).await.unwrap();
+    let booking = client.book_match(&found, false).await.unwrap();
+
+    assert_eq!(booking.date, "2024-06-03");
+    assert_eq!(booking.party_size, 4);
+    assert_eq!(booking.slot.start, found.slot.start);
+}
+
+#[tokio::test]
+async fn find_defaults_to_configured_day_and_party_size() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/4/find"))
+        .and(query_param("day", "2024-06-01"))
+        .and(query_param("party_size", "2"))
+        .respond_with(json_response(200, FIND))
+        .mount(&server)
+        .await;
+
+    let slots = client_for(&server).find(None, None).await.unwrap();
+
+    assert_eq!(slots.len(), 2);
+}
+
+#[tokio::test]
+async fn is_available_ignores_preferences() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/4/find"))
+        .and(query_param("day", "2024-06-08"))
+        .and(query_param("party_size", "4"))
+        .respond_with(json_response(200, FIND))
+        .mount(&server)
+        .await;
+
+    let mut client = client_for(&server);
+    client.config.reservation_type = Some("Patio".to_string());
+    client.config.allowed_types = vec!["Patio".to_string()];
+
+    assert!(client.is_available(day("2024-06-08"), 4).await.unwrap());
+    let requests = server.received_requests().await.unwrap();
+    assert!(requests[0].url.query_pairs().all(|(name, _)| name != "type"));
+}
+
+#[tokio::test]
+async fn is_available_is_false_when_sold_out() {
+    let server = MockServer::start().await;
+    mount(&server, "GET", "/4/find", json_response(200, FIND_SOLD_OUT)).await;
+
+    assert!(!client_for(&server).is_available(day("2024-06-01"), 2).await.unwrap());
+}
+
+#[tokio::test]
+async fn snapshots_append_and_round_trip() {
+    let server = MockServer::start().await;
+    mount(&server, "GET", "/4/find", json_response(200, FIND)).await;
+    let path = std::env::temp_dir().join(format!("marksman-snapshots-{}.jsonl", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+
+    let client = client_for(&server);
+    let first = client.snapshot_to(&path, day("2024-06-01"), 2).await.unwrap();
+    let second = client.snapshot_to(&path, day("2024-06-01"), 4).await.unwrap();
+
+    let snapshots = load_snapshots(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(snapshots.len(), 2);
+    assert_eq!(snapshots[0].taken_at, first.taken_at);
+    assert_eq!(snapshots[1].taken_at, second.taken_at);
+    assert_eq!((snapshots[0].venue_id.as_str(), snapshots[0].date.as_str(), snapshots[0].party_size), ("1505", "2024-06-01", 2));
+    assert_eq!(snapshots[1].party_size, 4);
+    let ids: Vec<&str> = snapshots[0].slots.iter().map(|slot| slot.id.as_str()).collect();
+    assert_eq!(ids, vec!["4349", "4350"]);
+    assert_eq!(snapshots[0].slots[0].token, first.slots[0].token);
+}
+
+async fn find_requests(server: &MockServer) -> usize {
+    server.received_requests().await.unwrap().iter().filter(|request| request.url.path() == "/4/find").count()
+}
+
+#[tokio::test]
+async fn concurrent_identical_finds_share_one_request() {
+    let server = MockServer::start().await;
+    mount(&server, "GET", "/4/find", json_response(200, FIND).set_delay(Duration::from_millis(100))).await;
+
+    let client = client_for(&server);
+    let target_client = client.for_target(&SnipeTarget { target_time: None, party_size: 2, ..plan_target() });
+    let (first, second, other_size) = tokio::join!(client.find(None, None), client.find(None, None), client.find(None, Some(4)));
+    assert_eq!(first.unwrap().len(), 2);
+    assert_eq!(second.unwrap().len(), 2);
+    assert_eq!(other_size.unwrap().len(), 2);
+    assert_eq!(find_requests(&server).await, 2);
+
+    // finished finds aren't reused
+    client.find(None, None).await.unwrap();
+    assert_eq!(find_requests(&server).await, 3);
+
+    // for_target clients join finds in flight on the parent
+    let (_, _) = tokio::join!(target_client.find(None, None), client.find(None, None));
+    assert_eq!(find_requests(&server).await, 4);
+}
+
+#[tokio::test]
+async fn find_dedupe_can_be_turned_off() {
+    let server = MockServer::start().await;
+    mount(&server, "GET", "/4/find", json_response(200, FIND).set_delay(Duration::from_millis(100))).await;
+
+    let mut config = client_for(&server).config;
+    config.dedupe_finds = false;
+    let client = ResyClient::from_config(config);
+    let (first, second) = tokio::join!(client.find(None, None), client.find(None, None));
+    first.unwrap();
+    second.unwrap();
+
+    assert_eq!(find_requests(&server).await, 2);
+}
+
+#[tokio::test]
+async fn find_carries_venue_currency_on_fees() {
+    let server = MockServer::start().await;
+    mount(&server, "GET", "/4/find", json_response(200, FIND_GBP)).await;
+
+    let slots = client_for(&server).find(None, None).await.unwrap();
+
+    assert_eq!(slots[0].fees, Money::new(25.0, "GBP"));
+    assert_eq!(slots[0].to_string(), "7:30 PM · Dining Room · party of 2 · £25.00");
+}
+
+#[tokio::test]
+async fn find_defaults_to_usd_without_venue_currency() {
+    let server = MockServer::start().await;
+    mount(&server, "GET", "/4/find", json_response(200, FIND)).await;
+
+    let slots = client_for(&server).find(None, None).await.unwrap();
+
+    assert_eq!(slots[0].fees.currency, "USD");
+}
+
+#[tokio::test]
+async fn find_uses_explicit_day_and_party_size() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/4/find"))
+        .and(query_param("day", "2024-06-08"))
+        .and(query_param("party_size", "4"))
+        .respond_with(json_response(200, FIND))
+        .mount(&server)
+        .await;
+
+    let slots = client_for(&server).find(Some(day("2024-06-08")), Some(4)).await.unwrap();
+
+    assert_eq!(slots.len(), 2);
+}
+
+#[tokio::test]
+async fn list_seating_types_returns_sorted_distinct_types() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/4/find"))
+        .and(query_param("venue_id", "60058"))
+        .and(query_param("day", "2024-06-08"))
+        .and(query_param("party_size", "4"))
+        .respond_with(json_response(200, FIND_SEATING_TYPES))
+        .mount(&server)
+        .await;
+
+    let types = client_for(&server).list_seating_types("60058", day("2024-06-08"), 4).await.unwrap();
+
+    assert_eq!(types, vec!["Bar", "Dining Room", "Patio"]);
+}
+
+#[tokio::test]
+async fn list_seating_types_is_empty_when_sold_out() {
+    let server = MockServer::start().await;
+    mount(&server, "GET", "/4/find", json_response(200, FIND_SOLD_OUT)).await;
+
+    let types = client_for(&server).list_seating_types("1505", day("2024-06-01"), 2).await.unwrap();
+
+    assert!(types.is_empty());
+}
+
+#[tokio::test]
+async fn find_sends_reservation_type_when_set() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/4/find"))
+        .and(query_param("type", "dining"))
+        .respond_with(json_response(200, FIND))
+        .mount(&server)
+        .await;
+
+    let mut client = client_for(&server);
+    client.config.reservation_type = Some("dining".to_string());
+    let slots = client.find(None, None).await.unwrap();
+
+    assert_eq!(slots.len(), 2);
+}
+
+#[tokio::test]
+async fn find_omits_reservation_type_by_default() {
+    let server = MockServer::start().await;
+    mount(&server, "GET", "/4/find", json_response(200, FIND)).await;
+
+    client_for(&server).find(None, None).await.unwrap();
+
+    let requests = server.received_requests().await.unwrap();
+    assert!(requests[0].url.query_pairs().all(|(key, _)| key != "type"));
+}
+
+#[tokio::test]
+async fn get_venue_by_id_returns_venue_details() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/3/venue"))
+        .and(query_param("id", "1505"))
+        .respond_with(json_response(200, VENUE))
+        .mount(&server)
+        .await;
+
+    let venue = client_for(&server).get_venue_by_id("1505").await.unwrap();
+    assert_eq!(venue.id, 1505);
+    assert_eq!(venue.name, "Don Angie");
+    assert_eq!(venue.url_slug, "don-angie");
+    assert_eq!(venue.time_zone.as_deref(), Some("EST5EDT"));
+}
+
+fn plan_target() -> SnipeTarget {
+    SnipeTarget {
+        venue_id: "1505".to_string(),
+        date: "2024-06-01".to_string(),
+        party_size: 4,
+        target_time: Some("1930".to_string()),
+        snipe_date: "2024-05-18".to_string(),
+        snipe_time: "0900".to_string(),
+        release_at: None,
+        recurrence: None,
+    }
+}
+
+#[tokio::test]
+async fn plan_resolves_venue_without_checking_availability() {
+    let server = MockServer::start().await;
+    mount(&server, "GET", "/3/venue", json_response(200, VENUE)).await;
+
+    let mut client = client_for(&server);
+    client.config.blocked_types = vec!["Bar".to_string()];
+    client.config.max_total = Some(150.0);
+    let plan = client.plan(&plan_target()).await.unwrap();
+
+    assert_eq!(plan.venue_name, "Don Angie");
+    assert_eq!(plan.party_size, 4);
+    assert_eq!(plan.release_at, plan_target().release_at());
+    assert_eq!(plan.preferences.target_time.as_deref(), Some("1930"));
+    assert_eq!(plan.payment_id.as_deref(), Some("123456"));
+
+    let rendered = plan.to_string();
+    assert!(rendered.contains("Venue:       Don Angie (1505)"));
+    assert!(rendered.contains("Order:       closest to 1930"));
+    assert!(rendered.contains("Seating:     never Bar"));
+    assert!(rendered.contains("Limits:      max total 150.00, deposits allowed"));
+
+    let requests = server.received_requests().await.unwrap();
+    assert!(requests.iter().all(|request| request.url.path() == "/3/venue"));
+}
+
+#[tokio::test]
+async fn plan_flags_missing_payment_method_and_bad_release_time() {
+    let server = MockServer::start().await;
+    mount(&server, "GET", "/3/venue", json_response(200, VENUE)).await;
+
+    let mut client = client_for(&server);
+    client.config.payment_id = String::new();
+    let mut target = plan_target();
+    target.snipe_time = "9am".to_string();
+    let plan = client.plan(&target).await.unwrap();
+
+    assert!(plan.release_at.is_none());
+    assert!(plan.payment_id.is_none());
+    assert!(plan.to_string().contains("Fires at:    never"));
+}
+
+async fn venue_requests(server: &MockServer) -> usize {
+    server.received_requests().await.unwrap().iter().filter(|request| request.url.path() == "/3/venue").count()
+}
+
+#[tokio::test]
+async fn venue_lookups_are_cached() {
+    let server = MockServer::start().await;
+    mount(&server, "GET", "/3/venue", json_response(200, VENUE)).await;
+
+    let client = client_for(&server);
+    client.get_venue_by_id("1505").await.unwrap();
+    let target = SnipeTarget {
+        venue_id: "1505".to_string(),
+        date: "2024-06-02".to_string(),
+        party_size: 4,
+        target_time: None,
+        snipe_date: "2024-05-26".to_string(),
+        snipe_time: "0900".to_string(),
+        release_at: None,
+        recurrence: None,
+    };
+    let venue = client.for_target(&target).get_venue_by_id("1505").await.unwrap();
+
+    assert_eq!(venue.name, "Don Angie");
+    assert_eq!(venue_requests(&server).await, 1);
+}
+
+#[tokio::test]
+async fn invalidated_venue_cache_refetches() {
+    let server = MockServer::start().await;
+    mount(&server, "GET", "/3/venue", json_response(200, VENUE)).await;
+
+    let client = client_for(&server);
+    client.get_venue_by_id("1505").await.unwrap();
+    client.invalidate_venue_cache();
+    client.get_venue_by_id("1505").await.unwrap();
+
+    assert_eq!(venue_requests(&server).await, 2);
+}
+
+#[tokio::test]
+async fn slot_preferences_take_the_venue_time_zone_for_lead_times() {
+    let server = MockServer::start().await;
+    mount(&server, "GET", "/3/venue", json_response(200, VENUE)).await;
+
+    let mut client = client_for(&server);
+    assert_eq!(client.slot_preferences().await.time_zone, None);
+    assert_eq!(venue_requests(&server).await, 0);
+
+    client.config.min_lead_mins = 60;
+    let preferences = client.slot_preferences().await;
+    assert_eq!(preferences.min_lead_mins, 60);
+    assert_eq!(preferences.time_zone.as_deref(), Some("EST5EDT"));
+}
+
+fn venue_cache_path(name: &str) -> std::path::PathBuf {
+    let path = std::env::temp_dir().join(format!("marksman-venues-{}-{}.json", name, std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    path
+}
+
+#[tokio::test]
+async fn shutdown_saves_venue_cache_for_the_next_client() {
+    let server = MockServer::start().await;
+    mount(&server, "GET", "/3/venue", json_response(200, VENUE)).await;
+    let path = venue_cache_path("saved");
+
+    let mut config = client_for(&server).config;
+    config.venue_cache_path = Some(path.to_string_lossy().to_string());
+    let client = ResyClient::from_config(config.clone());
+    client.get_venue_by_id("1505").await.unwrap();
+    client.shutdown().await.unwrap();
+
+    let venue = ResyClient::from_config(config).get_venue_by_id("1505").await.unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(venue.id, 1505);
+    assert_eq!(venue_requests(&server).await, 1);
+}
+
+#[tokio::test]
+async fn shutdown_runs_once_across_target_clients() {
+    let server = MockServer::start().await;
+    mount(&server, "GET", "/3/venue", json_response(200, VENUE)).await;
+    let path = venue_cache_path("once");
+
+    let mut client = client_for(&server);
+    client.config.venue_cache_path = Some(path.to_string_lossy().to_string());
+    let target_client = client.for_target(&plan_target());
+    client.get_venue_by_id("1505").await.unwrap();
+    client.shutdown().await.unwrap();
+    std::fs::remove_file(&path).unwrap();
+    client.shutdown().await.unwrap();
+    target_client.shutdown().await.unwrap();
+
+    assert!(!path.exists());
+}
+
+#[tokio::test]
+async fn shutdown_waits_for_finds_in_flight() {
+    let server = MockServer::start().await;
+    mount(&server, "GET", "/4/find", json_response(200, FIND).set_delay(Duration::from_millis(300))).await;
+    let client = client_for(&server);
+
+    let (found, waited) = tokio::join!(client.find(None, None), async {
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let started = std::time::Instant::now();
+        client.shutdown().await.unwrap();
+        started.elapsed()
+    });
+
+    assert_eq!(found.unwrap().len(), 2);
+    assert!(waited >= Duration::from_millis(200), "waited {:?}", waited);
+}
+
+#[tokio::test]
+async fn shutdown_stops_waiting_at_its_timeout() {
+    let server = MockServer::start().await;
+    mount(&server, "GET", "/4/find", json_response(200, FIND).set_delay(Duration::from_secs(10))).await;
+    let mut client = client_for(&server);
+    client.config.shutdown_timeout_secs = 1;
+
+    let (_, waited) = tokio::join!(tokio::time::timeout(Duration::from_millis(1500), client.find(None, None)), async {
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let started = std::time::Instant::now();
+        client.shutdown().await.unwrap();
+        started.elapsed()
+    });
+
+    assert!(waited >= Duration::from_millis(900) && waited < Duration::from_secs(3), "waited {:?}", waited);
+}
+
+#[tokio::test]
+async fn zero_ttl_disables_venue_cache() {
+    let server = MockServer::start().await;
+    mount(&server, "GET", "/3/venue", json_response(200, VENUE)).await;
+
+    let mut config = client_for(&server).config;
+    config.venue_cache_ttl_secs = 0;
+    let client = ResyClient::from_config(config);
+    client.get_venue_by_id("1505").await.unwrap();
+    client.get_venue_by_id("1505").await.unwrap();
+
+    assert_eq!(venue_requests(&server).await, 2);
+}
+
+fn discover_filters() -> DiscoverFilters {
+    DiscoverFilters {
+        query: None,
+        day: day("2024-06-01"),
+        party_size: 2,
+        radius_meters: 5000,
+        max_results: 50,
+        only_available: false,
+    }
+}
+
+const NYC: Coordinates = Coordinates { latitude: 40.7128, longitude: -74.0060 };
+
+async fn search_server() -> MockServer {
+    let server = MockServer::start().await;
+    for (page, body) in [(1, VENUE_SEARCH_PAGE1), (2, VENUE_SEARCH_PAGE2)] {
+        Mock::given(method("POST"))
+            .and(path("/3/venuesearch/search"))
+            .and(body_partial_json(json!({ "page": page, "slot_filter": { "day": "2024-06-01", "party_size": 2 } })))
+            .respond_with(json_response(200, body))
+            .mount(&server)
+            .await;
+    }
+    server
+}
+
+#[tokio::test]
+async fn discover_pages_through_all_results() {
+    let server = search_server().await;
+
+    let venues = client_for(&server).discover(&NYC, &discover_filters()).await.unwrap();
+
+    let names: Vec<&str> = venues.iter().map(|venue| venue.name.as_str()).collect();
+    assert_eq!(names, vec!["Don Angie", "4 Charles Prime Rib", "Lilia"]);
+    assert_eq!(venues[2].open_slots, 2);
+    assert_eq!(venues[0].cuisine.as_deref(), Some("Italian"));
+    assert_eq!(server.received_requests().await.unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn discover_can_keep_only_available_venues() {
+    let server = search_server().await;
+    let filters = DiscoverFilters { only_available: true, ..discover_filters() };
+
+    let venues = client_for(&server).discover(&NYC, &filters).await.unwrap();
+
+    assert!(venues.iter().all(|venue| venue.is_available()));
+    assert_eq!(venues.len(), 2);
+}
+
+#[tokio::test]
+async fn discover_stops_at_max_results() {
+    let server = search_server().await;
+    let filters = DiscoverFilters { max_results: 1, ..discover_filters() };
+
+    let venues = client_for(&server).discover(&NYC, &filters).await.unwrap();
+
+    assert_eq!(venues.len(), 1);
+    assert_eq!(server.received_requests().await.unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn discover_handles_empty_results() {
+    let server = MockServer::start().await;
+    mount(&server, "POST", "/3/venuesearch/search", json_response(200, r#"{"meta": {"total": 0}, "search": {"hits": []}}"#)).await;
+
+    let venues = client_for(&server).discover(&NYC, &discover_filters()).await.unwrap();
+
+    assert!(venues.is_empty());
+}
+
+async fn booked_body(details: &str, configure: impl FnOnce(&mut Config)) -> String {
+    let server = MockServer::start().await;
+    mount(&server, "GET", "/4/find", json_response(200, FIND)).await;
+    mount(&server, "POST", "/3/details", json_response(200, details)).await;
+    mount(&server, "POST", "/3/book", json_response(201, BOOK)).await;
+
+    let mut client = client_for(&server);
+    configure(&mut client.config);
+    client.book_best(false).await.unwrap();
+
+    let requests = server.received_requests().await.unwrap();
+    let book = requests.iter().find(|request| request.url.path() == "/3/book").unwrap();
+    String::from_utf8(book.body.clone()).unwrap()
+}
+
+async fn book_with_limit(details: &str, max_total: f64, preferred_add_on: Option<&str>) -> (MockServer, ResyResult<BookingResult>) {
+    let server = MockServer::start().await;
+    mount(&server, "GET", "/4/find", json_response(200, FIND)).await;
+    mount(&server, "POST", "/3/details", json_response(200, details)).await;
+    mount(&server, "POST", "/3/book", json_response(201, BOOK)).await;
+
+    let mut client = client_for(&server);
+    client.config.max_total = Some(max_total);
+    client.config.preferred_add_on = preferred_add_on.map(String::from);
+    let result = client.book_best(false).await;
+    (server, result)
+}
+
+async fn book_requests(server: &MockServer) -> usize {
+    server.received_requests().await.unwrap().iter().filter(|request| request.url.path() == "/3/book").count()
+}
+
+#[tokio::test]
+async fn price_over_max_total_aborts_before_booking() {
+    let (server, result) = book_with_limit(DETAILS_PRICED, 150.0, None).await;
+
+    match result {
+        Err(ResyClientError::PriceExceeded { total, max_total }) => {
+            assert_eq!(total, Money::usd(180.0));
+            assert_eq!(max_total, Money::usd(150.0));
+        }
+        other => panic!("expected PriceExceeded, got {:?}", other),
+    }
+    assert_eq!(book_requests(&server).await, 0);
+}
+
+#[tokio::test]
+async fn price_exceeded_reports_venue_currency() {
+    let (_, result) = book_with_limit(DETAILS_GBP, 150.0, None).await;
+
+    let error = result.unwrap_err();
+    assert!(matches!(&error, ResyClientError::PriceExceeded { total, .. } if *total == Money::new(180.0, "GBP")));
+    assert_eq!(error.to_string(), "PriceExceeded: total £180.00 is over the £150.00 limit");
+}
+
+#[tokio::test]
+async fn price_within_max_total_books() {
+    let (server, result) = book_with_limit(DETAILS_PRICED, 200.0, None).await;
+
+    assert!(result.is_ok());
+    assert_eq!(book_requests(&server).await, 1);
+}
+
+#[tokio::test]
+async fn max_total_includes_chosen_add_on() {
+    let (server, result) = book_with_limit(DETAILS_ADD_ONS, 100.0, Some("tasting menu")).await;
+
+    assert!(matches!(result, Err(ResyClientError::PriceExceeded { total, .. }) if total == Money::usd(145.0)));
+    assert_eq!(book_requests(&server).await, 0);
+}
+
+#[tokio::test]
+async fn missing_total_refuses_to_book_under_max_total() {
+    let (server, result) = book_with_limit(DETAILS, 200.0, None).await;
+
+    assert!(matches!(result, Err(ResyClientError::PriceUnknown(_))));
+    assert_eq!(book_requests(&server).await, 0);
+}
+
+#[tokio::test]
+async fn open_reservation_without_total_books_under_max_total() {
+    let (server, result) = book_with_limit(DETAILS_FREE, 200.0, None).await;
+
+    assert!(result.is_ok());
+    assert_eq!(book_requests(&server).await, 1);
+}
+
+async fn book_without_deposits(details: &str) -> (MockServer, ResyResult<BookingResult>) {
+    let server = MockServer::start().await;
+    mount(&server, "GET", "/4/find", json_response(200, FIND)).await;
+    mount(&server, "POST", "/3/details", json_response(200, details)).await;
+    mount(&server, "POST", "/3/book", json_response(201, BOOK)).await;
+
+    let mut client = client_for(&server);
+    client.config.allow_deposits = false;
+    let result = client.book_best(false).await;
+    (server, result)
+}
+
+#[tokio::test]
+async fn deposit_slots_are_refused_when_deposits_disallowed() {
+    let (server, result) = book_without_deposits(DETAILS_DEPOSIT).await;
+
+    assert!(matches!(result, Err(ResyClientError::DepositRequired(_))));
+    assert_eq!(book_requests(&server).await, 0);
+}
+
+#[tokio::test]
+async fn slots_without_deposit_book_when_deposits_disallowed() {
+    let (server, result) = book_without_deposits(DETAILS).await;
+
+    assert!(result.is_ok());
+    assert_eq!(book_requests(&server).await, 1);
+}
+
+#[tokio::test]
+async fn deposits_are_allowed_by_default() {
+    let body = booked_body(DETAILS_DEPOSIT, |_| {}).await;
+
+    assert!(body.contains("book_token"));
+}
+
+#[tokio::test]
+async fn book_sends_payment_method_when_required() {
+    let body = booked_body(DETAILS, |_| {}).await;
+    assert!(body.contains(r#"struct_payment_method={"id":123456}"#));
+}
+
+#[tokio::test]
+async fn book_omits_payment_method_for_open_reservations() {
+    let body = booked_body(DETAILS_FREE, |config| config.payment_id = String::new()).await;
+    assert!(!body.contains("struct_payment_method"));
+}
+
+#[tokio::test]
+async fn book_fails_without_payment_id_when_payment_required() {
+    let server = MockServer::start().await;
+    mount(&server, "GET", "/4/find", json_response(200, FIND)).await;
+    mount(&server, "POST", "/3/details", json_response(200, DETAILS)).await;
+    mount(&server, "POST", "/3/book", json_response(201, BOOK)).await;
+
+    let mut client = client_for(&server);
+    client.config.payment_id = String::new();
+    let result = client.book_best(false).await;
+
+    assert!(matches!(result, Err(ResyClientError::BookingError(_))));
+    assert_eq!(book_requests(&server).await, 0);
+}
+
+#[tokio::test]
+async fn book_sends_guest_id_when_set() {
+    let body = booked_body(DETAILS, |config| config.guest_id = Some("90210".to_string())).await;
+    assert!(body.contains("guest_id=90210"));
+}
+
+#[tokio::test]
+async fn book_omits_guest_id_by_default() {
+    let body = booked_body(DETAILS, |_| {}).await;
+    assert!(!body.contains("guest_id"));
+}
+
+#[tokio::test]
+async fn linked_accounts_are_parsed_from_user() {
+    let server = MockServer::start().await;
+    let user = json!({
+        "linked_accounts": [
+            { "id": 90210, "first_name": "Ada", "last_name": "Lovelace" },
+            { "id": "77", "first_name": "Alan" },
+            { "first_name": "No id" }
+        ]
+    });
+    mount(&server, "GET", "/2/user", json_response(200, &user.to_string())).await;
+
+    let accounts = client_for(&server).linked_accounts().await.unwrap();
+
+    let ids: Vec<&str> = accounts.iter().map(|account| account.id.as_str()).collect();
+    assert_eq!(ids, vec!["90210", "77"]);
+    assert_eq!(accounts[0].last_name, "Lovelace");
+    assert_eq!(accounts[1].last_name, "");
+}
+
+#[tokio::test]
+async fn linked_accounts_are_empty_when_user_has_none() {
+    let server = MockServer::start().await;
+    mount(&server, "GET", "/2/user", json_response(200, "{}")).await;
+
+    assert!(client_for(&server).linked_accounts().await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn book_sends_preferred_add_on() {
+    let body = booked_body(DETAILS_ADD_ONS, |config| config.preferred_add_on = Some("tasting menu".to_string())).await;
+    assert!(body.contains(&format!("struct_add_ons={}", urlencoding::encode(r#"[{"id":"881"}]"#))));
+}
+
+#[tokio::test]
+async fn book_skips_add_ons_when_venue_has_none() {
+    let body = booked_body(DETAILS, |config| config.preferred_add_on = Some("tasting menu".to_string())).await;
+    assert!(!body.contains("struct_add_ons"));
+}
+
+#[tokio::test]
+async fn healthcheck_reports_each_check() {
+    let server = MockServer::start().await;
+    mount(&server, "GET", "/2/user", json_response(200, r#"{ "payment_methods": [{ "id": 123456 }] }"#)).await;
+
+    let report = client_for(&server).healthcheck().await;
+    assert!(report.is_healthy());
+    assert_eq!(report.checks.len(), 3);
+    assert!(report.latency.is_some());
+}
+
+#[tokio::test]
+async fn healthcheck_fails_on_invalid_credentials() {
+    let server = MockServer::start().await;
+    mount(&server, "GET", "/2/user", json_response(419, "{}")).await;
+
+    let report = client_for(&server).healthcheck().await;
+    assert!(!report.is_healthy());
+    assert!(!report.checks.iter().find(|check| check.name == "credentials valid").unwrap().passed);
+}
+
+fn polling_client(server: &MockServer) -> ResyClient {
+    let mut client = client_for(server);
+    client.config.burst_window_secs = 1;
+    client.config.burst_interval_ms = 10;
+    client.config.poll_interval_ms = 50;
+    client.config.poll_timeout_secs = 2;
+    client
+}
+
+async fn mount_first(server: &MockServer, times: u64, response: ResponseTemplate) {
+    Mock::given(method("GET"))
+        .and(path("/4/find"))
+        .respond_with(response)
+        .up_to_n_times(times)
+        .with_priority(1)
+        .mount(server)
+        .await;
+}
+
+#[tokio::test]
+async fn poll_until_available_bursts_until_slots_appear() {
+    let server = MockServer::start().await;
+    mount_first(&server, 3, json_response(200, FIND_SOLD_OUT)).await;
+    mount(&server, "GET", "/4/find", json_response(200, FIND)).await;
+
+    let started = std::time::Instant::now();
+    let slots = polling_client(&server).poll_until_available().await.unwrap();
+
+    assert_eq!(slots.len(), 2);
+    assert_eq!(server.received_requests().await.unwrap().len(), 4);
+    assert!(started.elapsed() < std::time::Duration::from_millis(500));
+}
+
+#[tokio::test]
+async fn poll_until_available_backs_off_when_rate_limited() {
+    let server = MockServer::start().await;
+    mount_first(&server, 2, json_response(429, RATE_LIMITED)).await;
+    mount(&server, "GET", "/4/find", json_response(200, FIND)).await;
+
+    let started = std::time::Instant::now();
+    let slots = polling_client(&server).poll_until_available().await.unwrap();
+
+    assert_eq!(slots.len(), 2);
+    assert!(started.elapsed() >= std::time::Duration::from_millis(100));
+}
+
+#[tokio::test]
+async fn retry_policy_limits_requests_and_polls_alike() {
+    let server = MockServer::start().await;
+    mount(&server, "GET", "/2/user", json_response(429, RATE_LIMITED)).await;
+    mount(&server, "GET", "/4/find", json_response(429, RATE_LIMITED)).await;
+    let policy = RetryPolicy { max_attempts: 3, base_ms: 10, max_ms: 20, ..RetryPolicy::default() };
+
+    let mut gateway = ResyAPIGateway::from_auth("api-key".to_string(), "auth-token".to_string());
+    gateway.set_base_url(server.uri());
+    let result = send_with_retry(&policy, None, || gateway.get_user()).await;
+    assert_eq!(result.unwrap_err().status(), Some(429));
+
+    let mut client = polling_client(&server);
+    client.config.poll_interval_ms = 10;
+    client.config.retry = policy;
+    let result = client.poll_until_available().await;
+    assert!(matches!(result, Err(ResyClientError::RateLimited(_))));
+
+    let requests = server.received_requests().await.unwrap();
+    assert_eq!(requests.iter().filter(|request| request.url.path() == "/2/user").count(), 3);
+    assert_eq!(requests.iter().filter(|request| request.url.path() == "/4/find").count(), 3);
+}
+
+#[tokio::test]
+async fn poll_until_available_gives_up_after_timeout() {
+    let server = MockServer::start().await;
+    mount(&server, "GET", "/4/find", json_response(200, FIND_SOLD_OUT)).await;
+
+    let mut client = polling_client(&server);
+    client.config.poll_timeout_secs = 1;
+
+    let result = client.poll_until_available().await;
+    assert!(matches!(result, Err(ResyClientError::NotFound(_))));
+}
+
+#[tokio::test]
+async fn watch_slots_yields_only_when_slots_change() {
+    let server = MockServer::start().await;
+    mount_first(&server, 1, json_response(200, FIND_SOLD_OUT)).await;
+    mount(&server, "GET", "/4/find", json_response(200, FIND)).await;
+
+    let client = client_for(&server);
+    let updates: Vec<Vec<ResySlot>> = client.watch_slots(std::time::Duration::from_millis(10)).take(2).collect().await;
+
+    assert!(updates[0].is_empty());
+    assert_eq!(updates[1].len(), 2);
+
+    // unchanged results are polled but not yielded
+    let mut watch = Box::pin(client.watch_slots(std::time::Duration::from_millis(10)));
+    assert_eq!(watch.next().await.unwrap().len(), 2);
+    let next = tokio::time::timeout(std::time::Duration::from_millis(100), watch.next()).await;
+    assert!(next.is_err());
+}
+
+#[tokio::test]
+async fn watch_slots_stops_polling_when_dropped() {
+    let server = MockServer::start().await;
+    mount(&server, "GET", "/4/find", json_response(200, FIND)).await;
+
+    let client = client_for(&server);
+    let mut watch = Box::pin(client.watch_slots(std::time::Duration::from_millis(10)));
+    watch.next().await.unwrap();
+    drop(watch);
+
+    let polled = server.received_requests().await.unwrap().len();
+    tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    assert_eq!(server.received_requests().await.unwrap().len(), polled);
+}
+
+#[tokio::test]
+async fn watch_slots_keeps_watching_through_rate_limits() {
+    let server = MockServer::start().await;
+    mount_first(&server, 2, json_response(429, RATE_LIMITED)).await;
+    mount(&server, "GET", "/4/find", json_response(200, FIND)).await;
+
+    let client = client_for(&server);
+    let mut watch = Box::pin(client.watch_slots(std::time::Duration::from_millis(10)));
+
+    assert_eq!(watch.next().await.unwrap().len(), 2);
+    assert_eq!(server.received_requests().await.unwrap().len(), 3);
+}
+
+async fn watch_for(server: &MockServer, wanted: SlotCriterion) -> ResyResult<BookingResult> {
+    let until = chrono::Local::now() + chrono::Duration::milliseconds(200);
+    client_for(server).book_when_open(&wanted, until, std::time::Duration::from_millis(20), false).await
+}
+
+async fn details_requests(server: &MockServer) -> usize {
+    server.received_requests().await.unwrap().iter().filter(|request| request.url.path() == "/3/details").count()
+}
+
+#[tokio::test]
+async fn book_when_open_books_only_the_exact_time() {
+    let server = MockServer::start().await;
+    mount_first(&server, 1, json_response(200, FIND_SOLD_OUT)).await;
+    mount(&server, "GET", "/4/find", json_response(200, FIND)).await;
+    Mock::given(method("POST"))
+        .and(path("/3/details"))
+        .and(body_partial_json(json!({ "config_id": "rgs://resy/1505/4350/2/2024-06-01/2024-06-01/19:30:00/2/Dining Room" })))
+        .respond_with(json_response(200, DETAILS))
+        .expect(1)
+        .mount(&server)
+        .await;
+    mount(&server, "POST", "/3/book", json_response(201, BOOK)).await;
+
+    let wanted = SlotCriterion { seating: Some("dining room".to_string()), ..SlotCriterion::at("1930") };
+    let booking = watch_for(&server, wanted).await.expect("booking should succeed");
+
+    assert_eq!(booking.slot.start, "2024-06-01 19:30:00");
+}
+
+#[tokio::test]
+async fn book_when_open_ignores_other_times() {
+    let server = MockServer::start().await;
+    mount(&server, "GET", "/4/find", json_response(200, FIND)).await;
+
+    let result = watch_for(&server, SlotCriterion::at("1900")).await;
+
+    assert!(matches!(result, Err(ResyClientError::NotFound(_))));
+    assert_eq!(details_requests(&server).await, 0);
+}
+
+#[tokio::test]
+async fn book_when_open_ignores_other_seating() {
+    let server = MockServer::start().await;
+    mount(&server, "GET", "/4/find", json_response(200, FIND)).await;
+
+    let wanted = SlotCriterion { seating: Some("Patio".to_string()), ..SlotCriterion::at("1930") };
+    let result = watch_for(&server, wanted).await;
+
+    assert!(matches!(result, Err(ResyClientError::NotFound(_))));
+    assert_eq!(details_requests(&server).await, 0);
+}
+
+fn revalidating_client(server: &MockServer) -> ResyClient {
+    let mut client = client_for(server);
+    client.config.target_time = Some("1930".to_string());
+    client.config.revalidate_before_book = true;
+    client
+}
+
+#[tokio::test]
+async fn revalidation_books_with_refreshed_token() {
+    let server = MockServer::start().await;
+    let relisted = FIND.replace("4350/2/2024-06-01/2024-06-01/19:30:00", "4350/2/2024-06-01/2024-06-01/19:30:00-relisted");
+    mount_first(&server, 1, json_response(200, FIND)).await;
+    mount(&server, "GET", "/4/find", json_response(200, &relisted)).await;
+    Mock::given(method("POST"))
+        .and(path("/3/details"))
+        .and(body_partial_json(json!({ "config_id": "rgs://resy/1505/4350/2/2024-06-01/2024-06-01/19:30:00-relisted/2/Dining Room" })))
+        .respond_with(json_response(200, DETAILS))
+        .expect(1)
+        .mount(&server)
+        .await;
+    mount(&server, "POST", "/3/book", json_response(201, BOOK)).await;
+
+    let booking = revalidating_client(&server).book_best(false).await.unwrap();
+
+    assert!(booking.slot.token.ends_with("19:30:00-relisted/2/Dining Room"));
+}
+
+#[tokio::test]
+async fn revalidation_skips_slots_that_disappeared() {
+    let server = MockServer::start().await;
+    mount_first(&server, 1, json_response(200, FIND)).await;
+    mount(&server, "GET", "/4/find", json_response(200, FIND_SOLD_OUT)).await;
+    Mock::given(method("POST"))
+        .and(path("/3/details"))
+        .respond_with(json_response(200, DETAILS))
+        .expect(0)
+        .mount(&server)
+        .await;
+
+    let result = revalidating_client(&server).book_best(false).await;
+
+    assert!(matches!(result, Err(ResyClientError::BookingError(_))));
+}
+
+#[tokio::test]
+async fn watch_notify_only_reports_each_slot_once_and_never_books() {
+    let server = MockServer::start().await;
+    mount(&server, "GET", "/4/find", json_response(200, FIND)).await;
+    Mock::given(method("POST"))
+        .and(path("/3/book"))
+        .respond_with(json_response(201, BOOK))
+        .expect(0)
+        .mount(&server)
+        .await;
+
+    let mut client = polling_client(&server);
+    client.config.poll_timeout_secs = 1;
+    client.config.on_found = FoundAction::NotifyOnly;
+
+    let mut notified = Vec::new();
+    let booking = client.watch(false, |slot| notified.push(slot.id.clone())).await.unwrap();
+
+    assert!(booking.is_none());
+    assert_eq!(notified.len(), 2);
+    assert!(server.received_requests().await.unwrap().len() > 2);
+}
+
+#[tokio::test]
+async fn watch_books_by_default() {
+    let server = MockServer::start().await;
+    mount(&server, "GET", "/4/find", json_response(200, FIND)).await;
+    mount(&server, "POST", "/3/details", json_response(200, DETAILS)).await;
+    mount(&server, "POST", "/3/book", json_response(201, BOOK)).await;
+
+    let booking = polling_client(&server).watch(false, |_| panic!("book mode should not notify")).await.unwrap();
+
+    assert_eq!(booking.unwrap().resy_token, "resy-token-abc");
+}
+
+#[tokio::test]
+async fn snipe_at_warms_up_then_polls_after_the_release() {
+    let server = MockServer::start().await;
+    mount(&server, "GET", "/2/user", json_response(200, "{}")).await;
+    mount_first(&server, 2, json_response(200, FIND_SOLD_OUT)).await;
+    mount(&server, "GET", "/4/find", json_response(200, FIND)).await;
+    mount(&server, "POST", "/3/details", json_response(200, DETAILS)).await;
+    mount(&server, "POST", "/3/book", json_response(201, BOOK)).await;
+
+    let release_at = chrono::Local::now() + chrono::Duration::milliseconds(200);
+    let booking = polling_client(&server).snipe_at(release_at, false, |_| {}).await.unwrap();
+
+    assert_eq!(booking.unwrap().resy_token, "resy-token-abc");
+    assert!(chrono::Local::now() >= release_at);
+    let paths: Vec<String> = server.received_requests().await.unwrap().iter().map(|request| request.url.path().to_string()).collect();
+    assert_eq!(paths[0], "/2/user");
+    assert_eq!(paths.iter().filter(|path| *path == "/4/find").count(), 3);
+}
+
+fn slot(id: &str, start: &str) -> ResySlot {
+    ResySlot {
+        id: id.to_string(),
+        token: format!("config-token-{}", id),
+        slot_type: "Dining Room".to_string(),
+        start: format!("2024-06-01 {}:00", start),
+        end: "2024-06-01 23:00:00".to_string(),
+        min_size: 2,
+        max_size: 4,
+        quantity: 1,
+        fees: Money::default(),
+        tokens: SlotTokens::default(),
+    }
+}
+
+fn current_booking() -> BookingResult {
+    BookingResult {
+        resy_token: "resy-token-current".to_string(),
+        venue_id: "1505".to_string(),
+        date: "2024-06-01".to_string(),
+        party_size: 2,
+        slot: slot("4349", "17:30"),
+        cancellation: None,
+    }
+}
+
+#[tokio::test]
+async fn upgrade_cancels_original_after_booking_better_slot() {
+    let server = MockServer::start().await;
+    mount(&server, "POST", "/3/details", json_response(200, DETAILS)).await;
+    mount(&server, "POST", "/3/book", json_response(201, BOOK)).await;
+    Mock::given(method("POST"))
+        .and(path("/3/cancel"))
+        .and(body_string_contains("resy_token=resy-token-current"))
+        .respond_with(json_response(200, "{}"))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let (mut store, store_path) = temp_store("upgrade");
+    let upgraded = client_for(&server).upgrade(&current_booking(), &slot("4350", "19:30"), &mut store).await.unwrap();
+    assert_eq!(upgraded.resy_token, "resy-token-abc");
+    assert_eq!(upgraded.slot.start, "2024-06-01 19:30:00");
+
+    let history: Vec<String> = store.history().unwrap().into_iter().map(|booking| booking.resy_token).collect();
+    let cancelled = store.cancelled().unwrap();
+    std::fs::remove_file(store_path).unwrap();
+    assert_eq!(history, vec!["resy-token-abc"]);
+    assert!(cancelled.contains("resy-token-current"));
+}
+
+#[tokio::test]
+async fn upgrade_keeps_original_when_booking_fails() {
+    let server = MockServer::start().await;
+    mount(&server, "POST", "/3/details", json_response(412, DETAILS_SLOT_TAKEN)).await;
+    Mock::given(method("POST"))
+        .and(path("/3/cancel"))
+        .respond_with(json_response(200, "{}"))
+        .expect(0)
+        .mount(&server)
+        .await;
+
+    let (mut store, store_path) = temp_store("upgrade-failed");
+    let result = client_for(&server).upgrade(&current_booking(), &slot("4350", "19:30"), &mut store).await;
+    assert!(matches!(result, Err(ResyClientError::BookingError(_))));
+    assert!(!store_path.exists());
+}
+
+fn temp_store(name: &str) -> (JsonFileStore, std::path::PathBuf) {
+    let path = std::env::temp_dir().join(format!("marksman-client-store-{}-{}.json", name, std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    (JsonFileStore::new(path.clone()), path)
+}
+
+#[tokio::test]
+async fn cancel_all_reports_each_token_in_order() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/3/cancel"))
+        .and(body_string_contains("resy_token=resy-token-gone"))
+        .respond_with(json_response(404, BOOK_TOKEN_EXPIRED))
+        .mount(&server)
+        .await;
+    mount(&server, "POST", "/3/cancel", json_response(200, "{}")).await;
+
+    let results = client_for(&server).cancel_all(&["resy-token-a", "resy-token-gone", "resy-token-b"]).await;
+
+    let tokens: Vec<&str> = results.iter().map(|(token, _)| token.as_str()).collect();
+    assert_eq!(tokens, vec!["resy-token-a", "resy-token-gone", "resy-token-b"]);
+    assert!(results[0].1.is_ok());
+    assert!(matches!(results[1].1, Err(ResyClientError::ApiError(_))));
+    assert!(results[2].1.is_ok());
+    assert_eq!(server.received_requests().await.unwrap().len(), 3);
+}
+
+#[tokio::test]
+async fn cancel_all_retries_rate_limited_cancels() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/3/cancel"))
+        .respond_with(json_response(429, RATE_LIMITED))
+        .up_to_n_times(1)
+        .with_priority(1)
+        .mount(&server)
+        .await;
+    mount(&server, "POST", "/3/cancel", json_response(200, "{}")).await;
+
+    let mut client = client_for(&server);
+    client.config.retry = RetryPolicy { base_ms: 10, ..RetryPolicy::default() };
+    let results = client.cancel_all(&["resy-token-a"]).await;
+
+    assert!(results[0].1.is_ok());
+    assert_eq!(server.received_requests().await.unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn change_reservation_returns_updated_booking() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/3/reservation/change"))
+        .and(body_string_contains("resy_token=resy-token-current"))
+        .and(body_string_contains("party_size=4"))
+        .and(body_string_contains("day=2024-06-02"))
+        .respond_with(json_response(200, r#"{"resy_token": "resy-token-changed"}"#))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let changed = client_for(&server)
+        .change_reservation(&current_booking(), &slot("4350", "19:30"), 4, day("2024-06-02"))
+        .await
+        .unwrap();
+
+    assert_eq!(changed.resy_token, "resy-token-changed");
+    assert_eq!(changed.party_size, 4);
+    assert_eq!(changed.date, "2024-06-02");
+    assert_eq!(changed.slot.id, "4350");
+}
+
+#[tokio::test]
+async fn change_reservation_refusal_is_a_booking_error() {
+    let server = MockServer::start().await;
+    mount(&server, "POST", "/3/reservation/change", json_response(412, DETAILS_SLOT_TAKEN)).await;
+
+    let result = client_for(&server).change_reservation(&current_booking(), &slot("4350", "19:30"), 4, day("2024-06-02")).await;
+
+    assert!(matches!(result, Err(ResyClientError::BookingError(_))));
+}
+
+#[tokio::test]
+async fn change_reservation_server_error_is_an_api_error() {
+    let server = MockServer::start().await;
+    mount(&server, "POST", "/3/reservation/change", json_response(500, "{}")).await;
+
+    let result = client_for(&server).change_reservation(&current_booking(), &slot("4350", "19:30"), 4, day("2024-06-02")).await;
+
+    assert!(matches!(result, Err(ResyClientError::ApiError(_))));
+}
+
+#[tokio::test]
+async fn device_id_is_sent_on_booking_endpoints_when_configured() {
+    let server = MockServer::start().await;
+    mount(&server, "GET", "/4/find", json_response(200, FIND)).await;
+    for endpoint in ["/3/details", "/3/book"] {
+        Mock::given(method("POST"))
+            .and(path(endpoint))
+            .and(header("x-resy-device-id", "device-123"))
+            .respond_with(json_response(200, if endpoint == "/3/book" { BOOK } else { DETAILS }))
+            .expect(1)
+            .mount(&server)
+            .await;
+    }
+
+    let mut config = client_for(&server).config;
+    config.device_id = Some("device-123".to_string());
+    ResyClient::from_config(config).book_best(false).await.unwrap();
+}
+
+#[tokio::test]
+async fn device_id_is_not_sent_by_default() {
+    let server = MockServer::start().await;
+    mount(&server, "GET", "/4/find", json_response(200, FIND)).await;
+    mount(&server, "POST", "/3/details", json_response(200, DETAILS)).await;
+    mount(&server, "POST", "/3/book", json_response(201, BOOK)).await;
+
+    client_for(&server).book_best(false).await.unwrap();
+
+    let requests = server.received_requests().await.unwrap();
+    assert!(requests.iter().all(|request| !request.headers.contains_key("x-resy-device-id")));
+}
+
+#[tokio::test]
+async fn correlation_id_is_sent_on_every_request_of_the_attempt_when_configured() {
+    let server = MockServer::start().await;
+    mount(&server, "GET", "/4/find", json_response(200, FIND)).await;
+    mount(&server, "POST", "/3/details", json_response(200, DETAILS)).await;
+    mount(&server, "POST", "/3/book", json_response(201, BOOK)).await;
+
+    let mut config = client_for(&server).config;
+    config.send_correlation_id = true;
+    let client = ResyClient::from_config(config);
+    correlation::scope("attempt-1".to_string(), client.book_best(false)).await.unwrap();
+    client.find(None, None).await.unwrap();
+
+    let requests = server.received_requests().await.unwrap();
+    let ids: Vec<Option<&str>> = requests.iter()
+        .map(|request| request.headers.get("x-correlation-id").map(|id| id.to_str().unwrap()))
+        .collect();
+    assert_eq!(ids, vec![Some("attempt-1"), Some("attempt-1"), Some("attempt-1"), None]);
+    assert!(correlation::current().is_none());
+}
+
+#[tokio::test]
+async fn correlation_id_is_not_sent_by_default() {
+    let server = MockServer::start().await;
+    mount(&server, "GET", "/4/find", json_response(200, FIND)).await;
+
+    correlation::scope(correlation::new_id(), client_for(&server).find(None, None)).await.unwrap();
+
+    let requests = server.received_requests().await.unwrap();
+    assert!(requests.iter().all(|request| !request.headers.contains_key("x-correlation-id")));
+}
+
+#[test]
+fn errors_serialize_with_their_variant() {
+    let error = ResyClientError::RateLimited("slow down".to_string());
+
+    assert_eq!(serde_json::to_value(&error).unwrap(), json!({ "kind": "RateLimited", "message": "slow down" }));
+}
@@ -0,0 +1,68 @@
+use std::time::{Duration, Instant};
+
+use marksman::retry::RetryPolicy;
+
+fn policy() -> RetryPolicy {
+    RetryPolicy {
+        max_attempts: 5,
+        base_ms: 100,
+        max_ms: 1_000,
+        jitter: 0.0,
+        deadline_secs: None,
+    }
+}
+
+#[test]
+fn backoff_doubles_up_to_max() {
+    let policy = policy();
+
+    let waits: Vec<u128> = (1..=6).map(|retry| policy.backoff(retry).as_millis()).collect();
+
+    assert_eq!(waits, vec![100, 200, 400, 800, 1_000, 1_000]);
+    assert_eq!(policy.backoff(u32::MAX), Duration::from_millis(1_000));
+}
+
+#[test]
+fn jitter_only_shortens_the_wait() {
+    let policy = RetryPolicy { jitter: 0.5, ..policy() };
+
+    for _ in 0..100 {
+        let wait = policy.delay(3);
+        assert!(wait >= Duration::from_millis(200) && wait <= Duration::from_millis(400), "{:?}", wait);
+    }
+}
+
+#[test]
+fn allows_stops_at_max_attempts() {
+    let policy = policy();
+    let started = Instant::now();
+
+    assert!(policy.allows(4, started, Duration::ZERO));
+    assert!(!policy.allows(5, started, Duration::ZERO));
+}
+
+#[test]
+fn allows_stops_at_deadline() {
+    let policy = RetryPolicy { deadline_secs: Some(1), ..policy() };
+    let started = Instant::now();
+
+    assert!(policy.allows(1, started, Duration::from_millis(500)));
+    assert!(!policy.allows(1, started, Duration::from_secs(2)));
+}
+
+#[test]
+fn deserializes_with_defaults() {
+    let policy: RetryPolicy = serde_json::from_str(r#"{ "max_attempts": 3 }"#).unwrap();
+
+    assert_eq!(policy, RetryPolicy { max_attempts: 3, ..RetryPolicy::default() });
+}
+
+#[test]
+fn tight_policy_gives_up_within_a_second() {
+    let policy = RetryPolicy::tight();
+    let started = Instant::now();
+
+    let waited: Duration = (1..policy.max_attempts).map(|retry| policy.delay(retry)).sum();
+    assert!(waited < Duration::from_secs(1), "{:?}", waited);
+    assert!(!policy.allows(1, started, Duration::from_secs(1)));
+}
@@ -0,0 +1,142 @@
+use chrono::{NaiveDateTime, NaiveTime, TimeZone, Utc, Weekday};
+use chrono_tz::America::New_York;
+use marksman::day::Day;
+use marksman::schedule::{Recurrence, SnipeSchedule};
+
+fn nine_am(days_out: u32) -> SnipeSchedule {
+    SnipeSchedule::relative(days_out, NaiveTime::from_hms_opt(9, 0, 0).unwrap(), New_York)
+}
+
+fn day(date: &str) -> Day {
+    date.parse().unwrap()
+}
+
+#[test]
+fn resolves_todays_release_when_still_ahead() {
+    // 08:00 EDT
+    let now = Utc.with_ymd_and_hms(2024, 6, 1, 12, 0, 0).unwrap();
+
+    let drop = nine_am(30).next_after(now);
+
+    assert_eq!(drop.release_at.with_timezone(&Utc), Utc.with_ymd_and_hms(2024, 6, 1, 13, 0, 0).unwrap());
+    assert_eq!(drop.day, day("2024-07-01"));
+}
+
+#[test]
+fn rolls_to_tomorrow_once_todays_release_passed() {
+    // 10:00 EDT
+    let now = Utc.with_ymd_and_hms(2024, 6, 1, 14, 0, 0).unwrap();
+
+    let drop = nine_am(14).next_after(now);
+
+    assert_eq!(drop.release_at.with_timezone(&Utc), Utc.with_ymd_and_hms(2024, 6, 2, 13, 0, 0).unwrap());
+    assert_eq!(drop.day, day("2024-06-16"));
+}
+
+#[test]
+fn uses_venue_local_offset_across_spring_forward() {
+    // 10:00 EST on the Saturday before clocks spring forward
+    let now = Utc.with_ymd_and_hms(2024, 3, 9, 15, 0, 0).unwrap();
+
+    let drop = nine_am(7).next_after(now);
+
+    // 09:00 EDT on Sunday is 13:00 UTC, an hour earlier than Saturday's 14:00 UTC
+    assert_eq!(drop.release_at.with_timezone(&Utc), Utc.with_ymd_and_hms(2024, 3, 10, 13, 0, 0).unwrap());
+    assert_eq!(drop.day, day("2024-03-17"));
+}
+
+#[test]
+fn uses_venue_local_offset_across_fall_back() {
+    // 10:00 EDT on the Saturday before clocks fall back
+    let now = Utc.with_ymd_and_hms(2024, 11, 2, 14, 0, 0).unwrap();
+
+    let drop = nine_am(7).next_after(now);
+
+    assert_eq!(drop.release_at.with_timezone(&Utc), Utc.with_ymd_and_hms(2024, 11, 3, 14, 0, 0).unwrap());
+}
+
+#[test]
+fn release_in_the_spring_gap_moves_an_hour_later() {
+    let schedule = SnipeSchedule::relative(7, NaiveTime::from_hms_opt(2, 30, 0).unwrap(), New_York);
+    let now = Utc.with_ymd_and_hms(2024, 3, 10, 5, 0, 0).unwrap();
+
+    let drop = schedule.next_after(now);
+
+    // 02:30 doesn't exist that night, 03:30 EDT does
+    assert_eq!(drop.release_at.with_timezone(&Utc), Utc.with_ymd_and_hms(2024, 3, 10, 7, 30, 0).unwrap());
+}
+
+#[test]
+fn release_in_a_two_hour_gap_moves_two_hours_later() {
+    // Troll jumps from UTC+0 to UTC+2 at 01:00 on the last Sunday of March
+    let schedule = SnipeSchedule::relative(7, NaiveTime::from_hms_opt(1, 30, 0).unwrap(), chrono_tz::Antarctica::Troll);
+    let now = Utc.with_ymd_and_hms(2024, 3, 31, 0, 0, 0).unwrap();
+
+    let drop = schedule.next_after(now);
+
+    // 03:30 +02
+    assert_eq!(drop.release_at.with_timezone(&Utc), Utc.with_ymd_and_hms(2024, 3, 31, 1, 30, 0).unwrap());
+}
+
+#[test]
+fn release_on_a_skipped_day_moves_a_day_later() {
+    // Samoa skipped 2011-12-30 when it moved across the date line
+    let schedule = SnipeSchedule::relative(7, NaiveTime::from_hms_opt(9, 0, 0).unwrap(), chrono_tz::Pacific::Apia);
+    // 23:00 on the 29th, so the next release falls on the skipped day
+    let now = Utc.with_ymd_and_hms(2011, 12, 30, 9, 0, 0).unwrap();
+
+    let drop = schedule.next_after(now);
+
+    // 09:00 at UTC-10 on the 30th, which is 09:00 on the 31st at UTC+14
+    assert_eq!(drop.release_at.with_timezone(&Utc), Utc.with_ymd_and_hms(2011, 12, 30, 19, 0, 0).unwrap());
+}
+
+#[test]
+fn repeated_release_time_uses_first_occurrence() {
+    let schedule = SnipeSchedule::relative(7, NaiveTime::from_hms_opt(1, 30, 0).unwrap(), New_York);
+    let now = Utc.with_ymd_and_hms(2024, 11, 3, 4, 0, 0).unwrap();
+
+    let drop = schedule.next_after(now);
+
+    // 01:30 EDT, before the clocks go back to 01:00 EST
+    assert_eq!(drop.release_at.with_timezone(&Utc), Utc.with_ymd_and_hms(2024, 11, 3, 5, 30, 0).unwrap());
+}
+
+fn at(datetime: &str) -> NaiveDateTime {
+    NaiveDateTime::parse_from_str(datetime, "%Y-%m-%d %H:%M").unwrap()
+}
+
+#[test]
+fn daily_recurrence_moves_to_the_next_day() {
+    let last = at("2024-05-18 09:00");
+
+    assert_eq!(Recurrence::Daily.next_after(last, at("2024-05-18 09:01")), at("2024-05-19 09:00"));
+    // a missed day is skipped
+    assert_eq!(Recurrence::Daily.next_after(last, at("2024-05-19 09:00")), at("2024-05-20 09:00"));
+}
+
+#[test]
+fn weekly_recurrence_on_the_same_weekday_skips_a_week() {
+    // Saturday
+    let last = at("2024-05-18 09:00");
+
+    assert_eq!(Recurrence::Weekly(Weekday::Sat).next_after(last, at("2024-05-18 09:01")), at("2024-05-25 09:00"));
+}
+
+#[test]
+fn weekly_recurrence_crosses_into_the_next_week() {
+    // Friday to the following Monday, across the weekend
+    let last = at("2024-05-31 09:00");
+
+    assert_eq!(Recurrence::Weekly(Weekday::Mon).next_after(last, at("2024-05-31 09:01")), at("2024-06-03 09:00"));
+    // Tuesday to the next Sunday, still the same week
+    assert_eq!(Recurrence::Weekly(Weekday::Sun).next_after(at("2024-05-28 09:00"), at("2024-05-28 09:01")), at("2024-06-02 09:00"));
+}
+
+#[test]
+fn weekly_recurrence_skips_weeks_that_passed() {
+    let last = at("2024-05-18 09:00");
+
+    assert_eq!(Recurrence::Weekly(Weekday::Sat).next_after(last, at("2024-06-08 09:00")), at("2024-06-15 09:00"));
+    assert_eq!(Recurrence::Weekly(Weekday::Sat).next_after(last, at("2024-06-10 12:00")), at("2024-06-15 09:00"));
+}
@@ -0,0 +1,44 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use marksman::single_flight::SingleFlight;
+use tokio::time::{sleep, timeout};
+
+#[tokio::test]
+async fn concurrent_calls_share_one_result() {
+    let flights: SingleFlight<usize> = SingleFlight::new();
+    let calls = Arc::new(AtomicUsize::new(0));
+    let call = |calls: Arc<AtomicUsize>| async move {
+        sleep(Duration::from_millis(20)).await;
+        calls.fetch_add(1, Ordering::SeqCst) + 1
+    };
+
+    let (first, second) = tokio::join!(
+        flights.run("key".to_string(), call(calls.clone())),
+        flights.run("key".to_string(), call(calls.clone())),
+    );
+
+    assert_eq!((first, second), (1, 1));
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn abandoned_call_finishes_and_is_forgotten() {
+    let flights: SingleFlight<&'static str> = SingleFlight::new();
+    let finished = Arc::new(AtomicUsize::new(0));
+    let slow = {
+        let finished = finished.clone();
+        async move {
+            sleep(Duration::from_millis(50)).await;
+            finished.fetch_add(1, Ordering::SeqCst);
+            "stale"
+        }
+    };
+
+    assert!(timeout(Duration::from_millis(10), flights.run("key".to_string(), slow)).await.is_err());
+    sleep(Duration::from_millis(100)).await;
+
+    assert_eq!(finished.load(Ordering::SeqCst), 1);
+    assert_eq!(flights.run("key".to_string(), async { "fresh" }).await, "fresh");
+}
@@ -0,0 +1,77 @@
+use marksman::money::Money;
+use marksman::resy_api_gateway::SlotTokens;
+use marksman::resy_client::{BookingResult, ResySlot};
+
+fn slot(start: &str, slot_type: &str, min_size: u64, max_size: u64, fees: f64) -> ResySlot {
+    ResySlot {
+        id: "4350".to_string(),
+        token: "token-4350".to_string(),
+        slot_type: slot_type.to_string(),
+        start: format!("2024-06-01 {}:00", start),
+        end: "2024-06-01 23:00:00".to_string(),
+        min_size,
+        max_size,
+        quantity: 1,
+        fees: Money::usd(fees),
+        tokens: SlotTokens::default(),
+    }
+}
+
+#[test]
+fn slot_renders_twelve_hour_time_seating_and_party() {
+    assert_eq!(slot("19:30", "Dining Room", 2, 2, 0.0).to_string(), "7:30 PM · Dining Room · party of 2");
+}
+
+#[test]
+fn slot_renders_party_range_and_price() {
+    assert_eq!(slot("12:05", "Patio", 2, 4, 25.0).to_string(), "12:05 PM · Patio · party of 2-4 · $25.00");
+}
+
+#[test]
+fn slot_renders_price_in_venue_currency() {
+    let mut slot = slot("19:30", "Dining Room", 2, 2, 0.0);
+    slot.fees = Money::new(25.0, "EUR");
+    assert_eq!(slot.to_string(), "7:30 PM · Dining Room · party of 2 · €25.00");
+
+    slot.fees = Money::new(40.0, "chf");
+    assert_eq!(slot.to_string(), "7:30 PM · Dining Room · party of 2 · CHF 40.00");
+}
+
+#[test]
+fn slot_reads_legacy_fees_as_usd() {
+    let slot: ResySlot = serde_json::from_value(serde_json::json!({
+        "id": "4350",
+        "token": "token-4350",
+        "slot_type": "Dining Room",
+        "start": "2024-06-01 19:30:00",
+        "end": "2024-06-01 21:00:00",
+        "min_size": 2,
+        "max_size": 2,
+        "quantity": 1,
+        "fees": 25.0,
+    })).unwrap();
+
+    assert_eq!(slot.fees, Money::usd(25.0));
+}
+
+#[test]
+fn slot_falls_back_to_raw_start_when_unparseable() {
+    let mut slot = slot("17:30", "Bar", 1, 1, 0.0);
+    slot.start = "soon".to_string();
+
+    assert_eq!(slot.to_string(), "soon · Bar · party of 1");
+}
+
+#[test]
+fn booking_result_renders_date_and_booked_party() {
+    let booking = BookingResult {
+        resy_token: "resy-token-abc".to_string(),
+        venue_id: "1505".to_string(),
+        date: "2024-06-01".to_string(),
+        party_size: 3,
+        slot: slot("09:00", "Dining Room", 2, 4, 0.0),
+        cancellation: None,
+    };
+
+    assert_eq!(booking.to_string(), "2024-06-01 9:00 AM · Dining Room · party of 3");
+}
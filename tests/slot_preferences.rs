@@ -0,0 +1,341 @@
+use marksman::config::SlotCriterion;
+use marksman::money::Money;
+use marksman::resy_api_gateway::SlotTokens;
+use marksman::resy_client::{dedup_slots, explain_selection, rank_slots, select_slot, Rejection, ResySlot, SelectionReason, SlotPreferences, SlotSelection, SlotSelector};
+
+fn slot(id: &str, start: &str, fees: f64) -> ResySlot {
+    ResySlot {
+        id: id.to_string(),
+        token: format!("token-{}", id),
+        slot_type: "Dining Room".to_string(),
+        start: format!("2024-06-01 {}:00", start),
+        end: "2024-06-01 23:00:00".to_string(),
+        min_size: 2,
+        max_size: 4,
+        quantity: 1,
+        fees: Money::usd(fees),
+        tokens: SlotTokens::default(),
+    }
+}
+
+fn ids(slots: &[ResySlot]) -> Vec<&str> {
+    slots.iter().map(|slot| slot.id.as_str()).collect()
+}
+
+#[test]
+fn fewer_fees_breaks_ties_between_equal_slots() {
+    let slots = vec![slot("deposit", "18:30", 50.0), slot("free", "19:30", 0.0)];
+    let preferences = SlotPreferences {
+        target_time: Some("1900".to_string()),
+        prefer_fewer_fees: true,
+        ..SlotPreferences::default()
+    };
+
+    assert_eq!(ids(&rank_slots(slots, &preferences)), vec!["free", "deposit"]);
+}
+
+#[test]
+fn fees_are_ignored_unless_preferred() {
+    let slots = vec![slot("deposit", "18:30", 50.0), slot("free", "19:30", 0.0)];
+    let preferences = SlotPreferences {
+        target_time: Some("1900".to_string()),
+        prefer_fewer_fees: false,
+        ..SlotPreferences::default()
+    };
+
+    assert_eq!(ids(&rank_slots(slots, &preferences)), vec!["deposit", "free"]);
+}
+
+#[test]
+fn fewer_fees_does_not_override_target_time() {
+    let slots = vec![slot("late", "21:00", 0.0), slot("target", "19:00", 50.0)];
+    let preferences = SlotPreferences {
+        target_time: Some("1900".to_string()),
+        prefer_fewer_fees: true,
+        ..SlotPreferences::default()
+    };
+
+    assert_eq!(ids(&rank_slots(slots, &preferences)), vec!["target", "late"]);
+}
+
+#[test]
+fn dedup_keeps_best_variant_of_overlapping_results() {
+    let small_party = vec![slot("a-1900", "19:00", 0.0), slot("a-2000", "20:00", 0.0)];
+    let mut large_party = vec![slot("b-1900", "19:00", 0.0), slot("b-2100", "21:00", 0.0)];
+    large_party[0].quantity = 3;
+
+    let merged: Vec<ResySlot> = small_party.into_iter().chain(large_party).collect();
+    assert_eq!(ids(&dedup_slots(merged)), vec!["b-1900", "a-2000", "b-2100"]);
+}
+
+#[test]
+fn dedup_keeps_distinct_seating_types() {
+    let mut bar = slot("bar", "19:00", 0.0);
+    bar.slot_type = "Bar".to_string();
+    let slots = vec![slot("dining", "19:00", 0.0), bar, slot("dining-fee", "19:00", 25.0)];
+
+    assert_eq!(ids(&dedup_slots(slots)), vec!["dining", "bar"]);
+}
+
+fn seated(id: &str, slot_type: &str) -> ResySlot {
+    let mut slot = slot(id, "19:00", 0.0);
+    slot.slot_type = slot_type.to_string();
+    slot
+}
+
+fn seating_options() -> Vec<ResySlot> {
+    vec![seated("dining", "Dining Room"), seated("bar", "Bar"), seated("patio", "Patio"), seated("counter", "Counter")]
+}
+
+fn types(types: &[&str]) -> Vec<String> {
+    types.iter().map(|t| t.to_string()).collect()
+}
+
+#[test]
+fn allowed_types_keep_only_listed_seating() {
+    let preferences = SlotPreferences {
+        allowed_types: types(&["dining room", "Patio"]),
+        ..SlotPreferences::default()
+    };
+
+    assert_eq!(ids(&rank_slots(seating_options(), &preferences)), vec!["dining", "patio"]);
+}
+
+#[test]
+fn blocked_types_drop_listed_seating() {
+    let preferences = SlotPreferences {
+        blocked_types: types(&["Bar", "Counter"]),
+        ..SlotPreferences::default()
+    };
+
+    assert_eq!(ids(&rank_slots(seating_options(), &preferences)), vec!["dining", "patio"]);
+}
+
+#[test]
+fn blocked_types_apply_within_allowed_types() {
+    let preferences = SlotPreferences {
+        allowed_types: types(&["Dining Room", "Bar"]),
+        blocked_types: types(&["Bar"]),
+        ..SlotPreferences::default()
+    };
+
+    assert_eq!(ids(&rank_slots(seating_options(), &preferences)), vec!["dining"]);
+}
+
+fn seated_at(id: &str, slot_type: &str, start: &str) -> ResySlot {
+    let mut slot = slot(id, start, 0.0);
+    slot.slot_type = slot_type.to_string();
+    slot
+}
+
+fn criterion(time: Option<&str>, seating: Option<&str>) -> SlotCriterion {
+    SlotCriterion {
+        time: time.map(str::to_string),
+        seating: seating.map(str::to_string),
+        ..SlotCriterion::default()
+    }
+}
+
+fn compound_preferences() -> SlotPreferences {
+    SlotPreferences {
+        criteria: vec![
+            criterion(Some("1900"), Some("Patio")),
+            criterion(Some("2000"), Some("Dining Room")),
+            criterion(None, Some("bar")),
+        ],
+        ..SlotPreferences::default()
+    }
+}
+
+#[test]
+fn criteria_rank_slots_by_first_criterion_they_satisfy() {
+    let slots = vec![
+        seated_at("bar", "Bar", "18:00"),
+        seated_at("dining-2000", "Dining Room", "20:00"),
+        seated_at("dining-1900", "Dining Room", "19:00"),
+        seated_at("patio-1900", "Patio", "19:00"),
+    ];
+
+    assert_eq!(ids(&rank_slots(slots, &compound_preferences())), vec!["patio-1900", "dining-2000", "bar"]);
+}
+
+#[test]
+fn select_slot_falls_back_to_later_criteria() {
+    let slots = vec![seated_at("patio-2000", "Patio", "20:00"), seated_at("bar", "Bar", "22:30")];
+    assert_eq!(select_slot(slots, &compound_preferences()).unwrap().id, "bar");
+
+    let slots = vec![seated_at("patio-2000", "Patio", "20:00"), seated_at("dining-1900", "Dining Room", "19:00")];
+    assert!(select_slot(slots, &compound_preferences()).is_none());
+}
+
+#[test]
+fn criteria_within_mins_prefers_closest_slot() {
+    let preferences = SlotPreferences {
+        criteria: vec![SlotCriterion { within_mins: 30, ..SlotCriterion::at("1900") }],
+        ..SlotPreferences::default()
+    };
+    let slots = vec![slot("early", "18:30", 0.0), slot("late", "19:15", 0.0), slot("too-late", "19:45", 0.0)];
+
+    assert_eq!(ids(&rank_slots(slots, &preferences)), vec!["late", "early"]);
+}
+
+#[test]
+fn time_only_criteria_keep_listed_order() {
+    let preferences = SlotPreferences {
+        criteria: vec![SlotCriterion::at("2000"), SlotCriterion::at("1900")],
+        ..SlotPreferences::default()
+    };
+    let slots = vec![slot("1900", "19:00", 0.0), slot("2000", "20:00", 0.0), slot("2100", "21:00", 0.0)];
+
+    assert_eq!(ids(&rank_slots(slots, &preferences)), vec!["2000", "1900"]);
+}
+
+#[test]
+fn select_fn_takes_precedence_over_preferences() {
+    let preferences = SlotPreferences {
+        select_fn: Some(SlotSelector::new(|slots| slots.iter().find(|slot| slot.id == "bar"))),
+        ..compound_preferences()
+    };
+    let slots = vec![seated_at("patio-1900", "Patio", "19:00"), seated_at("bar", "Bar", "18:00")];
+
+    assert_eq!(select_slot(slots.clone(), &preferences).unwrap().id, "bar");
+    assert_eq!(ids(&rank_slots(slots, &preferences)), vec!["bar"]);
+}
+
+#[test]
+fn select_fn_can_pick_nothing() {
+    let preferences = SlotPreferences {
+        select_fn: Some(SlotSelector::new(|_| None)),
+        ..SlotPreferences::default()
+    };
+
+    assert!(select_slot(vec![slot("1900", "19:00", 0.0)], &preferences).is_none());
+}
+
+fn chosen(selection: &SlotSelection) -> Option<&str> {
+    selection.slot.as_ref().map(|slot| slot.id.as_str())
+}
+
+fn rejections(selection: &SlotSelection) -> Vec<(&str, Rejection)> {
+    selection.rejected.iter().map(|(slot, rejection)| (slot.id.as_str(), rejection.clone())).collect()
+}
+
+#[test]
+fn explains_seating_rejections() {
+    let preferences = SlotPreferences {
+        allowed_types: vec!["dining room".to_string(), "bar".to_string(), "patio".to_string()],
+        blocked_types: vec!["Patio".to_string()],
+        ..SlotPreferences::default()
+    };
+
+    let selection = explain_selection(seating_options(), &preferences);
+
+    assert_eq!(chosen(&selection), Some("dining"));
+    assert_eq!(selection.reason, SelectionReason::FirstListed);
+    assert_eq!(rejections(&selection), vec![
+        ("bar", Rejection::Outranked),
+        ("patio", Rejection::SeatingBlocked("Patio".to_string())),
+        ("counter", Rejection::SeatingNotAllowed("Counter".to_string())),
+    ]);
+}
+
+#[test]
+fn explains_which_criterion_matched() {
+    let preferences = SlotPreferences {
+        criteria: vec![criterion(Some("1900"), Some("Patio")), SlotCriterion { within_mins: 30, ..SlotCriterion::at("1900") }],
+        ..SlotPreferences::default()
+    };
+    let slots = vec![slot("early", "17:00", 0.0), slot("late", "19:15", 0.0), slot("later", "19:30", 0.0)];
+
+    let selection = explain_selection(slots, &preferences);
+
+    assert_eq!(chosen(&selection), Some("late"));
+    assert_eq!(selection.reason, SelectionReason::Criterion {
+        priority: 1,
+        criterion: SlotCriterion { within_mins: 30, ..SlotCriterion::at("1900") },
+        minutes_off: 15,
+    });
+    assert_eq!(rejections(&selection), vec![("early", Rejection::OutsideCriteria), ("later", Rejection::Outranked)]);
+}
+
+#[test]
+fn explains_target_time_and_duplicates() {
+    let preferences = SlotPreferences { target_time: Some("1900".to_string()), ..SlotPreferences::default() };
+    let slots = vec![slot("deposit", "19:30", 50.0), slot("early", "18:00", 0.0), slot("free", "19:30", 0.0)];
+
+    let selection = explain_selection(slots.clone(), &preferences);
+
+    assert_eq!(chosen(&selection), select_slot(slots, &preferences).as_ref().map(|slot| slot.id.as_str()));
+    assert_eq!(selection.reason, SelectionReason::ClosestToTarget { target_time: "1900".to_string(), minutes_off: 30 });
+    assert_eq!(rejections(&selection), vec![("deposit", Rejection::Duplicate), ("early", Rejection::Outranked)]);
+}
+
+#[test]
+fn explains_when_nothing_is_acceptable() {
+    let preferences = SlotPreferences { blocked_types: vec!["Dining Room".to_string()], ..SlotPreferences::default() };
+
+    let selection = explain_selection(vec![slot("only", "19:00", 0.0)], &preferences);
+
+    assert!(selection.slot.is_none());
+    assert_eq!(selection.reason, SelectionReason::NoneAcceptable);
+    assert_eq!(rejections(&selection), vec![("only", Rejection::SeatingBlocked("Dining Room".to_string()))]);
+}
+
+#[test]
+fn explains_custom_selection() {
+    let preferences = SlotPreferences {
+        select_fn: Some(SlotSelector::new(|slots: &[ResySlot]| slots.last())),
+        ..SlotPreferences::default()
+    };
+
+    let selection = explain_selection(vec![slot("first", "18:00", 0.0), slot("last", "21:00", 0.0)], &preferences);
+
+    assert_eq!(chosen(&selection), Some("last"));
+    assert_eq!(selection.reason, SelectionReason::Custom);
+    assert_eq!(rejections(&selection), vec![("first", Rejection::NotPicked)]);
+}
+
+/// Slot starting `mins` from now on the wall clock of `zone`.
+fn slot_in(id: &str, zone: chrono_tz::Tz, mins: i64) -> ResySlot {
+    let start = chrono::Utc::now().with_timezone(&zone) + chrono::Duration::minutes(mins);
+    let mut slot = slot(id, "00:00", 0.0);
+    slot.start = start.format("%Y-%m-%d %H:%M:%S").to_string();
+    slot
+}
+
+#[test]
+fn slots_inside_the_lead_time_are_dropped() {
+    // far from any machine's zone, so local time would get the cutoff wrong
+    let zone = chrono_tz::Pacific::Kiritimati;
+    let preferences = SlotPreferences {
+        min_lead_mins: 60,
+        time_zone: Some(zone.name().to_string()),
+        ..SlotPreferences::default()
+    };
+    let slots = vec![slot_in("soon", zone, 20), slot_in("later", zone, 120)];
+
+    assert_eq!(ids(&rank_slots(slots.clone(), &preferences)), vec!["later"]);
+    assert_eq!(rejections(&explain_selection(slots, &preferences)), vec![("soon", Rejection::TooSoon)]);
+}
+
+#[test]
+fn lead_time_counts_in_the_venue_time_zone() {
+    let zone = chrono_tz::Pacific::Kiritimati;
+    let preferences = SlotPreferences {
+        min_lead_mins: 60,
+        time_zone: Some(chrono_tz::Pacific::Pago_Pago.name().to_string()),
+        ..SlotPreferences::default()
+    };
+
+    // a day ahead of Pago Pago's clock, not too soon there
+    assert!(!preferences.too_soon(&slot_in("ahead", zone, 20)));
+}
+
+#[test]
+fn lead_time_is_off_by_default() {
+    let preferences = SlotPreferences::from_config(&marksman::config::Config::default());
+    let slots = vec![slot("past", "17:20", 0.0), slot("later", "19:00", 0.0)];
+
+    assert_eq!(preferences.min_lead_mins, 0);
+    assert_eq!(ids(&rank_slots(slots, &preferences)), vec!["past", "later"]);
+}
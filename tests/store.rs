@@ -0,0 +1,129 @@
+use std::env;
+use std::fs;
+use marksman::money::Money;
+use marksman::resy_api_gateway::SlotTokens;
+use marksman::config::SnipeTarget;
+use marksman::resy_client::{BookingResult, ResySlot};
+use marksman::store::{JsonFileStore, SnipeAttempt, Store, VenueStats};
+
+fn booking(venue_id: &str, date: &str) -> BookingResult {
+    BookingResult {
+        resy_token: "resy-token-abc".to_string(),
+        venue_id: venue_id.to_string(),
+        date: date.to_string(),
+        party_size: 2,
+        slot: ResySlot {
+            id: "4350".to_string(),
+            token: "config-token".to_string(),
+            slot_type: "Dining Room".to_string(),
+            start: format!("{} 19:30:00", date),
+            end: format!("{} 21:00:00", date),
+            min_size: 2,
+            max_size: 4,
+            quantity: 1,
+            fees: Money::default(),
+            tokens: SlotTokens::default(),
+        },
+        cancellation: None,
+    }
+}
+
+#[test]
+fn json_store_round_trips_history() {
+    let path = env::temp_dir().join(format!("marksman-store-{}.json", std::process::id()));
+    let _ = fs::remove_file(&path);
+    let mut store = JsonFileStore::new(path.clone());
+
+    store.record(&booking("1505", "2024-06-01")).unwrap();
+    store.record_attempt(SnipeAttempt::new("1505", "2024-06-02", 2, Some("sold out".to_string()))).unwrap();
+
+    let reopened = JsonFileStore::new(path.clone());
+    assert_eq!(reopened.history().unwrap().len(), 1);
+    assert_eq!(reopened.attempts().unwrap()[0].error.as_deref(), Some("sold out"));
+    assert!(reopened.is_booked("1505", "2024-06-01").unwrap());
+    assert!(!reopened.is_booked("1505", "2024-06-02").unwrap());
+
+    fs::remove_file(path).unwrap();
+}
+
+fn temp_store(name: &str) -> (JsonFileStore, std::path::PathBuf) {
+    let path = env::temp_dir().join(format!("marksman-store-{}-{}.json", name, std::process::id()));
+    let _ = fs::remove_file(&path);
+    (JsonFileStore::new(path.clone()), path)
+}
+
+#[test]
+fn success_rate_counts_attempts_that_booked() {
+    let (mut store, path) = temp_store("rates");
+
+    assert_eq!(store.success_rate("1505").unwrap(), None);
+    for error in [None, Some("sold out"), Some("sold out"), None] {
+        store.record_attempt(SnipeAttempt::new("1505", "2024-06-01", 2, error.map(str::to_string))).unwrap();
+    }
+    store.record_attempt(SnipeAttempt::new("834", "2024-06-01", 2, Some("sold out".to_string()))).unwrap();
+
+    assert_eq!(store.success_rate("1505").unwrap(), Some(0.5));
+    assert_eq!(store.success_rate("834").unwrap(), Some(0.0));
+    assert_eq!(store.venue_stats().unwrap()["1505"], VenueStats { attempts: 4, successes: 2 });
+
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn venue_stats_are_rebuilt_for_stores_without_them() {
+    let (mut store, path) = temp_store("legacy");
+    fs::write(&path, r#"{
+        "bookings": [],
+        "attempts": [
+            { "venue_id": "1505", "date": "2024-06-01", "party_size": 2, "attempted_at": "2024-05-25 09:00:00", "error": null },
+            { "venue_id": "1505", "date": "2024-06-02", "party_size": 2, "attempted_at": "2024-05-26 09:00:00", "error": "sold out" }
+        ]
+    }"#).unwrap();
+
+    assert_eq!(store.success_rate("1505").unwrap(), Some(0.5));
+    store.record_attempt(SnipeAttempt::new("1505", "2024-06-03", 2, Some("sold out".to_string()))).unwrap();
+    assert_eq!(store.venue_stats().unwrap()["1505"], VenueStats { attempts: 3, successes: 1 });
+
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn rescheduled_targets_survive_reopening() {
+    let (mut store, path) = temp_store("rescheduled");
+    let next: SnipeTarget = toml::from_str(r#"
+        venue_id = "1505"
+        date = "2024-06-08"
+        snipe_date = "2024-05-25"
+        snipe_time = "0900"
+        recurrence = "daily"
+    "#).unwrap();
+
+    store.reschedule("series", Some(next)).unwrap();
+    let reopened = JsonFileStore::new(path.clone());
+    assert_eq!(reopened.rescheduled().unwrap()["series"].date, "2024-06-08");
+
+    store.reschedule("series", None).unwrap();
+    assert!(store.rescheduled().unwrap().is_empty());
+
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn cancelled_bookings_stay_in_history_but_not_booked() {
+    let (mut store, path) = temp_store("cancelled");
+    let mut kept = booking("1505", "2024-06-02");
+    kept.resy_token = "resy-token-kept".to_string();
+    store.record(&booking("1505", "2024-06-01")).unwrap();
+    store.record(&kept).unwrap();
+
+    store.record_cancellation("resy-token-abc").unwrap();
+
+    let reopened = JsonFileStore::new(path.clone());
+    assert_eq!(reopened.history().unwrap().len(), 2);
+    let active: Vec<String> = reopened.active_bookings().unwrap().into_iter().map(|booking| booking.resy_token).collect();
+    assert_eq!(active, vec!["resy-token-kept"]);
+    assert!(!reopened.is_booked("1505", "2024-06-01").unwrap());
+    assert!(reopened.is_booked("1505", "2024-06-02").unwrap());
+
+    fs::remove_file(path).unwrap();
+}
@@ -0,0 +1,65 @@
+//! Property tests for `extract_venue_slug`, the parser behind `marksman venue --url`.
+//!
+//! Run with `cargo test --test venue_url`. Set `PROPTEST_CASES` to throw more
+//! random input at it, e.g. `PROPTEST_CASES=100000 cargo test --test venue_url`.
+
+use marksman::resy_client::extract_venue_slug;
+use proptest::prelude::*;
+
+/// Real Resy url shapes, one per line.
+const SEED_URLS: &str = include_str!("fixtures/venue_urls.txt");
+
+fn is_well_formed(slug: &str) -> bool {
+    !slug.is_empty()
+        && slug.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_')
+}
+
+#[test]
+fn seed_urls_yield_their_slug() {
+    let expected = [
+        "carbone",
+        "carbone",
+        "don-angie",
+        "lilia",
+        "4-charles-prime-rib",
+        "bestia",
+        "state-bird-provisions",
+        "i_sodi",
+        "via-carota",
+    ];
+
+    let slugs: Vec<_> = SEED_URLS.lines().map(extract_venue_slug).collect();
+
+    assert_eq!(slugs, expected.map(|slug| Some(slug.to_string())));
+}
+
+#[test]
+fn rejects_urls_without_a_slug() {
+    for url in ["", "https://resy.com", "https://resy.com/cities/ny/venues/", "https://resy.com/cities/ny/venues/?date=2024-06-01", "https://resy.com/cities/ny/venues/caf%C3%A9"] {
+        assert_eq!(extract_venue_slug(url), None, "{url}");
+    }
+}
+
+proptest! {
+    #[test]
+    fn never_panics_and_returns_well_formed_slug(url in any::<String>()) {
+        if let Some(slug) = extract_venue_slug(&url) {
+            prop_assert!(is_well_formed(&slug), "{:?} -> {:?}", url, slug);
+        }
+    }
+
+    #[test]
+    fn never_panics_on_mangled_seed_urls(seed in prop::sample::select(SEED_URLS.lines().collect::<Vec<_>>()), cut in any::<prop::sample::Index>(), noise in ".{0,16}") {
+        let cut = cut.index(seed.len() + 1);
+        let url = format!("{}{}{}", &seed[..cut], noise, &seed[cut..]);
+        if let Some(slug) = extract_venue_slug(&url) {
+            prop_assert!(is_well_formed(&slug), "{:?} -> {:?}", url, slug);
+        }
+    }
+
+    #[test]
+    fn recovers_generated_slugs(slug in "[a-z0-9][a-z0-9_-]{0,30}", query in "(\\?[a-z=&0-9-]{0,20})?") {
+        let url = format!("https://resy.com/cities/ny/venues/{}{}", slug, query);
+        prop_assert_eq!(extract_venue_slug(&url), Some(slug));
+    }
+}
@@ -0,0 +1,33 @@
+use marksman::money::Money;
+use marksman::resy_api_gateway::SlotTokens;
+use marksman::resy_client::ResySlot;
+use marksman::view_utils::slots_table;
+
+fn slot(start: &str, slot_type: &str, max_size: u64, fees: f64) -> ResySlot {
+    ResySlot {
+        id: start.to_string(),
+        token: format!("token-{}", start),
+        slot_type: slot_type.to_string(),
+        start: format!("2024-06-01 {}:00", start),
+        end: "2024-06-01 23:00:00".to_string(),
+        min_size: 2,
+        max_size,
+        quantity: 1,
+        fees: Money::usd(fees),
+        tokens: SlotTokens::default(),
+    }
+}
+
+#[test]
+fn slots_table_rows_are_sorted_by_time() {
+    let slots = vec![slot("21:00", "Bar", 2, 0.0), slot("17:30", "Patio", 4, 25.0), slot("19:15", "Dining Room", 2, 0.0)];
+
+    let rendered = slots_table(&slots).to_string();
+    let rows: Vec<&str> = rendered.lines().filter(|line| line.starts_with('|')).collect();
+
+    assert_eq!(rows.len(), 4);
+    assert!(rows[0].contains("Time") && rows[0].contains("Party Size Range") && rows[0].contains("Price"));
+    assert!(rows[1].contains("17:30") && rows[1].contains("Patio") && rows[1].contains("2-4") && rows[1].contains("$25.00"));
+    assert!(rows[2].contains("19:15") && rows[2].contains("Dining Room"));
+    assert!(rows[3].contains("21:00") && rows[3].contains("Bar"));
+}